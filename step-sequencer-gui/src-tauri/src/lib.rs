@@ -9,6 +9,8 @@ use step_sequencer::{
     drum_track::DrumTrack,
     id::SSId,
     launcher::{Command, SSLauncher},
+    project::F,
+    tempo::{tempo_from_f64, tempo_to_f64},
 };
 use tauri::{AppHandle, Emitter, Manager, State};
 
@@ -55,20 +57,23 @@ fn stop(state: State<Mutex<AppState>>) -> String {
 }
 
 #[tauri::command]
-fn get_tempo(state: State<Mutex<AppState>>) -> u16 {
-    state
-        .lock()
-        .unwrap()
-        .ss_launcher
-        .project()
-        .project_settings()
-        .read()
-        .unwrap()
-        .tempo
+fn get_tempo(state: State<Mutex<AppState>>) -> f64 {
+    tempo_to_f64(
+        state
+            .lock()
+            .unwrap()
+            .ss_launcher
+            .project()
+            .project_settings()
+            .read()
+            .unwrap()
+            .tempo_map
+            .initial_tempo(),
+    )
 }
 
 #[tauri::command]
-fn set_tempo(state: State<Mutex<AppState>>, tempo: u16) -> String {
+fn set_tempo(state: State<Mutex<AppState>>, tempo: f64) -> String {
     print!("{}", tempo);
     state
         .lock()
@@ -78,7 +83,8 @@ fn set_tempo(state: State<Mutex<AppState>>, tempo: u16) -> String {
         .project_settings()
         .write()
         .unwrap()
-        .tempo = tempo;
+        .tempo_map
+        .set_initial_tempo(tempo_from_f64(tempo));
     format!("set tempo to {}", tempo)
 }
 
@@ -119,6 +125,50 @@ fn remove_track(state: State<Mutex<AppState>>, track_idx: usize) -> Result<Strin
     Ok(format!("remove track {track_idx}"))
 }
 
+#[tauri::command]
+fn save_project(state: State<Mutex<AppState>>, path: String) -> Result<String, String> {
+    state
+        .lock()
+        .unwrap()
+        .ss_launcher
+        .send_command(Command::SaveProject(path.clone().into()))
+        .map_err(|e| e.to_string())?;
+    Ok(format!("save project to {path}"))
+}
+
+#[tauri::command]
+fn load_project(state: State<Mutex<AppState>>, path: String) -> Result<String, String> {
+    state
+        .lock()
+        .unwrap()
+        .ss_launcher
+        .send_command(Command::LoadProject(path.clone().into()))
+        .map_err(|e| e.to_string())?;
+    Ok(format!("load project from {path}"))
+}
+
+#[tauri::command]
+fn export_midi(state: State<Mutex<AppState>>, path: String) -> Result<String, String> {
+    state
+        .lock()
+        .unwrap()
+        .ss_launcher
+        .send_command(Command::ExportMIDI(path.clone().into()))
+        .map_err(|e| e.to_string())?;
+    Ok(format!("export MIDI to {path}"))
+}
+
+#[tauri::command]
+fn import_midi(state: State<Mutex<AppState>>, path: String) -> Result<String, String> {
+    state
+        .lock()
+        .unwrap()
+        .ss_launcher
+        .send_command(Command::ImportMIDI(path.clone().into(), F::new(1u64, 4u64)))
+        .map_err(|e| e.to_string())?;
+    Ok(format!("import MIDI from {path}"))
+}
+
 fn run_beatmaker_event_handler(
     app_handle: AppHandle,
     beatmaker_subscription: BeatMakerSubscription,
@@ -129,6 +179,12 @@ fn run_beatmaker_event_handler(
                 BeatMakerEvent::Beat(_) | BeatMakerEvent::Pause | BeatMakerEvent::Stop => {
                     app_handle.emit("beat-signal", event).unwrap();
                 }
+                BeatMakerEvent::Position(_) => {
+                    app_handle.emit("position-signal", event).unwrap();
+                }
+                BeatMakerEvent::SongPosition(_) | BeatMakerEvent::SongEnd => {
+                    app_handle.emit("song-signal", event).unwrap();
+                }
                 _ => {}
             }
         }
@@ -152,7 +208,11 @@ pub fn run() {
             set_tempo,
             get_track_list,
             add_empty_track,
-            remove_track
+            remove_track,
+            save_project,
+            load_project,
+            export_midi,
+            import_midi
         ])
         .setup(|app| {
             let beatmaker_event_subscription = ss_launcher.subscribe_to_beatmaker();
@@ -5,13 +5,22 @@ use std::sync::OnceLock;
 use crossbeam::channel::{unbounded, Sender};
 use log::info;
 use step_sequencer::{
+    arpeggiator::{ArpOrder, Arpeggiator},
+    arrangement::SectionLength,
     beatmaker::pattern::{
         ExampleDrumTracks, EXAMPLE_DRUMTRACKS_BITWIG, EXAMPLE_DRUMTRACKS_GARAGEBAND,
     },
+    drum_track::{ChordBeat, Condition, Gate, PatternSlot, Ratchet},
     error::{CommandError, SSError},
     launcher::{Command, SSLauncher},
-    midi::{note::Note, Channel, Velocity},
-    project::F,
+    midi::{
+        chord::{Chord, Voicing},
+        note::{Note, PitchClass},
+        Channel, Velocity, PITCH_BEND_CENTER,
+    },
+    project::{parse_fraction, ProbabilityMode, Project, TimeSignature, F},
+    scale::{Degree, Scale},
+    tempo::Ramp,
     timeline::TimelineState,
     SSResult,
 };
@@ -34,13 +43,17 @@ fn main() -> SSResult<()> {
     let mut ss_launcher = SSLauncher::new();
     let project = ss_launcher.project();
     let beat_receiver = ss_launcher.subscribe_to_beatmaker();
-    let example_drumtracks = if cfg!(target_os = "linux") {
-        &EXAMPLE_DRUMTRACKS_BITWIG
+    if let Some(path) = std::env::args().nth(1) {
+        project.replace(Project::load(path)?);
     } else {
-        &EXAMPLE_DRUMTRACKS_GARAGEBAND
-    };
-    for track in example_drumtracks.all_tracks() {
-        project.add_track(track);
+        let example_drumtracks = if cfg!(target_os = "linux") {
+            &EXAMPLE_DRUMTRACKS_BITWIG
+        } else {
+            &EXAMPLE_DRUMTRACKS_GARAGEBAND
+        };
+        for track in example_drumtracks.all_tracks() {
+            project.add_track(track);
+        }
     }
     ss_launcher.start()?;
     let mut tui = Tui::new(&mut ss_launcher);
@@ -86,6 +99,54 @@ fn str_to_command(s: &str) -> SSResult<Command> {
             "quit" => Ok(Command::Quit),
             "add_track" => Ok(Command::AddTrack),
             "debug" => Ok(Command::Debug),
+            "fill" => Ok(Command::ToggleFill),
+            "save" => {
+                if !args.is_empty() {
+                    Ok(Command::SaveProject(args[0].into()))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "load" => {
+                if !args.is_empty() {
+                    Ok(Command::LoadProject(args[0].into()))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "export" => {
+                if !args.is_empty() {
+                    Ok(Command::ExportMIDI(args[0].into()))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "import" => {
+                // import <path> [numer] [denom]: steps of numer/denom beats
+                // (sixteenth notes by default)
+                let grid = match args.len() {
+                    0 | 1 => F::new(1u64, 4u64),
+                    2 => F::from(args[1].parse::<u64>()?),
+                    _ => F::new(args[1].parse::<u64>()?, args[2].parse::<u64>()?),
+                };
+                if !args.is_empty() && grid.is_finite() && grid > F::from(0) {
+                    Ok(Command::ImportMIDI(args[0].into(), grid))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
             "R" => {
                 // "(R)ename track"
                 if args.len() >= 2 {
@@ -99,15 +160,76 @@ fn str_to_command(s: &str) -> SSResult<Command> {
                 }
             }
             "t" => {
-                // "(T)empo"
-                if args.len() >= 1 {
-                    let tempo = args[0].parse::<u16>()?;
-                    Ok(Command::ChangeTempo(tempo))
-                } else {
-                    Err(SSError::CommandError(CommandError::ArgumentError(
+                // "(T)empo", e.g. "t 120" or "t 120.5"
+                match args.first().and_then(|arg| parse_fraction(arg)) {
+                    Some(tempo) if tempo > F::from(0) => Ok(Command::ChangeTempo(tempo)),
+                    _ => Err(SSError::CommandError(CommandError::ArgumentError(
                         command.to_string(),
                         args.join(" "),
-                    )))
+                    ))),
+                }
+            }
+            "tp" => {
+                // (T)empo (P)oint: <beat> <tempo> [lin|exp], ramping to the next point
+                let ramp = match args.get(2) {
+                    None => Some(Ramp::Step),
+                    Some(&"lin") => Some(Ramp::Linear),
+                    Some(&"exp") => Some(Ramp::Exponential),
+                    Some(_) => None,
+                };
+                let beat = args.first().and_then(|arg| parse_fraction(arg));
+                let tempo = args.get(1).and_then(|arg| parse_fraction(arg));
+                match (beat, tempo, ramp) {
+                    (Some(beat), Some(tempo), Some(ramp))
+                        if beat >= F::from(0) && tempo > F::from(0) =>
+                    {
+                        Ok(Command::SetTempoPoint(beat, tempo, ramp))
+                    }
+                    _ => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "tprm" => {
+                // (T)empo (P)oint (R)e(M)ove: <beat>
+                match args.first().and_then(|arg| parse_fraction(arg)) {
+                    Some(beat) => Ok(Command::RemoveTempoPoint(beat)),
+                    None => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "sig" => {
+                // Time (SIG)nature: <bar> <numerator/denominator>
+                let bar = args
+                    .first()
+                    .and_then(|arg| arg.parse::<usize>().ok())
+                    .filter(|bar| *bar > 0);
+                let time_signature = args.get(1).and_then(|arg| TimeSignature::parse(arg));
+                match (bar, time_signature) {
+                    (Some(bar), Some(time_signature)) => {
+                        Ok(Command::SetTimeSignature(bar - 1, time_signature))
+                    }
+                    _ => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "sigrm" => {
+                // Time (SIG)nature (R)e(M)ove: <bar>
+                match args
+                    .first()
+                    .and_then(|arg| arg.parse::<usize>().ok())
+                    .filter(|bar| *bar > 0)
+                {
+                    Some(bar) => Ok(Command::RemoveTimeSignature(bar - 1)),
+                    None => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
                 }
             }
             "b" => {
@@ -123,6 +245,245 @@ fn str_to_command(s: &str) -> SSResult<Command> {
                     )))
                 }
             }
+            "d" => {
+                // scale (D)egree: <track> <beat> <degree> [octave], e.g. "d 1 3 5 2"
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let degree = args[2].parse::<u8>()?;
+                    let octave = match args.get(3) {
+                        Some(octave) => octave.parse::<i8>()?,
+                        None => 3,
+                    };
+                    if degree == 0 {
+                        return Err(SSError::CommandError(CommandError::ArgumentError(
+                            command.to_string(),
+                            args.join(" "),
+                        )));
+                    }
+                    Ok(Command::SetDegree(track, beat, Degree { degree, octave }))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "ch" => {
+                // (CH)ord: <track> <beat> <symbol> [octave] [voicing], e.g. "ch 1 3 Cmaj7/E 3 drop2"
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let chord = args[2].parse::<Chord>()?;
+                    let octave = match args.get(3) {
+                        Some(octave) => octave.parse::<i8>()?,
+                        None => 3,
+                    };
+                    let voicing = match args.get(4) {
+                        Some(voicing) => Voicing::parse(voicing).ok_or(SSError::CommandError(
+                            CommandError::ArgumentError(command.to_string(), args.join(" ")),
+                        ))?,
+                        None => Voicing::default(),
+                    };
+                    Ok(Command::SetChord(
+                        track,
+                        beat,
+                        ChordBeat {
+                            chord,
+                            octave,
+                            voicing,
+                        },
+                    ))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "cc" => {
+                // cc <track> <beat> <controller> <value | off>
+                if args.len() >= 4 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let controller = args[2].parse::<u8>()?;
+                    let value = match args[3] {
+                        "off" => None,
+                        value => Some(value.parse::<u8>()?),
+                    };
+                    if controller > 127 || value.is_some_and(|value| value > 127) {
+                        return Err(SSError::CommandError(CommandError::ArgumentError(
+                            command.to_string(),
+                            args.join(" "),
+                        )));
+                    }
+                    Ok(Command::SetCCLock(track, beat, controller, value))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "pb" => {
+                // (P)itch (B)end: <track> <beat> <-8192..8191 | off>
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let value = match args[2] {
+                        "off" => None,
+                        value => {
+                            let bend = value.parse::<i16>()?;
+                            if !(-8192..8192).contains(&bend) {
+                                return Err(SSError::CommandError(CommandError::ArgumentError(
+                                    command.to_string(),
+                                    args.join(" "),
+                                )));
+                            }
+                            Some((PITCH_BEND_CENTER as i16 + bend) as u16)
+                        }
+                    };
+                    Ok(Command::SetPitchBendLock(track, beat, value))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "ccdef" => {
+                // ccdef <track> <controller> <value | off>
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let controller = args[1].parse::<u8>()?;
+                    let value = match args[2] {
+                        "off" => None,
+                        value => Some(value.parse::<u8>()?),
+                    };
+                    if controller > 127 || value.is_some_and(|value| value > 127) {
+                        return Err(SSError::CommandError(CommandError::ArgumentError(
+                            command.to_string(),
+                            args.join(" "),
+                        )));
+                    }
+                    Ok(Command::SetCCDefault(track, controller, value))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "strum" => {
+                // strum <track> <beat> <fraction of a step>, negative to strum down
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let strum = parse_fraction(args[2]).ok_or(SSError::CommandError(
+                        CommandError::ArgumentError(command.to_string(), args.join(" ")),
+                    ))?;
+                    Ok(Command::SetStrum(track, beat, strum))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "n" => {
+                // (N)udge beat by a fraction of a step, e.g. -1/8
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let offset = parse_fraction(args[2]).ok_or(SSError::CommandError(
+                        CommandError::ArgumentError(command.to_string(), args.join(" ")),
+                    ))?;
+                    Ok(Command::SetOffset(track, beat, offset))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "p" => {
+                // set beat (P)robability in percent
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let probability = args[2].trim_end_matches('%').parse::<u8>()?;
+                    Ok(Command::SetProbability(track, beat, probability))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "c" => {
+                // set beat (C)ondition: a:b, fill, !fill, pre, !pre or always
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let condition = Condition::parse(args[2]).ok_or(SSError::CommandError(
+                        CommandError::ArgumentError(command.to_string(), args.join(" ")),
+                    ))?;
+                    Ok(Command::SetCondition(track, beat, condition))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "x" => {
+                // ratchet: <track> <beat> <count> [velocity ramp]
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let beat = args[1].parse::<usize>()? - 1;
+                    let count = args[2].parse::<u8>()?;
+                    let velocity_ramp = if args.len() >= 4 {
+                        args[3].parse::<i8>()?
+                    } else {
+                        0
+                    };
+                    Ok(Command::SetRatchet(
+                        track,
+                        beat,
+                        Ratchet {
+                            count,
+                            velocity_ramp,
+                        },
+                    ))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "m" => {
+                // toggle (M)ute: <track>
+                if !args.is_empty() {
+                    Ok(Command::ToggleMute(args[0].parse::<usize>()? - 1))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "s" => {
+                // toggle (S)olo: <track>
+                if !args.is_empty() {
+                    Ok(Command::ToggleSolo(args[0].parse::<usize>()? - 1))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
             "r" => {
                 // (R)esize
                 if args.len() >= 2 {
@@ -136,6 +497,25 @@ fn str_to_command(s: &str) -> SSResult<Command> {
                     )))
                 }
             }
+            "e" => {
+                // (E)uclidean rhythm: <track> <hits> <steps> [rotation]
+                if args.len() >= 3 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let hits = args[1].parse::<usize>()?;
+                    let steps = args[2].parse::<usize>()?;
+                    let rotation = if args.len() >= 4 {
+                        args[3].parse::<usize>()?
+                    } else {
+                        0
+                    };
+                    Ok(Command::Euclidean(track, hits, steps, rotation))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
             "tc" => {
                 // set (T)rack (C)hannel
                 if args.len() >= 2 {
@@ -192,6 +572,294 @@ fn str_to_command(s: &str) -> SSResult<Command> {
                     )))
                 }
             }
+            "tg" => {
+                // set (T)rack (G)ate: legato, <steps> or <beats> b,
+                // where lengths are fractions like 1/2
+                if args.len() >= 2 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    if args[1] == "legato" {
+                        Ok(Command::SetGate(track, Gate::Legato))
+                    } else {
+                        let length = parse_fraction(args[1]).ok_or(SSError::CommandError(
+                            CommandError::ArgumentError(command.to_string(), args.join(" ")),
+                        ))?;
+                        match args.get(2) {
+                            Some(&"b") => Ok(Command::SetGate(track, Gate::Beats(length))),
+                            _ => Ok(Command::SetGate(track, Gate::Steps(length))),
+                        }
+                    }
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "arp" => {
+                // (ARP)eggiator: <track> <order> [octaves] [rate] [gate] or <track> off,
+                // e.g. "arp 1 updown 2 1/8 1/2"
+                if args.len() >= 2 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    if args[1] == "off" {
+                        return Ok(Command::SetArpeggiator(track, None));
+                    }
+                    let default = Arpeggiator::default();
+                    let fraction = |idx: usize, default: F| match args.get(idx) {
+                        Some(arg) => parse_fraction(arg).filter(|f| *f > F::from(0)),
+                        None => Some(default),
+                    };
+                    let octaves = match args.get(2) {
+                        Some(octaves) => octaves.parse::<u8>()?,
+                        None => default.octaves,
+                    };
+                    match (
+                        ArpOrder::parse(args[1]),
+                        fraction(3, default.rate),
+                        fraction(4, default.gate),
+                    ) {
+                        (Some(order), Some(rate), Some(gate)) if octaves > 0 => {
+                            Ok(Command::SetArpeggiator(
+                                track,
+                                Some(Arpeggiator {
+                                    order,
+                                    octaves,
+                                    rate,
+                                    gate,
+                                }),
+                            ))
+                        }
+                        _ => Err(SSError::CommandError(CommandError::ArgumentError(
+                            command.to_string(),
+                            args.join(" "),
+                        ))),
+                    }
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "hold" => {
+                // hold <track> [notes], e.g. "hold 1 C3 E3 G3", or "hold 1" to release
+                if !args.is_empty() {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let notes = args[1..]
+                        .iter()
+                        .map(|note| note.parse::<Note>())
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(Command::HoldNotes(track, notes))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "pat" => {
+                // select (PAT)tern: <slot> [track], e.g. "pat A2" for all tracks
+                let slot = args.first().and_then(|arg| PatternSlot::parse(arg)).ok_or(
+                    SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )),
+                )?;
+                let track = match args.get(1) {
+                    Some(track) => Some(track.parse::<usize>()? - 1),
+                    None => None,
+                };
+                Ok(Command::SelectPattern(track, slot))
+            }
+            "pcopy" => {
+                // (P)attern (COPY): <from> <to> [track]
+                let (from, to) = match args[..] {
+                    [from, to, ..] => (PatternSlot::parse(from), PatternSlot::parse(to)),
+                    _ => (None, None),
+                };
+                let (Some(from), Some(to)) = (from, to) else {
+                    return Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )));
+                };
+                let track = match args.get(2) {
+                    Some(track) => Some(track.parse::<usize>()? - 1),
+                    None => None,
+                };
+                Ok(Command::CopyPattern(track, from, to))
+            }
+            "pclear" => {
+                // (P)attern (CLEAR): <slot> [track]
+                let slot = args.first().and_then(|arg| PatternSlot::parse(arg)).ok_or(
+                    SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )),
+                )?;
+                let track = match args.get(1) {
+                    Some(track) => Some(track.parse::<usize>()? - 1),
+                    None => None,
+                };
+                Ok(Command::ClearPattern(track, slot))
+            }
+            "sec" => {
+                // add (SEC)tion: <name> <slot> <length> [bars|loops], e.g. "sec Verse A2 4"
+                let length = match args[..] {
+                    [_, _, length] | [_, _, length, "bars"] => {
+                        Some(SectionLength::Bars(length.parse::<usize>()?))
+                    }
+                    [_, _, length, "loops"] => Some(SectionLength::Loops(length.parse::<usize>()?)),
+                    _ => None,
+                };
+                let slot = args.get(1).and_then(|arg| PatternSlot::parse(arg));
+                let (Some(slot), Some(length)) = (slot, length) else {
+                    return Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )));
+                };
+                Ok(Command::AddSection(args[0].to_string(), slot, length))
+            }
+            "secrm" => {
+                // (SEC)tion (R)e(M)ove: <section>
+                if !args.is_empty() {
+                    Ok(Command::RemoveSection(args[0].parse::<usize>()? - 1))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "secpat" => {
+                // (SEC)tion (PAT)tern: <section> <track> <slot>
+                if args.len() >= 3 {
+                    let section = args[0].parse::<usize>()? - 1;
+                    let track = args[1].parse::<usize>()? - 1;
+                    let slot = PatternSlot::parse(args[2]).ok_or(SSError::CommandError(
+                        CommandError::ArgumentError(command.to_string(), args.join(" ")),
+                    ))?;
+                    Ok(Command::SetSectionPattern(section, track, slot))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "song" => Ok(Command::ToggleSong),
+            "secloop" => {
+                // (SEC)tion (LOOP): <section> or off
+                match args.first() {
+                    Some(&"off") => Ok(Command::LoopSection(None)),
+                    Some(section) => Ok(Command::LoopSection(Some(section.parse::<usize>()? - 1))),
+                    None => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "swing" => {
+                // swing <fraction of a step>, e.g. 1/3 for a triplet feel
+                match args.first().and_then(|arg| parse_fraction(arg)) {
+                    Some(swing) => Ok(Command::SetSwing(swing)),
+                    None => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "tsw" => {
+                // set (T)rack (SW)ing: <fraction of a step> or off
+                if args.len() >= 2 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    if args[1] == "off" {
+                        Ok(Command::SetTrackSwing(track, None))
+                    } else {
+                        let swing = parse_fraction(args[1]).ok_or(SSError::CommandError(
+                            CommandError::ArgumentError(command.to_string(), args.join(" ")),
+                        ))?;
+                        Ok(Command::SetTrackSwing(track, Some(swing)))
+                    }
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "groove" => {
+                // groove <track> <name> or off
+                if args.len() >= 2 {
+                    let track = args[0].parse::<usize>()? - 1;
+                    let name = args[1..].join(" ");
+                    if name == "off" {
+                        Ok(Command::SetGroove(track, None))
+                    } else {
+                        Ok(Command::SetGroove(track, Some(name)))
+                    }
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "key" => {
+                // key <root>, e.g. "key F#"
+                match args.first().and_then(|arg| arg.parse::<PitchClass>().ok()) {
+                    Some(root) => Ok(Command::SetKey(root)),
+                    None => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "scale" => {
+                // scale <name>, or the semitones of a custom scale like "0,2,3,7,8"
+                match args.first().and_then(|arg| Scale::parse(arg)) {
+                    Some(scale) => Ok(Command::SetScale(scale)),
+                    None => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "seed" => {
+                if !args.is_empty() {
+                    Ok(Command::SetSeed(args[0].parse::<u64>()?))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
+            "pm" => {
+                // (P)robability (M)ode: reroll every loop or fixed per loop
+                match args.first() {
+                    Some(&"reroll") => Ok(Command::SetProbabilityMode(
+                        ProbabilityMode::RerollEveryLoop,
+                    )),
+                    Some(&"fixed") => {
+                        Ok(Command::SetProbabilityMode(ProbabilityMode::FixedPerLoop))
+                    }
+                    _ => Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    ))),
+                }
+            }
+            "grooves" => {
+                if !args.is_empty() {
+                    Ok(Command::LoadGrooves(args[0].into()))
+                } else {
+                    Err(SSError::CommandError(CommandError::ArgumentError(
+                        command.to_string(),
+                        args.join(" "),
+                    )))
+                }
+            }
             _ => Err(SSError::CommandError(CommandError::InvalidCommand(
                 command.to_string(),
             ))),
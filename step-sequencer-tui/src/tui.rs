@@ -19,6 +19,7 @@ use step_sequencer::{
     drum_track::{DrumTrack, DrumTrackBeat},
     error::SSError,
     launcher::SSLauncher,
+    project::TrackMap,
     SSResult,
 };
 use tui_input::backend::crossterm::EventHandler;
@@ -115,7 +116,10 @@ impl<'a> Tui<'a> {
         thread::spawn(move || loop {
             if let Ok(signal) = beatmaker_subscription.receiver.recv() {
                 match signal {
-                    BeatMakerEvent::Beat(_) => {
+                    BeatMakerEvent::Beat(_)
+                    | BeatMakerEvent::Position(_)
+                    | BeatMakerEvent::SongPosition(_)
+                    | BeatMakerEvent::SongEnd => {
                         // Be it Beat, Pause or Stop, redraw anyways
                         event_sender.send(TuiEvent::Redraw);
                     }
@@ -159,6 +163,18 @@ impl<'a> Tui<'a> {
                                     KeyCode::Esc => {
                                         self.execute_command("stop", &mut command_handler);
                                     }
+                                    KeyCode::Char(track @ '1'..='9') => {
+                                        self.execute_command(
+                                            &format!("m {}", track),
+                                            &mut command_handler,
+                                        );
+                                    }
+                                    KeyCode::F(track @ 1..=9) => {
+                                        self.execute_command(
+                                            &format!("s {}", track),
+                                            &mut command_handler,
+                                        );
+                                    }
                                     _ => {}
                                 },
                                 InputMode::Help => match key.code {
@@ -273,6 +289,8 @@ impl<'a> Tui<'a> {
             +       Add track
             Space   Play/Pause
             Esc     Stop
+            1-9     Mute track
+            F1-F9   Solo track
         "#;
         let popup = Popup::new(help_msg);
         frame.render_widget(popup, area);
@@ -287,12 +305,26 @@ impl<'a> Tui<'a> {
         let beat_view_area = beat_view_block.inner(area);
         let binding = self.ss_launcher.project().tracks();
         let tracks = binding.read().unwrap();
-        let current_beat = {
+        let (current_beat, tonality, song_tracks) = {
             let binding = self.ss_launcher.project().project_settings();
             let binding = binding.read().unwrap();
             let x = *binding.current_beat_time.read().unwrap();
-            x
+            // In song mode, show the patterns of the section being played
+            let song_tracks = binding.song_position.read().unwrap().is_some().then(|| {
+                tracks
+                    .iter()
+                    .map(|(id, track)| {
+                        let mut track = track.clone();
+                        if let Some(slot) = binding.song_pattern(id) {
+                            track.select_pattern(slot);
+                        }
+                        (*id, track)
+                    })
+                    .collect::<TrackMap>()
+            });
+            (x, binding.tonality.clone(), song_tracks)
         };
+        let tracks = song_tracks.as_ref().unwrap_or(&*tracks);
 
         // let beat_view_layout = Layout::vertical(vec![Constraint::Fill(1); tracks.len()]);
         // let track_areas = beat_view_layout.split(beat_view_area);
@@ -300,7 +332,7 @@ impl<'a> Tui<'a> {
         // for (track, area) in tracks.values().zip(track_areas.into_iter()) {
         //     self.render_track(frame, track, current_beat, *area);
         // }
-        let tracker_view = TrackerView::new(&*tracks, current_beat);
+        let tracker_view = TrackerView::new(tracks, current_beat, &tonality);
         frame.render_stateful_widget(tracker_view, beat_view_area, &mut Default::default());
     }
 
@@ -339,7 +371,9 @@ impl<'a> Tui<'a> {
                         Color::LightMagenta
                     }
                 }
-                DrumTrackBeat::OverrideBeat(_) => {
+                DrumTrackBeat::OverrideBeat(_)
+                | DrumTrackBeat::Degree(_)
+                | DrumTrackBeat::Chord(_) => {
                     if is_active_beat {
                         Color::LightBlue
                     } else {
@@ -358,8 +392,18 @@ impl<'a> Tui<'a> {
         let project_settings = project_settings.read().unwrap();
         let current_beat_time = *project_settings.current_beat_time.read().unwrap();
         let info = List::new(vec![
-            format!("Tempo: {}", project_settings.tempo),
-            format!("Current beat: {}", current_beat_time),
+            format!(
+                "Tempo: {:#.2}",
+                project_settings.tempo_map.tempo_at(current_beat_time)
+            ),
+            format!(
+                "Time signature: {}",
+                project_settings.meter.time_signature_at(current_beat_time)
+            ),
+            format!(
+                "Position: {}",
+                project_settings.meter.position(current_beat_time)
+            ),
         ])
         .block(Block::bordered().title("Info"));
         frame.render_widget(info, area);
@@ -5,7 +5,12 @@ use ratatui::{
     style::Stylize,
     widgets::{Cell, Row, StatefulWidget, Table, TableState},
 };
-use step_sequencer::{beatmaker::beat_time::BeatTime, drum_track::Beat, project::TrackMap};
+use step_sequencer::{
+    beatmaker::beat_time::BeatTime,
+    drum_track::{Beat, Condition, Ratchet},
+    project::{TrackMap, F},
+    scale::Tonality,
+};
 
 use super::styles::get_tracker_view_styles;
 
@@ -26,21 +31,46 @@ impl Default for TrackerViewState {
 pub struct TrackerView<'a> {
     tracks: &'a TrackMap,
     current_beat_time: BeatTime,
+    tonality: &'a Tonality,
 }
 
 impl<'a> TrackerView<'a> {
-    pub fn new(tracks: &'a TrackMap, current_beat: BeatTime) -> Self {
+    pub fn new(tracks: &'a TrackMap, current_beat: BeatTime, tonality: &'a Tonality) -> Self {
         Self {
             tracks,
             current_beat_time: current_beat,
+            tonality,
         }
     }
 }
 
-fn display_beats(beats: &Option<Vec<Beat>>) -> String {
+fn display_beats(
+    beats: &Option<Vec<Beat>>,
+    offset: F,
+    probability: u8,
+    condition: Condition,
+    ratchet: Ratchet,
+) -> String {
     match *beats {
         None => "".to_string(),
-        Some(ref beats) => beats.iter().map(|b| b.note.to_string()).join(","),
+        Some(ref beats) => {
+            let mut display = beats.iter().map(|b| b.note.to_string()).join(",");
+            if offset < F::from(0) {
+                display = format!("{} ({})", display, offset);
+            } else if offset > F::from(0) {
+                display = format!("{} (+{})", display, offset);
+            }
+            if ratchet.count > 1 {
+                display = format!("{} x{}", display, ratchet.count);
+            }
+            if probability < 100 {
+                display = format!("{} {}%", display, probability);
+            }
+            if condition != Condition::Always {
+                display = format!("{} [{}]", display, condition);
+            }
+            display
+        }
     }
 }
 
@@ -54,10 +84,23 @@ impl<'a> StatefulWidget for TrackerView<'a> {
         state: &mut Self::State,
     ) {
         let styles = get_tracker_view_styles();
-        let headers = self
-            .tracks
-            .values()
-            .map(|t| format!("{} (x{})", t.name(), t.get_tempo_scale()));
+        let headers = self.tracks.values().map(|t| {
+            let pattern = match t.get_queued_pattern() {
+                Some(queued) => format!("{}>{}", t.get_pattern(), queued),
+                None => t.get_pattern().to_string(),
+            };
+            let mut header = format!("{} [{}] (x{})", t.name(), pattern, t.get_tempo_scale());
+            if let Some(arpeggiator) = t.get_arpeggiator() {
+                header.push_str(&format!(" <{}>", arpeggiator.order));
+            }
+            if t.is_muted() {
+                header.push_str(" M");
+            }
+            if t.is_soloed() {
+                header.push_str(" S");
+            }
+            header
+        });
         let mut rows = vec![];
         let track_count = self.tracks.len();
         for (track_idx, track) in self.tracks.values().enumerate() {
@@ -67,7 +110,7 @@ impl<'a> StatefulWidget for TrackerView<'a> {
             let tempo_scale = track.get_tempo_scale();
             let track_beat_time = self.current_beat_time.stretch(tempo_scale);
             let active_beat_idx = track_beat_time.integral() % track.len();
-            for (beat_idx, beats_in_track) in track.iter_as_beats().enumerate() {
+            for (beat_idx, beats_in_track) in track.iter_as_beats(self.tonality).enumerate() {
                 if rows.len() < track.len() {
                     rows.resize(track.len() + 1, vec![Cell::new(""); track_count]);
                 }
@@ -76,8 +119,14 @@ impl<'a> StatefulWidget for TrackerView<'a> {
                 } else {
                     styles.default_cell_bg
                 };
-                rows[beat_idx][track_idx] =
-                    Cell::new(display_beats(&beats_in_track)).bg(row_bg_style);
+                rows[beat_idx][track_idx] = Cell::new(display_beats(
+                    &beats_in_track,
+                    track.get_offset(beat_idx),
+                    track.get_probability(beat_idx),
+                    track.get_condition(beat_idx),
+                    track.get_ratchet(beat_idx),
+                ))
+                .bg(row_bg_style);
             }
         }
         let rows: Vec<Row> = rows.into_iter().map(|r| Row::new(r)).collect();
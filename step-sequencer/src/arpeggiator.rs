@@ -0,0 +1,170 @@
+//! Arpeggiators: tracks that play the notes of a held chord one after
+//! another instead of all at once.
+//!
+//! A chord is held either by a step, for as long as its gate lasts, or
+//! by notes held with the `HoldNotes` command (see `DrumTrack::hold_notes`),
+//! which take precedence over the steps. Holding notes on a MIDI input is
+//! not supported yet, as no engine reads MIDI input. `BeatSorter` plays the notes of a held chord
+//! on a grid of `rate` beats of the track, so that arpeggios stay exact
+//! fractions of a beat at any tempo scale.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{drum_track::Beat, midi::Key, project::F};
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum ArpOrder {
+    #[default]
+    Up,
+    Down,
+    /// Up and back down without repeating the highest and lowest notes
+    UpDown,
+    /// A random note of the chord on every step of the arpeggio
+    Random,
+    /// In the order the notes of the chord were entered
+    AsPlayed,
+}
+
+impl ArpOrder {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            "updown" | "up_down" => Some(Self::UpDown),
+            "random" => Some(Self::Random),
+            "played" | "as_played" => Some(Self::AsPlayed),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ArpOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Up => write!(f, "up"),
+            Self::Down => write!(f, "down"),
+            Self::UpDown => write!(f, "updown"),
+            Self::Random => write!(f, "random"),
+            Self::AsPlayed => write!(f, "played"),
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Arpeggiator {
+    pub order: ArpOrder,
+    /// Number of octaves the chord is played in, from its own upwards
+    pub octaves: u8,
+    /// Distance of the notes in beats of the track
+    pub rate: F,
+    /// Length of the notes as a fraction of `rate`
+    pub gate: F,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self {
+            order: ArpOrder::default(),
+            octaves: 1,
+            rate: F::new(1u64, 4u64),
+            gate: F::new(1u64, 2u64),
+        }
+    }
+}
+
+impl Arpeggiator {
+    /// Notes of `chord` over all octaves in the order they are played.
+    /// Random arpeggios pick from the notes in ascending order.
+    /// Notes transposed beyond the range of MIDI keys are left out.
+    pub fn sequence(&self, chord: &[Beat]) -> Vec<Beat> {
+        let mut notes = chord.to_vec();
+        if self.order != ArpOrder::AsPlayed {
+            notes.sort_by_key(|beat| Into::<Key>::into(beat.note));
+        }
+        let mut sequence: Vec<_> = (0..self.octaves.max(1))
+            .flat_map(|octave| {
+                notes.iter().filter_map(move |beat| {
+                    let key = Into::<Key>::into(beat.note) as u16 + 12 * octave as u16;
+                    (key <= 127).then(|| Beat {
+                        note: (key as Key).into(),
+                        ..*beat
+                    })
+                })
+            })
+            .collect();
+        match self.order {
+            ArpOrder::Down => sequence.reverse(),
+            ArpOrder::UpDown if sequence.len() > 2 => {
+                let down: Vec<_> = sequence[1..sequence.len() - 1]
+                    .iter()
+                    .rev()
+                    .copied()
+                    .collect();
+                sequence.extend(down);
+            }
+            _ => {}
+        }
+        sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{drum_track::Beat, midi::note::Note, project::F};
+
+    use super::{ArpOrder, Arpeggiator};
+
+    fn chord(notes: &[Note]) -> Vec<Beat> {
+        notes
+            .iter()
+            .map(|note| Beat {
+                channel: 1,
+                note: *note,
+                velocity: 100,
+                gate: None,
+            })
+            .collect()
+    }
+
+    fn notes(arpeggiator: Arpeggiator, chord: &[Beat]) -> Vec<Note> {
+        arpeggiator
+            .sequence(chord)
+            .into_iter()
+            .map(|beat| beat.note)
+            .collect()
+    }
+
+    #[test]
+    fn test_sequence() {
+        let chord = chord(&[Note::E(3), Note::C(3), Note::G(3)]);
+        let arpeggiator = |order, octaves| Arpeggiator {
+            order,
+            octaves,
+            rate: F::new(1u64, 4u64),
+            gate: F::new(1u64, 2u64),
+        };
+        assert_eq!(
+            notes(arpeggiator(ArpOrder::Up, 2), &chord),
+            vec![
+                Note::C(3),
+                Note::E(3),
+                Note::G(3),
+                Note::C(4),
+                Note::E(4),
+                Note::G(4)
+            ]
+        );
+        assert_eq!(
+            notes(arpeggiator(ArpOrder::Down, 1), &chord),
+            vec![Note::G(3), Note::E(3), Note::C(3)]
+        );
+        assert_eq!(
+            notes(arpeggiator(ArpOrder::UpDown, 1), &chord),
+            vec![Note::C(3), Note::E(3), Note::G(3), Note::E(3)]
+        );
+        assert_eq!(
+            notes(arpeggiator(ArpOrder::AsPlayed, 1), &chord),
+            vec![Note::E(3), Note::C(3), Note::G(3)]
+        );
+    }
+}
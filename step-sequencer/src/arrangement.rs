@@ -0,0 +1,190 @@
+//! Arrangement of patterns into a song.
+//!
+//! A song is an ordered list of sections, each playing a pattern on every
+//! track for a number of bars or loops. In song mode, `BeatSorter` plans
+//! the sections ahead of playback and plays the steps of every section from
+//! the start of its patterns, so that the song ends after the last section
+//! instead of looping forever. A section can be looped until the loop is
+//! turned off, after which the song continues with the next section.
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    beatmaker::beat_time::BeatTime,
+    drum_track::PatternSlot,
+    id::SSId,
+    meter::Meter,
+    project::{lcm, TrackMap, F},
+};
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum SectionLength {
+    /// Bars of the time signatures the section plays in
+    Bars(usize),
+    /// Loops of the section, i.e. until all of its patterns line up again
+    Loops(usize),
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Section {
+    pub name: String,
+    /// Pattern of all tracks not in `track_patterns`
+    pub pattern: PatternSlot,
+    #[serde(default)]
+    pub track_patterns: IndexMap<SSId, PatternSlot>,
+    pub length: SectionLength,
+}
+
+impl Section {
+    pub fn new(name: &str, pattern: PatternSlot, length: SectionLength) -> Self {
+        Self {
+            name: name.to_string(),
+            pattern,
+            track_patterns: IndexMap::new(),
+            length,
+        }
+    }
+
+    pub fn pattern_for(&self, id: &SSId) -> PatternSlot {
+        self.track_patterns.get(id).copied().unwrap_or(self.pattern)
+    }
+
+    /// Length of the section in beats when starting at `start`. Sections of
+    /// loops of empty patterns have no length.
+    pub fn length(&self, tracks: &TrackMap, meter: &Meter, start: BeatTime) -> F {
+        match self.length {
+            SectionLength::Bars(bars) => meter.bars_length(start, bars),
+            SectionLength::Loops(loops) => {
+                tracks
+                    .iter()
+                    .map(|(id, track)| track.pattern_loop_length(self.pattern_for(id)))
+                    .filter(|loop_length| *loop_length > F::from(0))
+                    .reduce(lcm)
+                    .unwrap_or(F::from(0))
+                    * F::from(loops)
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Arrangement {
+    pub sections: Vec<Section>,
+    /// Song mode: play the sections instead of looping the active patterns
+    pub enabled: bool,
+    /// Section that is repeated instead of moving on to the next one
+    pub loop_section: Option<usize>,
+}
+
+impl Arrangement {
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.sections.is_empty()
+    }
+
+    /// Section to play after `section`, or the first one if `None`.
+    /// Returns `None` at the end of the song.
+    pub fn next_section(&self, section: Option<usize>) -> Option<usize> {
+        match section {
+            None => (!self.sections.is_empty()).then_some(0),
+            Some(section) if self.loop_section == Some(section) => Some(section),
+            Some(section) => (section + 1 < self.sections.len()).then_some(section + 1),
+        }
+    }
+
+    /// Length of the song in beats with every section played once.
+    pub fn length(&self, tracks: &TrackMap, meter: &Meter) -> F {
+        self.sections.iter().fold(F::from(0), |sum, section| {
+            sum + section.length(tracks, meter, BeatTime::new(sum))
+        })
+    }
+}
+
+/// Section planned to play from `start` (inclusive) to `end` (exclusive).
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct PlannedSection {
+    pub section: usize,
+    pub start: BeatTime,
+    pub end: BeatTime,
+}
+
+/// Position in a song, counting sections and bars within them from zero.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize)]
+pub struct SongPosition {
+    pub section: usize,
+    pub bar: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use crate::{
+        beatmaker::beat_time::BeatTime,
+        drum_track::{DrumTrack, PatternSlot},
+        id::new_id,
+        meter::Meter,
+        midi::note::Note,
+        project::{TimeSignature, F},
+    };
+
+    use super::{Arrangement, Section, SectionLength};
+
+    #[test]
+    fn test_section_length() {
+        let a1 = PatternSlot::parse("A1").unwrap();
+        let a2 = PatternSlot::parse("A2").unwrap();
+        let mut track = DrumTrack::with_beats_for_test(F::from(2), Note::C(1), 4);
+        track.select_pattern(a2);
+        track.resize(3);
+        track.select_pattern(a1);
+        let tracks: IndexMap<_, _> = [
+            (new_id(), track),
+            (
+                new_id(),
+                DrumTrack::with_beats_for_test(F::from(1), Note::D(1), 4),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut meter = Meter::new(TimeSignature {
+            numerator: 6,
+            denominator: 8,
+        });
+        let start = BeatTime::zero();
+        let bars = Section::new("Intro", a1, SectionLength::Bars(2));
+        assert_eq!(bars.length(&tracks, &meter, start), F::from(6));
+        // 2 and 4 beats long
+        let loops = Section::new("Verse", a1, SectionLength::Loops(2));
+        assert_eq!(loops.length(&tracks, &meter, start), F::from(8));
+        // 3/2 and 4 beats long
+        let loops = Section::new("Chorus", a2, SectionLength::Loops(1));
+        assert_eq!(loops.length(&tracks, &meter, start), F::from(12));
+        // A bar of 6/8, then one of 4/4
+        meter.insert(1, TimeSignature::default());
+        assert_eq!(bars.length(&tracks, &meter, start), F::from(7));
+        let arrangement = Arrangement {
+            sections: vec![bars.clone(), bars],
+            ..Default::default()
+        };
+        assert_eq!(arrangement.length(&tracks, &meter), F::from(15));
+    }
+
+    #[test]
+    fn test_next_section() {
+        let a1 = PatternSlot::default();
+        let mut arrangement = Arrangement {
+            sections: vec![
+                Section::new("Verse", a1, SectionLength::Bars(1)),
+                Section::new("Chorus", a1, SectionLength::Bars(1)),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(arrangement.next_section(None), Some(0));
+        assert_eq!(arrangement.next_section(Some(0)), Some(1));
+        assert_eq!(arrangement.next_section(Some(1)), None);
+        arrangement.loop_section = Some(0);
+        assert_eq!(arrangement.next_section(Some(0)), Some(0));
+    }
+}
@@ -1,59 +1,428 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    ops::Range,
     sync::{Arc, RwLock},
 };
 
 use crate::{
-    drum_track::{Beat, DrumTrack},
+    arpeggiator::{ArpOrder, Arpeggiator},
+    arrangement::{PlannedSection, SongPosition},
+    drum_track::{Beat, Condition, DrumTrack, ParameterLocks, PatternSlot},
+    groove::Groove,
     id::SSId,
-    midi::ChannelVoiceEvent,
-    project::{TrackMap, F},
+    midi::{Channel, ChannelVoiceEvent, Controller, Key, PITCH_BEND_CENTER},
+    project::{ProbabilityMode, ProjectSettings, TrackMap, F},
+    util::random,
 };
 
 use super::beat_time::BeatTime;
 
+pub type SortedEvents = Vec<(BeatTime, Vec<(SSId, ChannelVoiceEvent)>)>;
+
+/// Controller values of the step of a beat along with the channel of its track
+type Controls = (Channel, ParameterLocks);
+
+/// Beat along with its gate length and the controls of its step
+type StepBeat = (BeatTime, Beat, F, Controls);
+
+/// Beat of a track along with its gate length and the controls of its step
+type TrackBeat = (BeatTime, SSId, Beat, F, Controls);
+
 pub struct BeatSorter {
     tracks: Arc<RwLock<TrackMap>>,
+    project_settings: Arc<RwLock<ProjectSettings>>,
     current_beat_time: BeatTime,
+    /// Events scheduled at or after `current_beat_time`, e.g. NoteOffs of
+    /// notes that are still sounding
+    pending: BTreeMap<BeatTime, Vec<(SSId, ChannelVoiceEvent)>>,
+    /// Beat times of the NoteOn and the pending NoteOff of every sounding key
+    sounding: HashMap<(Channel, Key), (BeatTime, BeatTime)>,
+    /// Step at which the active pattern of each track started, if not zero
+    pattern_origins: HashMap<SSId, usize>,
+    /// Step at which the queued pattern of each track takes over
+    pattern_switches: HashMap<SSId, usize>,
+    /// Patterns switched to since the last `apply_pattern_switches`
+    switched_patterns: Vec<(SSId, PatternSlot)>,
+    /// Sections of the song planned from around `current_beat_time` on
+    planned_sections: VecDeque<PlannedSection>,
+    /// Beat time at which the song ends, once planned
+    song_end: Option<BeatTime>,
+    /// Last value sent of every controller
+    controllers: HashMap<(Channel, Controller), u8>,
+    /// Last pitch bend sent on every channel, centered if none
+    pitch_bends: HashMap<Channel, u16>,
 }
 
-fn find_next_beat_in_track(track: &DrumTrack, beat_time: BeatTime) -> usize {
-    let idx = if beat_time.fraction() > F::from(0) {
-        // We are between two beats
-        beat_time.integral() + 1
-    } else {
-        beat_time.integral()
+/// Swing, grooves and step offsets shift steps by at most one step in total,
+/// so steps up to one step outside of a range may still land inside it.
+const GROOVE_MARGIN: usize = 1;
+
+/// Steps are counted from the start of the timeline. The active pattern of a
+/// track starts at step `origin`, so the `step`th step plays step `idx` of
+/// the pattern in loop `iteration`.
+#[derive(Clone, Copy)]
+struct PatternStep {
+    iteration: usize,
+    idx: usize,
+}
+
+fn pattern_step(track: &DrumTrack, origin: usize, step: usize) -> PatternStep {
+    let step = step - origin;
+    PatternStep {
+        iteration: step / track.len(),
+        idx: step % track.len(),
+    }
+}
+
+/// Beat time of the `step`th step of a track since the start of the timeline,
+/// shifted by groove and step offset. Steps that would be moved before the
+/// start of the timeline are played right at the start.
+fn step_beat_time(track: &DrumTrack, groove: &Groove, origin: usize, step: usize) -> BeatTime {
+    let margin = F::from(GROOVE_MARGIN);
+    let idx = pattern_step(track, origin, step).idx;
+    let offset = (groove.timing(step) + track.get_offset(idx)).clamp(-margin, margin);
+    BeatTime::new(F::from(step) + offset)
+        .compress(track.get_tempo_scale())
+        .max(BeatTime::zero())
+}
+
+/// Whether the `step`th step of a track since the start of the timeline fires.
+/// The roll only depends on the seed, the track and the step (or the step
+/// within the loop, depending on the probability mode).
+fn step_fires(
+    id: SSId,
+    track: &DrumTrack,
+    project_settings: &ProjectSettings,
+    origin: usize,
+    step: usize,
+) -> bool {
+    let idx = pattern_step(track, origin, step).idx;
+    let probability = track.get_probability(idx);
+    if probability >= 100 {
+        return true;
+    }
+    let roll_step = match project_settings.probability_mode {
+        ProbabilityMode::RerollEveryLoop => step,
+        ProbabilityMode::FixedPerLoop => idx,
     };
-    return idx % track.len();
+    let (hi, lo) = id.as_u64_pair();
+    let roll = random::below(&[project_settings.seed, hi, lo, roll_step as u64], 100);
+    roll < probability as u64
 }
 
-/// Get beats from start (inclusive) to end (exclusive) respecting track tempo scale
+/// Whether the condition of the `step`th step of a track since the start of
+/// the timeline is met, counting loops from the start of the active pattern.
+fn condition_met(
+    track: &DrumTrack,
+    project_settings: &ProjectSettings,
+    origin: usize,
+    step: usize,
+) -> bool {
+    let PatternStep { iteration, idx } = pattern_step(track, origin, step);
+    match track.get_condition(idx) {
+        Condition::Always => true,
+        Condition::Loop(a, b) => b > 0 && iteration % b + 1 == a,
+        Condition::Fill => project_settings.fill,
+        Condition::NotFill => !project_settings.fill,
+        Condition::Pre => previous_condition_met(track, project_settings, origin, step),
+        Condition::NotPre => !previous_condition_met(track, project_settings, origin, step),
+    }
+}
+
+/// Evaluates the closest condition other than `Always`, `Pre` and `NotPre`
+/// on a beat of the track within one loop before the `step`th step.
+/// Without such a condition, it counts as met.
+fn previous_condition_met(
+    track: &DrumTrack,
+    project_settings: &ProjectSettings,
+    origin: usize,
+    step: usize,
+) -> bool {
+    for previous in (step.saturating_sub(track.len()).max(origin)..step).rev() {
+        let idx = pattern_step(track, origin, previous).idx;
+        if track
+            .get_as_beats(idx, &project_settings.tonality)
+            .flatten()
+            .is_none()
+        {
+            continue;
+        }
+        match track.get_condition(idx) {
+            Condition::Always | Condition::Pre | Condition::NotPre => continue,
+            _ => return condition_met(track, project_settings, origin, previous),
+        }
+    }
+    true
+}
+
+/// First loop boundary of the active pattern of a track, which starts at step
+/// `origin`, none of whose steps has been played before `current_beat_time`.
+/// Since steps may be shifted by up to a step, it lies at least one step later.
+fn next_loop_boundary(track: &DrumTrack, origin: usize, current_beat_time: BeatTime) -> usize {
+    let earliest = if current_beat_time == BeatTime::zero() {
+        0
+    } else {
+        current_beat_time
+            .stretch(track.get_tempo_scale())
+            .ceil()
+            .integral()
+            + GROOVE_MARGIN
+    }
+    .max(origin);
+    if track.is_empty() {
+        earliest
+    } else {
+        origin + (earliest - origin).div_ceil(track.len()) * track.len()
+    }
+}
+
+/// Ids of muted tracks, and of tracks not soloed while any track is
+fn silenced_tracks(tracks: &TrackMap) -> HashSet<SSId> {
+    let any_soloed = tracks.values().any(|track| track.is_soloed());
+    tracks
+        .iter()
+        .filter(|(_, track)| track.is_muted() || (any_soloed && !track.is_soloed()))
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// First step of a track at or after `beat_time`
+fn step_at(beat_time: BeatTime, tempo_scale: F) -> usize {
+    beat_time.stretch(tempo_scale).ceil().integral()
+}
+
+/// Get beats from start (inclusive) to end (exclusive) respecting track tempo scale,
+/// groove, step offsets, conditions, probabilities and ratchets, along with their
+/// gate length. Each retrigger of a ratchet is returned as a beat of its own.
+/// Only `steps` are played by the active pattern, which starts at `steps.start`.
 /// `start` and `end` are global beat time (i.e. when tempo scale = 1)
 fn get_beats_between_in_track(
+    id: SSId,
     track: &DrumTrack,
+    project_settings: &ProjectSettings,
+    steps: Range<usize>,
     start: BeatTime,
     end: BeatTime,
-) -> Vec<(BeatTime, Beat)> {
+) -> Vec<StepBeat> {
+    if let Some(arpeggiator) = track.get_arpeggiator() {
+        return get_arpeggio_between_in_track(
+            id,
+            track,
+            arpeggiator,
+            project_settings,
+            steps,
+            start,
+            end,
+        );
+    }
+    if track.is_empty() {
+        return vec![];
+    }
+    let origin = steps.start;
+    let groove = project_settings.groove_for(track);
     let tempo_scale = track.get_tempo_scale();
-    let mut start_scaled = start.stretch(tempo_scale).ceil();
-    let end_scaled = end.stretch(tempo_scale);
+    // Retriggers of a step may land up to one step after it
+    let first_step = start
+        .stretch(tempo_scale)
+        .ceil()
+        .integral()
+        .saturating_sub(GROOVE_MARGIN + 1)
+        .max(steps.start);
+    let last_step = (end.stretch(tempo_scale).ceil().integral() + GROOVE_MARGIN)
+        .min(steps.end.saturating_sub(1));
     let mut result = vec![];
-    while start_scaled < end_scaled {
-        let idx = find_next_beat_in_track(track, start_scaled);
-        let beats = track.get_as_beats(idx).flatten();
-        if let Some(beats) = beats {
-            result.extend(
-                beats
-                    .into_iter()
-                    .map(|b| (start_scaled.compress(tempo_scale), b)),
-            );
+    for step in first_step..=last_step {
+        let idx = pattern_step(track, origin, step).idx;
+        let Some(beats) = track
+            .get_as_beats(idx, &project_settings.tonality)
+            .flatten()
+        else {
+            continue;
+        };
+        let step_time = step_beat_time(track, &groove, origin, step);
+        let controls = (track.get_default_beat().channel, track.controls(Some(idx)));
+        let ratchet = track.get_ratchet(idx);
+        let count = ratchet.count.max(1);
+        let spacing = F::from(1) / tempo_scale / F::from(count);
+        let delays = strum_delays(&beats, track.get_strum(idx) / tempo_scale, spacing);
+        let notes: Vec<_> = (0..count)
+            .flat_map(|i| {
+                let retrigger_time = step_time.add_fraction(spacing * F::from(i));
+                delays
+                    .iter()
+                    .enumerate()
+                    .map(move |(n, delay)| (i, n, retrigger_time.add_fraction(*delay)))
+            })
+            .filter(|(_, _, beat_time)| *beat_time >= start && *beat_time < end)
+            .collect();
+        if notes.is_empty()
+            || !condition_met(track, project_settings, origin, step)
+            || !step_fires(id, track, project_settings, origin, step)
+        {
+            continue;
+        }
+        for (i, n, beat_time) in notes {
+            let b = &beats[n];
+            let velocity = groove.velocity(step, b.velocity);
+            let b = Beat {
+                velocity: ratchet.velocity(i, velocity),
+                ..*b
+            };
+            let mut gate_length = track.gate_length(idx, &b);
+            if count > 1 {
+                gate_length = gate_length.min(spacing);
+            }
+            result.push((beat_time, b, gate_length, controls.clone()));
         }
-        start_scaled = start_scaled.add_integral(1);
     }
 
     return result;
 }
 
+/// Delays of `beats` strummed `strum` beats apart, from the lowest note up,
+/// or from the highest down if negative. All notes start within `max`.
+fn strum_delays(beats: &[Beat], strum: F, max: F) -> Vec<F> {
+    let mut delays = vec![F::from(0); beats.len()];
+    if strum == F::from(0) || beats.len() < 2 {
+        return delays;
+    }
+    let mut order: Vec<_> = (0..beats.len()).collect();
+    order.sort_by_key(|n| Into::<Key>::into(beats[*n].note));
+    if strum < F::from(0) {
+        order.reverse();
+    }
+    let strum = if strum < F::from(0) { -strum } else { strum };
+    let delay = strum.min(max / F::from(beats.len() - 1));
+    for (rank, n) in order.into_iter().enumerate() {
+        delays[n] = delay * F::from(rank);
+    }
+    delays
+}
+
+/// Like `get_beats_between_in_track`, but plays the notes of the chord held
+/// by each step one after another for as long as its gate lasts, or the
+/// notes held on the track throughout. Arpeggios start over on every step.
+/// Chords of steps are held for at most a loop of the track.
+fn get_arpeggio_between_in_track(
+    id: SSId,
+    track: &DrumTrack,
+    arpeggiator: &Arpeggiator,
+    project_settings: &ProjectSettings,
+    steps: Range<usize>,
+    start: BeatTime,
+    end: BeatTime,
+) -> Vec<StepBeat> {
+    let tempo_scale = track.get_tempo_scale();
+    let rate = arpeggiator.rate / tempo_scale;
+    if rate <= F::from(0) {
+        return vec![];
+    }
+    let gate_length = rate * arpeggiator.gate;
+    let (hi, lo) = id.as_u64_pair();
+    // Index of the first note of an arpeggio starting at `from` at or after `start`
+    let first_note = |from: BeatTime| {
+        if start <= from {
+            0
+        } else {
+            BeatTime::new((start.as_fraction() - from.as_fraction()) / rate)
+                .ceil()
+                .integral()
+        }
+    };
+    let pick = |sequence: &[Beat], step: u64, i: usize| match arpeggiator.order {
+        ArpOrder::Random => {
+            let roll = [project_settings.seed, hi, lo, step, i as u64];
+            sequence[random::below(&roll, sequence.len() as u64) as usize]
+        }
+        _ => sequence[i % sequence.len()],
+    };
+    let channel = track.get_default_beat().channel;
+    let held = track.held_chord();
+    let mut result = vec![];
+    if !held.is_empty() {
+        let sequence = arpeggiator.sequence(&held);
+        if sequence.is_empty() {
+            return result;
+        }
+        // Notes of the range of steps only, which may be part of a song
+        let from = BeatTime::new(F::from(steps.start)).compress(tempo_scale);
+        let to = BeatTime::new(F::from(steps.end)).compress(tempo_scale);
+        let mut i = BeatTime::new(start.max(from).as_fraction() / rate)
+            .ceil()
+            .integral();
+        loop {
+            let beat_time = BeatTime::new(rate * F::from(i));
+            if beat_time >= end || beat_time >= to {
+                break;
+            }
+            result.push((
+                beat_time,
+                pick(&sequence, u64::MAX, i),
+                gate_length,
+                (channel, track.controls(None)),
+            ));
+            i += 1;
+        }
+        return result;
+    }
+    if track.is_empty() {
+        return result;
+    }
+    let origin = steps.start;
+    let groove = project_settings.groove_for(track);
+    let first_step = start
+        .stretch(tempo_scale)
+        .ceil()
+        .integral()
+        .saturating_sub(track.len() + GROOVE_MARGIN)
+        .max(steps.start);
+    let last_step = (end.stretch(tempo_scale).ceil().integral() + GROOVE_MARGIN)
+        .min(steps.end.saturating_sub(1));
+    for step in first_step..=last_step {
+        let idx = pattern_step(track, origin, step).idx;
+        let Some(chord) = track
+            .get_as_beats(idx, &project_settings.tonality)
+            .flatten()
+        else {
+            continue;
+        };
+        let step_time = step_beat_time(track, &groove, origin, step);
+        let hold = track
+            .gate_length(idx, &chord[0])
+            .min(F::from(track.len()) / tempo_scale);
+        let mut i = first_note(step_time);
+        let mut notes = vec![];
+        while rate * F::from(i) < hold {
+            let beat_time = step_time.add_fraction(rate * F::from(i));
+            if beat_time >= end {
+                break;
+            }
+            notes.push((i, beat_time));
+            i += 1;
+        }
+        if notes.is_empty()
+            || !condition_met(track, project_settings, origin, step)
+            || !step_fires(id, track, project_settings, origin, step)
+        {
+            continue;
+        }
+        let sequence = arpeggiator.sequence(&chord);
+        if sequence.is_empty() {
+            continue;
+        }
+        let controls = (channel, track.controls(Some(idx)));
+        for (i, beat_time) in notes {
+            let beat = pick(&sequence, step as u64, i);
+            let beat = Beat {
+                velocity: groove.velocity(step, beat.velocity),
+                ..beat
+            };
+            result.push((beat_time, beat, gate_length.min(hold), controls.clone()));
+        }
+    }
+    result
+}
+
 fn beat_to_channel_voice_events(beat: Beat) -> [ChannelVoiceEvent; 2] {
     [
         ChannelVoiceEvent::NoteOn {
@@ -69,40 +438,1350 @@ fn beat_to_channel_voice_events(beat: Beat) -> [ChannelVoiceEvent; 2] {
     ]
 }
 
+/// Order of events at the same beat time: a note has to be ended before
+/// the same key can be struck again, and other messages take effect
+/// before new notes are struck.
+fn event_order(event: &ChannelVoiceEvent) -> u8 {
+    match event {
+        ChannelVoiceEvent::NoteOff { .. } => 0,
+        ChannelVoiceEvent::NoteOn { .. } => 2,
+        _ => 1,
+    }
+}
+
 impl BeatSorter {
-    pub fn with_tracks(tracks: Arc<RwLock<TrackMap>>) -> Self {
+    pub fn new(
+        tracks: Arc<RwLock<TrackMap>>,
+        project_settings: Arc<RwLock<ProjectSettings>>,
+    ) -> Self {
         Self {
             tracks,
+            project_settings,
             current_beat_time: BeatTime::zero(),
+            pending: BTreeMap::new(),
+            sounding: HashMap::new(),
+            pattern_origins: HashMap::new(),
+            pattern_switches: HashMap::new(),
+            switched_patterns: vec![],
+            planned_sections: VecDeque::new(),
+            song_end: None,
+            controllers: HashMap::new(),
+            pitch_bends: HashMap::new(),
         }
     }
 
+    /// Rewinds to zero and drops pending events.
+    /// Call `flush` first to not leave any notes hanging.
     pub fn reset(&mut self) {
         self.current_beat_time = BeatTime::zero();
+        self.pending.clear();
+        self.sounding.clear();
+        self.pattern_origins.clear();
+        self.pattern_switches.clear();
+        self.switched_patterns.clear();
+        self.planned_sections.clear();
+        self.song_end = None;
+        self.controllers.clear();
+        self.pitch_bends.clear();
+    }
+
+    /// Carries on from `beat_time` with the tracks as they are now, e.g.
+    /// after tracks were added or changed. Unlike `reset`, patterns switched
+    /// to during playback keep the step they started at.
+    /// Call `flush` first to not leave any notes hanging.
+    pub fn reload(&mut self, beat_time: BeatTime) {
+        let pattern_origins = std::mem::take(&mut self.pattern_origins);
+        self.reset();
+        self.pattern_origins = pattern_origins;
+        self.jump(beat_time);
     }
 
     pub fn jump(&mut self, beat_time: BeatTime) {
         self.current_beat_time = beat_time;
+        self.planned_sections.clear();
+        self.song_end = None;
+    }
+
+    /// Returns all events from the current beat time (inclusive) to
+    /// `next_beat_time` (exclusive) in order.
+    /// Events of notes starting in this range that fall beyond it
+    /// (e.g. NoteOffs) are held back until a later call covers them.
+    /// In song mode, tracks play the patterns of the sections of the song.
+    /// Otherwise, queued patterns take over at the next loop boundary of
+    /// their track covered by the range. The tracks are left as they are
+    /// until `apply_pattern_switches` is called.
+    /// Muted tracks, and tracks not soloed while others are, keep running
+    /// silently. Their notes still sounding are ended right away.
+    /// Controller values locked on a step, or restored to the defaults of its
+    /// track, are sent before any note starting at the same beat time.
+    pub fn advance(&mut self, next_beat_time: BeatTime) -> SortedEvents {
+        let project_settings = self.project_settings.clone();
+        let project_settings = project_settings.read().unwrap();
+        let tracks = self.tracks.clone();
+        let (beats, selections) = {
+            let tracks = tracks.read().unwrap();
+            let silenced = silenced_tracks(&tracks);
+            for id in silenced.iter() {
+                self.release(*id);
+            }
+            let (mut beats, selections) = if project_settings.arrangement.is_active() {
+                (
+                    self.song_beats(&tracks, &project_settings, next_beat_time),
+                    vec![],
+                )
+            } else {
+                self.loop_beats(&tracks, &project_settings, next_beat_time)
+            };
+            beats.retain(|(_, id, ..)| !silenced.contains(id));
+            (beats, selections)
+        };
+        self.switched_patterns.extend(selections);
+        self.schedule_beats(beats, next_beat_time)
+    }
+
+    /// Selects the patterns switched to by `advance` on the tracks, so that
+    /// they show the patterns being played. Only the owner of the tracks
+    /// being played should do so, as offline renders must leave them as they
+    /// are.
+    pub fn apply_pattern_switches(&mut self) {
+        if self.switched_patterns.is_empty() {
+            return;
+        }
+        let mut tracks = self.tracks.write().unwrap();
+        for (id, slot) in self.switched_patterns.drain(..) {
+            if let Some(track) = tracks.get_mut(&id) {
+                track.select_pattern(slot);
+            }
+        }
+    }
+
+    /// Beats of the active patterns, switching to queued patterns at loop
+    /// boundaries, along with the patterns to switch to.
+    fn loop_beats(
+        &mut self,
+        tracks: &TrackMap,
+        project_settings: &ProjectSettings,
+        next_beat_time: BeatTime,
+    ) -> (Vec<TrackBeat>, Vec<(SSId, PatternSlot)>) {
+        let mut beats = vec![];
+        let mut switches = vec![];
+        for (id, track) in tracks.iter() {
+            let origin = self.pattern_origins.get(id).copied().unwrap_or(0);
+            let mut steps = origin..usize::MAX;
+            if let Some(slot) = track.get_queued_pattern() {
+                let boundary = *self
+                    .pattern_switches
+                    .entry(*id)
+                    .or_insert_with(|| next_loop_boundary(track, origin, self.current_beat_time));
+                let mut next_track = track.clone();
+                next_track.select_pattern(slot);
+                beats.extend(
+                    get_beats_between_in_track(
+                        *id,
+                        &next_track,
+                        project_settings,
+                        boundary..usize::MAX,
+                        self.current_beat_time,
+                        next_beat_time,
+                    )
+                    .into_iter()
+                    .map(|(beat_time, beat, gate_length, controls)| {
+                        (beat_time, *id, beat, gate_length, controls)
+                    }),
+                );
+                steps = origin..boundary;
+                // Steps before the boundary may be shifted up to the boundary itself
+                if BeatTime::new(F::from(boundary)).compress(track.get_tempo_scale())
+                    < next_beat_time
+                {
+                    switches.push((*id, slot));
+                    self.pattern_switches.remove(id);
+                    self.pattern_origins.insert(*id, boundary);
+                }
+            } else {
+                self.pattern_switches.remove(id);
+            }
+            beats.extend(
+                get_beats_between_in_track(
+                    *id,
+                    track,
+                    project_settings,
+                    steps,
+                    self.current_beat_time,
+                    next_beat_time,
+                )
+                .into_iter()
+                .map(|(beat_time, beat, gate_length, controls)| {
+                    (beat_time, *id, beat, gate_length, controls)
+                }),
+            );
+        }
+        (beats, switches)
     }
 
-    pub fn advance(
+    /// Beats of the patterns of the sections of the song. The active
+    /// patterns of the tracks are left as they are, see
+    /// `ProjectSettings::song_pattern`.
+    fn song_beats(
         &mut self,
+        tracks: &TrackMap,
+        project_settings: &ProjectSettings,
         next_beat_time: BeatTime,
-    ) -> Vec<(BeatTime, Vec<(SSId, ChannelVoiceEvent)>)> {
-        let mut treemap: BTreeMap<BeatTime, Vec<_>> = BTreeMap::new();
-        for (id, track) in self.tracks.read().unwrap().iter() {
-            for (beat_time, beat) in
-                get_beats_between_in_track(track, self.current_beat_time, next_beat_time)
+    ) -> Vec<TrackBeat> {
+        // Steps up to two steps (see `get_beats_between_in_track`) around
+        // the range may be played in it
+        let margin = |track: &DrumTrack| F::from(GROOVE_MARGIN + 1) / track.get_tempo_scale();
+        let lookahead = tracks.values().map(margin).max().unwrap_or_default();
+        self.plan_sections(
+            tracks,
+            project_settings,
+            next_beat_time.add_fraction(lookahead),
+        );
+        while self.planned_sections.len() > 1
+            && self.planned_sections[0].end.add_fraction(lookahead) <= self.current_beat_time
+        {
+            self.planned_sections.pop_front();
+        }
+        let sections = &project_settings.arrangement.sections;
+        let mut beats = vec![];
+        for (id, track) in tracks.iter() {
+            let tempo_scale = track.get_tempo_scale();
+            let margin = margin(track);
+            for planned in &self.planned_sections {
+                if planned.start >= next_beat_time.add_fraction(margin)
+                    || planned.end.add_fraction(margin) <= self.current_beat_time
+                {
+                    continue;
+                }
+                let Some(section) = sections.get(planned.section) else {
+                    continue;
+                };
+                let slot = section.pattern_for(id);
+                let steps = step_at(planned.start, tempo_scale)..step_at(planned.end, tempo_scale);
+                let section_beats = if slot == track.get_pattern() {
+                    get_beats_between_in_track(
+                        *id,
+                        track,
+                        project_settings,
+                        steps,
+                        self.current_beat_time,
+                        next_beat_time,
+                    )
+                } else {
+                    let mut section_track = track.clone();
+                    section_track.select_pattern(slot);
+                    get_beats_between_in_track(
+                        *id,
+                        &section_track,
+                        project_settings,
+                        steps,
+                        self.current_beat_time,
+                        next_beat_time,
+                    )
+                };
+                beats.extend(section_beats.into_iter().map(
+                    |(beat_time, beat, gate_length, controls)| {
+                        (beat_time, *id, beat, gate_length, controls)
+                    },
+                ));
+            }
+        }
+        beats
+    }
+
+    /// Plans the sections of the song up to `until`, skipping sections
+    /// without length.
+    fn plan_sections(
+        &mut self,
+        tracks: &TrackMap,
+        project_settings: &ProjectSettings,
+        until: BeatTime,
+    ) {
+        let arrangement = &project_settings.arrangement;
+        let length = |section: usize, start: BeatTime| {
+            arrangement.sections[section].length(tracks, &project_settings.meter, start)
+        };
+        while self.song_end.is_none() {
+            let (previous, start) = match self.planned_sections.back() {
+                Some(planned) => (Some(planned.section), planned.end),
+                None => (None, BeatTime::zero()),
+            };
+            if start >= until {
+                break;
+            }
+            let mut section = arrangement.next_section(previous);
+            let mut skipped = 0;
+            while let Some(s) = section
+                && s < arrangement.sections.len()
+                && skipped <= arrangement.sections.len()
+                && length(s, start) == F::from(0)
             {
-                let [note_on, note_off] = beat_to_channel_voice_events(beat);
-                treemap.entry(beat_time).or_default().push((*id, note_on));
-                treemap
-                    .entry(beat_time.add_fraction(F::new(1u64, 4u64)))
-                    .or_default()
-                    .push((*id, note_off));
+                section = arrangement.next_section(Some(s));
+                skipped += 1;
+            }
+            match section {
+                Some(s) if s < arrangement.sections.len() && length(s, start) > F::from(0) => {
+                    self.planned_sections.push_back(PlannedSection {
+                        section: s,
+                        start,
+                        end: start.add_fraction(length(s, start)),
+                    });
+                }
+                _ => self.song_end = Some(start),
             }
         }
+    }
+
+    /// Section and bar played at `beat_time` in song mode, if planned.
+    pub fn song_position(&self, beat_time: BeatTime) -> Option<SongPosition> {
+        let project_settings = self.project_settings.read().unwrap();
+        if !project_settings.arrangement.is_active() {
+            return None;
+        }
+        let planned = self
+            .planned_sections
+            .iter()
+            .find(|planned| planned.start <= beat_time && beat_time < planned.end)?;
+        Some(SongPosition {
+            section: planned.section,
+            bar: project_settings
+                .meter
+                .bars_between(planned.start, beat_time),
+        })
+    }
+
+    /// Whether the song has ended by `beat_time` in song mode.
+    pub fn song_ended(&self, beat_time: BeatTime) -> bool {
+        self.song_end.is_some_and(|end| beat_time >= end)
+    }
+
+    /// Schedules `beats` and returns all events before `next_beat_time`.
+    fn schedule_beats(
+        &mut self,
+        mut beats: Vec<TrackBeat>,
+        next_beat_time: BeatTime,
+    ) -> SortedEvents {
+        // Overlaps can only be resolved in chronological order
+        beats.sort_by_key(|(beat_time, ..)| *beat_time);
+        for (beat_time, id, beat, gate_length, controls) in beats {
+            // Notes of zero length would be ended before they start
+            if gate_length <= F::from(0) {
+                continue;
+            }
+            self.schedule_controls(id, beat_time, &controls);
+            self.schedule(id, beat_time, beat, gate_length);
+        }
         self.current_beat_time = next_beat_time;
-        return treemap.into_iter().collect();
+        let later = self.pending.split_off(&next_beat_time);
+        let mut due = std::mem::replace(&mut self.pending, later);
+        for events in due.values_mut() {
+            events.sort_by_key(|(_id, event)| event_order(event));
+        }
+        return due.into_iter().collect();
+    }
+
+    /// Schedules NoteOn and NoteOff of a beat.
+    /// If the same key is still sounding by then, its NoteOff is moved
+    /// forward to the new NoteOn, so that every NoteOn is matched by
+    /// exactly one NoteOff. Of several notes on the same key at the same
+    /// beat time, only the first one is played.
+    fn schedule(&mut self, id: SSId, beat_time: BeatTime, beat: Beat, gate_length: F) {
+        let [note_on, note_off] = beat_to_channel_voice_events(beat);
+        let key = (beat.channel, beat.note.into());
+        if let Some((note_on_time, note_off_time)) = self.sounding.get(&key).copied() {
+            if note_on_time == beat_time {
+                return;
+            }
+            if note_off_time > beat_time
+                && let Some(previous_note_off) = self.take_note_off(key, note_off_time)
+            {
+                self.pending
+                    .entry(beat_time)
+                    .or_default()
+                    .push(previous_note_off);
+            }
+        }
+        let note_off_time = beat_time.add_fraction(gate_length);
+        self.pending
+            .entry(beat_time)
+            .or_default()
+            .push((id, note_on));
+        self.pending
+            .entry(note_off_time)
+            .or_default()
+            .push((id, note_off));
+        self.sounding.insert(key, (beat_time, note_off_time));
+    }
+
+    /// Schedules Control Changes and Pitch Bend for the controller values of
+    /// a step that differ from the ones last sent. They are sorted before
+    /// the NoteOns at the same beat time, see `event_order`.
+    fn schedule_controls(&mut self, id: SSId, beat_time: BeatTime, controls: &Controls) {
+        let (channel, locks) = controls;
+        let channel = *channel;
+        for (controller, value) in locks.cc.iter() {
+            if self.controllers.get(&(channel, *controller)) == Some(value) {
+                continue;
+            }
+            self.controllers.insert((channel, *controller), *value);
+            self.pending.entry(beat_time).or_default().push((
+                id,
+                ChannelVoiceEvent::ControlChange {
+                    channel,
+                    controller: *controller,
+                    value: *value,
+                },
+            ));
+        }
+        if let Some(value) = locks.pitch_bend {
+            let previous = self
+                .pitch_bends
+                .get(&channel)
+                .copied()
+                .unwrap_or(PITCH_BEND_CENTER);
+            if previous != value {
+                self.pitch_bends.insert(channel, value);
+                self.pending
+                    .entry(beat_time)
+                    .or_default()
+                    .push((id, ChannelVoiceEvent::PitchBend { channel, value }));
+            }
+        }
+    }
+
+    /// Moves the NoteOffs of all notes of a track still sounding to the
+    /// current beat time.
+    fn release(&mut self, id: SSId) {
+        let mut note_offs = vec![];
+        self.pending.retain(|_, events| {
+            events.retain(|(event_id, event)| {
+                let is_note_off =
+                    *event_id == id && matches!(event, ChannelVoiceEvent::NoteOff { .. });
+                if is_note_off {
+                    note_offs.push((*event_id, *event));
+                }
+                !is_note_off
+            });
+            !events.is_empty()
+        });
+        for (_, event) in note_offs.iter() {
+            if let ChannelVoiceEvent::NoteOff { channel, key, .. } = event {
+                self.sounding.remove(&(*channel, *key));
+            }
+        }
+        if !note_offs.is_empty() {
+            self.pending
+                .entry(self.current_beat_time)
+                .or_default()
+                .extend(note_offs);
+        }
+    }
+
+    fn take_note_off(
+        &mut self,
+        key: (Channel, Key),
+        beat_time: BeatTime,
+    ) -> Option<(SSId, ChannelVoiceEvent)> {
+        let events = self.pending.get_mut(&beat_time)?;
+        let pos = events.iter().position(|(_, event)| {
+            matches!(event, ChannelVoiceEvent::NoteOff { channel, key: k, .. } if (*channel, *k) == key)
+        })?;
+        let note_off = events.remove(pos);
+        if events.is_empty() {
+            self.pending.remove(&beat_time);
+        }
+        Some(note_off)
+    }
+
+    /// Returns all held back events regardless of their beat time.
+    pub fn flush(&mut self) -> SortedEvents {
+        self.sounding.clear();
+        std::mem::take(&mut self.pending).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use indexmap::IndexMap;
+    use uuid::Uuid;
+
+    use crate::{
+        arpeggiator::{ArpOrder, Arpeggiator},
+        arrangement::{Arrangement, Section, SectionLength, SongPosition},
+        beatmaker::beat_time::BeatTime,
+        drum_track::{
+            Beat, ChordBeat, Condition, DrumTrack, DrumTrackBeat, Gate, PatternSlot, Ratchet,
+        },
+        groove::{GrooveStep, GrooveTemplate},
+        id::new_id,
+        meter::Meter,
+        midi::{
+            chord::Voicing,
+            note::{Note, PitchClass},
+            ChannelVoiceEvent, PITCH_BEND_CENTER,
+        },
+        project::{ProbabilityMode, ProjectSettings, TimeSignature, F},
+        scale::{Degree, Scale},
+    };
+
+    use super::BeatSorter;
+
+    fn beat_time(numer: u64, denom: u64) -> BeatTime {
+        BeatTime::new(F::new(numer, denom))
+    }
+
+    /// Advances through `end` in half beats and returns
+    /// (beat time, is NoteOn, key) of every event.
+    fn play(tracks: Vec<DrumTrack>, end: BeatTime) -> Vec<(BeatTime, bool, u8)> {
+        play_with_settings(tracks, ProjectSettings::default(), end)
+    }
+
+    fn play_with_settings(
+        tracks: Vec<DrumTrack>,
+        project_settings: ProjectSettings,
+        end: BeatTime,
+    ) -> Vec<(BeatTime, bool, u8)> {
+        // Fixed ids keep probability rolls the same from run to run
+        let tracks: IndexMap<_, _> = tracks
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| (Uuid::from_u128(i as u128), t))
+            .collect();
+        let mut beat_sorter = BeatSorter::new(
+            Arc::new(RwLock::new(tracks)),
+            Arc::new(RwLock::new(project_settings)),
+        );
+        let mut events = vec![];
+        let mut now = BeatTime::zero();
+        while now < end {
+            now = now.add_fraction(F::new(1u64, 2u64));
+            events.extend(beat_sorter.advance(now));
+            beat_sorter.apply_pattern_switches();
+        }
+        events.extend(beat_sorter.flush());
+        events
+            .into_iter()
+            .flat_map(|(beat_time, events)| {
+                events.into_iter().map(move |(_, event)| match event {
+                    ChannelVoiceEvent::NoteOn { key, .. } => (beat_time, true, key),
+                    ChannelVoiceEvent::NoteOff { key, .. } => (beat_time, false, key),
+                    event => panic!("Unexpected event {:?}", event),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gate_steps_and_beats() {
+        let mut steps = DrumTrack::with_beats_for_test(F::from(2), Note::C(1), 1);
+        steps.set_gate(Gate::Steps(F::new(1u64, 2u64)));
+        let mut beats = DrumTrack::with_beats_for_test(F::from(2), Note::D(1), 1);
+        beats.set_gate(Gate::Beats(F::new(3u64, 2u64)));
+        let events = play(vec![steps, beats], beat_time(1, 2));
+        assert_eq!(
+            events,
+            vec![
+                (BeatTime::zero(), true, 36),
+                (BeatTime::zero(), true, 38),
+                (beat_time(1, 4), false, 36),
+                (beat_time(3, 2), false, 38),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gate_override_on_beat() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 2);
+        track.assign_beat(
+            1,
+            DrumTrackBeat::OverrideBeat(vec![Beat {
+                gate: Some(Gate::Beats(F::new(1u64, 2u64))),
+                ..track.get_default_beat()
+            }]),
+        );
+        let events = play(vec![track], beat_time(2, 1));
+        assert!(events.contains(&(beat_time(1, 4), false, 36)));
+        assert!(events.contains(&(beat_time(3, 2), false, 36)));
+    }
+
+    #[test]
+    fn test_gate_legato() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 0);
+        track.assign_beat(0, DrumTrackBeat::DefaultBeat);
+        track.assign_beat(3, DrumTrackBeat::DefaultBeat);
+        track.resize(5);
+        track.set_gate(Gate::Legato);
+        let events = play(vec![track], beat_time(5, 1));
+        assert_eq!(
+            events,
+            vec![
+                (BeatTime::zero(), true, 36),
+                // Retriggered right when the previous note ends
+                (beat_time(3, 1), false, 36),
+                (beat_time(3, 1), true, 36),
+                // Held until the next loop
+                (beat_time(5, 1), false, 36),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlapping_notes_on_same_key() {
+        let mut long = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 3);
+        long.set_gate(Gate::Steps(F::from(3)));
+        // Another track on the same key, starting while the first note sounds
+        let mut short = DrumTrack::with_beats_for_test(F::from(2), Note::C(1), 0);
+        short.assign_beat(1, DrumTrackBeat::DefaultBeat);
+        short.resize(6);
+        let events = play(vec![long, short], beat_time(3, 1));
+        assert_eq!(
+            events,
+            vec![
+                (BeatTime::zero(), true, 36),
+                (beat_time(1, 2), false, 36),
+                (beat_time(1, 2), true, 36),
+                (beat_time(3, 4), false, 36),
+                (beat_time(1, 1), true, 36),
+                (beat_time(2, 1), false, 36),
+                (beat_time(2, 1), true, 36),
+                (beat_time(5, 1), false, 36),
+            ]
+        );
+    }
+
+    fn note_ons(events: &[(BeatTime, bool, u8)]) -> Vec<BeatTime> {
+        events
+            .iter()
+            .filter(|(_, note_on, _)| *note_on)
+            .map(|(beat_time, ..)| *beat_time)
+            .collect()
+    }
+
+    #[test]
+    fn test_swing() {
+        let project_settings = ProjectSettings {
+            swing: F::new(1u64, 3u64),
+            ..Default::default()
+        };
+        // Eighth notes, the second one swung to a triplet feel
+        let swung = DrumTrack::with_beats_for_test(F::from(2), Note::C(1), 4);
+        let mut straight = DrumTrack::with_beats_for_test(F::from(2), Note::D(1), 4);
+        straight.set_swing(Some(F::from(0)));
+        let events = play_with_settings(vec![swung, straight], project_settings, beat_time(2, 1));
+        let (swung, straight): (Vec<_>, Vec<_>) = events.into_iter().partition(|e| e.2 == 36);
+        assert_eq!(
+            note_ons(&swung),
+            vec![
+                BeatTime::zero(),
+                beat_time(2, 3),
+                beat_time(1, 1),
+                beat_time(5, 3)
+            ]
+        );
+        assert_eq!(
+            note_ons(&straight),
+            vec![
+                BeatTime::zero(),
+                beat_time(1, 2),
+                beat_time(1, 1),
+                beat_time(3, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_groove_template() {
+        let mut project_settings = ProjectSettings::default();
+        project_settings.grooves.insert(
+            "Push".to_string(),
+            GrooveTemplate {
+                steps: vec![
+                    GrooveStep {
+                        timing: -F::new(1u64, 4u64),
+                        velocity: 10,
+                    },
+                    GrooveStep::default(),
+                    GrooveStep {
+                        timing: F::new(1u64, 2u64),
+                        velocity: -10,
+                    },
+                ],
+            },
+        );
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.set_groove(Some("Push".to_string()));
+        let tracks: IndexMap<_, _> = [(new_id(), track)].into_iter().collect();
+        let mut beat_sorter = BeatSorter::new(
+            Arc::new(RwLock::new(tracks)),
+            Arc::new(RwLock::new(project_settings)),
+        );
+        let mut events = vec![];
+        let mut now = BeatTime::zero();
+        while now < beat_time(5, 1) {
+            now = now.add_fraction(F::new(1u64, 2u64));
+            for (beat_time, bucket) in beat_sorter.advance(now) {
+                for (_, event) in bucket {
+                    if let ChannelVoiceEvent::NoteOn { velocity, .. } = event {
+                        events.push((beat_time, velocity));
+                    }
+                }
+            }
+        }
+        // The first step of the timeline cannot be moved before zero
+        assert_eq!(
+            events,
+            vec![
+                (BeatTime::zero(), 82),
+                (beat_time(1, 1), 72),
+                (beat_time(5, 2), 62),
+                // Pushed into the range before its own step
+                (beat_time(11, 4), 82),
+                (beat_time(4, 1), 72),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_step_offsets_across_loop_boundary() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.set_offset(0, -F::new(1u64, 8u64));
+        track.set_offset(2, F::new(1u64, 8u64));
+        let events = play(vec![track], beat_time(8, 1));
+        assert_eq!(
+            note_ons(&events),
+            vec![
+                // The very first step cannot be early
+                BeatTime::zero(),
+                beat_time(1, 1),
+                beat_time(17, 8),
+                beat_time(3, 1),
+                // The first step of the second loop comes before the loop starts
+                beat_time(31, 8),
+                beat_time(5, 1),
+                beat_time(49, 8),
+                beat_time(7, 1),
+                beat_time(63, 8),
+            ]
+        );
+    }
+
+    /// Track of eight steps on every beat, where the first step never fires
+    /// and all others fire half of the time.
+    fn half_probability_track() -> DrumTrack {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 8);
+        track.set_probability(0, 0);
+        for idx in 1..8 {
+            track.set_probability(idx, 50);
+        }
+        track
+    }
+
+    /// Steps within the loop of the NoteOns of each of the first four loops
+    fn played_steps(seed: u64, probability_mode: ProbabilityMode) -> Vec<Vec<u64>> {
+        let project_settings = ProjectSettings {
+            seed,
+            probability_mode,
+            ..Default::default()
+        };
+        let events = play_with_settings(
+            vec![half_probability_track()],
+            project_settings,
+            beat_time(32, 1),
+        );
+        let mut loops = vec![vec![]; 4];
+        for beat_time in note_ons(&events) {
+            let step = *beat_time.as_fraction().numer().unwrap();
+            loops[step as usize / 8].push(step % 8);
+        }
+        loops
+    }
+
+    #[test]
+    fn test_probability() {
+        let loops = played_steps(0, ProbabilityMode::RerollEveryLoop);
+        assert!(loops.iter().all(|steps| !steps.contains(&0)));
+        let played: usize = loops.iter().map(Vec::len).sum();
+        assert!((7..=21).contains(&played), "{:?}", loops);
+        // Reproducible with the same seed, but not with another one
+        assert_eq!(played_steps(0, ProbabilityMode::RerollEveryLoop), loops);
+        assert_ne!(played_steps(1, ProbabilityMode::RerollEveryLoop), loops);
+        assert!(loops.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn test_probability_fixed_per_loop() {
+        let loops = played_steps(0, ProbabilityMode::FixedPerLoop);
+        assert!(!loops[0].contains(&0));
+        assert!(loops.iter().all(|steps| *steps == loops[0]));
+        assert_ne!(played_steps(1, ProbabilityMode::FixedPerLoop), loops);
+    }
+
+    #[test]
+    fn test_probability_does_not_depend_on_chunks() {
+        let project_settings = ProjectSettings {
+            seed: 42,
+            ..Default::default()
+        };
+        let tracks: IndexMap<_, _> = [(Uuid::from_u128(1), half_probability_track())]
+            .into_iter()
+            .collect();
+        let tracks = Arc::new(RwLock::new(tracks));
+        let project_settings = Arc::new(RwLock::new(project_settings));
+        let mut at_once = BeatSorter::new(tracks.clone(), project_settings.clone());
+        let mut at_once_events = at_once.advance(beat_time(16, 1));
+        at_once_events.extend(at_once.flush());
+        let mut chunked = BeatSorter::new(tracks, project_settings);
+        let mut chunked_events = vec![];
+        let mut now = BeatTime::zero();
+        while now < beat_time(16, 1) {
+            now = now.add_fraction(F::new(3u64, 10u64)).min(beat_time(16, 1));
+            chunked_events.extend(chunked.advance(now));
+        }
+        chunked_events.extend(chunked.flush());
+        assert_eq!(at_once_events, chunked_events);
+    }
+
+    #[test]
+    fn test_loop_conditions() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 2);
+        track.set_condition(0, Condition::Loop(1, 2));
+        track.set_condition(1, Condition::Loop(2, 3));
+        let events = play(vec![track], beat_time(12, 1));
+        assert_eq!(
+            note_ons(&events),
+            vec![
+                BeatTime::zero(),
+                beat_time(3, 1),
+                beat_time(4, 1),
+                beat_time(8, 1),
+                beat_time(9, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fill_and_pre_conditions() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.set_condition(0, Condition::Loop(1, 2));
+        track.set_condition(1, Condition::Pre);
+        track.set_condition(2, Condition::NotPre);
+        track.set_condition(3, Condition::Fill);
+        let events = play(vec![track.clone()], beat_time(8, 1));
+        assert_eq!(
+            note_ons(&events),
+            vec![BeatTime::zero(), beat_time(1, 1), beat_time(6, 1)]
+        );
+        let project_settings = ProjectSettings {
+            fill: true,
+            ..Default::default()
+        };
+        let events = play_with_settings(vec![track], project_settings, beat_time(8, 1));
+        assert_eq!(
+            note_ons(&events),
+            vec![
+                BeatTime::zero(),
+                beat_time(1, 1),
+                beat_time(3, 1),
+                beat_time(6, 1),
+                beat_time(7, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ratchet() {
+        let mut track = DrumTrack::with_beats_for_test(F::new(3u64, 2u64), Note::C(1), 2);
+        track.set_ratchet(
+            0,
+            Ratchet {
+                count: 3,
+                velocity_ramp: -40,
+            },
+        );
+        let events = play(vec![track.clone()], beat_time(1, 1));
+        // Three retriggers within the first step of 2/3 beats,
+        // each ended before the next one starts
+        assert_eq!(
+            events,
+            vec![
+                (BeatTime::zero(), true, 36),
+                (beat_time(2, 9), false, 36),
+                (beat_time(2, 9), true, 36),
+                (beat_time(4, 9), false, 36),
+                (beat_time(4, 9), true, 36),
+                (beat_time(2, 3), false, 36),
+                (beat_time(2, 3), true, 36),
+                (beat_time(11, 12), false, 36),
+            ]
+        );
+
+        let tracks: IndexMap<_, _> = [(new_id(), track)].into_iter().collect();
+        let mut beat_sorter = BeatSorter::new(
+            Arc::new(RwLock::new(tracks)),
+            Arc::new(RwLock::new(ProjectSettings::default())),
+        );
+        let velocities: Vec<_> = beat_sorter
+            .advance(beat_time(4, 3))
+            .into_iter()
+            .flat_map(|(_, bucket)| bucket)
+            .filter_map(|(_, event)| match event {
+                ChannelVoiceEvent::NoteOn { velocity, .. } => Some(velocity),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(velocities, vec![72, 52, 32, 72]);
+    }
+
+    #[test]
+    fn test_pattern_switch_at_loop_boundary() {
+        let a1 = PatternSlot::parse("A1").unwrap();
+        let a2 = PatternSlot::parse("A2").unwrap();
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.select_pattern(a2);
+        track.resize(3);
+        track.assign_beat(0, DrumTrackBeat::DefaultBeat);
+        track.assign_beat(1, DrumTrackBeat::DefaultBeat);
+        track.select_pattern(a1);
+        let id = new_id();
+        let tracks = Arc::new(RwLock::new([(id, track)].into_iter().collect()));
+        let mut beat_sorter = BeatSorter::new(
+            tracks.clone(),
+            Arc::new(RwLock::new(ProjectSettings::default())),
+        );
+        let mut events = vec![];
+        let mut now = BeatTime::zero();
+        while now < beat_time(8, 1) {
+            now = now.add_fraction(F::new(1u64, 2u64));
+            events.extend(beat_sorter.advance(now));
+            beat_sorter.apply_pattern_switches();
+            if now == beat_time(3, 2) {
+                tracks.write().unwrap()[&id].queue_pattern(a2);
+            }
+        }
+        let note_ons: Vec<_> = events
+            .into_iter()
+            .filter(|(_, bucket)| {
+                bucket
+                    .iter()
+                    .any(|(_, event)| matches!(event, ChannelVoiceEvent::NoteOn { .. }))
+            })
+            .map(|(beat_time, _)| beat_time.as_fraction())
+            .collect();
+        // A2 starts from its first step at the end of the loop of A1
+        let expected: Vec<_> = [0, 1, 2, 3, 4, 5, 7].into_iter().map(F::from).collect();
+        assert_eq!(note_ons, expected);
+        let tracks = tracks.read().unwrap();
+        assert_eq!(tracks[&id].get_pattern(), a2);
+        assert_eq!(tracks[&id].get_queued_pattern(), None);
+    }
+
+    /// A1 hits on all of 4 steps, A2 on the first of 2 steps
+    fn song_track() -> DrumTrack {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.select_pattern(PatternSlot::parse("A2").unwrap());
+        track.resize(2);
+        track.assign_beat(0, DrumTrackBeat::DefaultBeat);
+        track.select_pattern(PatternSlot::default());
+        track
+    }
+
+    fn song_settings(loop_section: Option<usize>) -> ProjectSettings {
+        let a1 = PatternSlot::default();
+        let a2 = PatternSlot::parse("A2").unwrap();
+        ProjectSettings {
+            arrangement: Arrangement {
+                sections: vec![
+                    Section::new("Intro", a1, SectionLength::Bars(1)),
+                    Section::new("Verse", a2, SectionLength::Loops(2)),
+                ],
+                enabled: true,
+                loop_section,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_song_sections() {
+        let events = play_with_settings(vec![song_track()], song_settings(None), beat_time(12, 1));
+        let expected: Vec<_> = [0, 1, 2, 3, 4, 6]
+            .into_iter()
+            .map(|t| beat_time(t, 1))
+            .collect();
+        assert_eq!(note_ons(&events), expected);
+    }
+
+    #[test]
+    fn test_song_loop_section() {
+        let events =
+            play_with_settings(vec![song_track()], song_settings(Some(1)), beat_time(12, 1));
+        let expected: Vec<_> = [0, 1, 2, 3, 4, 6, 8, 10]
+            .into_iter()
+            .map(|t| beat_time(t, 1))
+            .collect();
+        assert_eq!(note_ons(&events), expected);
+    }
+
+    #[test]
+    fn test_song_position_and_end() {
+        let id = new_id();
+        let tracks = Arc::new(RwLock::new([(id, song_track())].into_iter().collect()));
+        // Bars of 2 beats, i.e. an intro of 2 beats and a verse of 2 bars
+        let project_settings = ProjectSettings {
+            meter: Meter::new(TimeSignature {
+                numerator: 2,
+                denominator: 4,
+            }),
+            ..song_settings(None)
+        };
+        let mut beat_sorter =
+            BeatSorter::new(tracks.clone(), Arc::new(RwLock::new(project_settings)));
+        let mut positions = vec![];
+        let mut now = BeatTime::zero();
+        while now < beat_time(6, 1) {
+            now = now.add_fraction(F::new(1u64, 2u64));
+            beat_sorter.advance(now);
+            beat_sorter.apply_pattern_switches();
+            assert!(!beat_sorter.song_ended(now) || now == beat_time(6, 1));
+            if let Some(position) = beat_sorter.song_position(now)
+                && positions.last() != Some(&position)
+            {
+                positions.push(position);
+            }
+        }
+        assert!(beat_sorter.song_ended(now));
+        assert_eq!(
+            positions,
+            vec![
+                SongPosition { section: 0, bar: 0 },
+                SongPosition { section: 1, bar: 0 },
+                SongPosition { section: 1, bar: 1 },
+            ]
+        );
+        // The track keeps its active pattern while the song plays another
+        assert_eq!(
+            tracks.read().unwrap()[&id].get_pattern(),
+            PatternSlot::default()
+        );
+    }
+
+    #[test]
+    fn test_song_edited_after_end() {
+        let tracks = Arc::new(RwLock::new([(new_id(), song_track())].into_iter().collect()));
+        let project_settings = Arc::new(RwLock::new(song_settings(None)));
+        let mut beat_sorter = BeatSorter::new(tracks, project_settings.clone());
+        let mut events = vec![];
+        let mut now = BeatTime::zero();
+        while now < beat_time(10, 1) {
+            now = now.add_fraction(F::new(1u64, 2u64));
+            events.extend(beat_sorter.advance(now));
+        }
+        assert!(beat_sorter.song_ended(now));
+        // An outro from beat 8 on picks up where the song is now
+        project_settings.write().unwrap().arrangement.sections.push(Section::new(
+            "Outro",
+            PatternSlot::default(),
+            SectionLength::Bars(1),
+        ));
+        events.extend(beat_sorter.flush());
+        beat_sorter.reload(now);
+        assert!(!beat_sorter.song_ended(now));
+        while now < beat_time(14, 1) {
+            now = now.add_fraction(F::new(1u64, 2u64));
+            events.extend(beat_sorter.advance(now));
+        }
+        let note_ons: Vec<_> = events
+            .iter()
+            .filter(|(_, events)| {
+                events
+                    .iter()
+                    .any(|(_, event)| matches!(event, ChannelVoiceEvent::NoteOn { .. }))
+            })
+            .map(|(beat_time, _)| *beat_time)
+            .collect();
+        let expected: Vec<_> = [0, 1, 2, 3, 4, 6, 10, 11]
+            .into_iter()
+            .map(|t| beat_time(t, 1))
+            .collect();
+        assert_eq!(note_ons, expected);
+        assert!(beat_sorter.song_ended(now));
+    }
+
+    #[test]
+    fn test_mute_and_solo() {
+        let c1: u8 = Note::C(1).into();
+        let d1: u8 = Note::D(1).into();
+        let e1: u8 = Note::E(1).into();
+        let tracks = || {
+            [Note::C(1), Note::D(1), Note::E(1)]
+                .into_iter()
+                .map(|note| DrumTrack::with_beats_for_test(F::from(1), note, 2))
+                .collect::<Vec<_>>()
+        };
+        let played_keys = |tracks: Vec<DrumTrack>| {
+            let mut keys: Vec<_> = play(tracks, beat_time(2, 1))
+                .into_iter()
+                .filter(|(_, note_on, _)| *note_on)
+                .map(|(_, _, key)| key)
+                .collect();
+            keys.sort();
+            keys.dedup();
+            keys
+        };
+        let mut muted = tracks();
+        muted[1].set_muted(true);
+        assert_eq!(played_keys(muted), vec![c1, e1]);
+        let mut soloed = tracks();
+        soloed[1].set_soloed(true);
+        soloed[2].set_soloed(true);
+        assert_eq!(played_keys(soloed), vec![d1, e1]);
+        // Mute wins over solo
+        let mut soloed = tracks();
+        soloed[1].set_soloed(true);
+        soloed[1].set_muted(true);
+        assert!(played_keys(soloed).is_empty());
+    }
+
+    #[test]
+    fn test_mute_ends_sounding_notes() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.set_gate(Gate::Beats(F::from(2)));
+        let id = new_id();
+        let tracks = Arc::new(RwLock::new([(id, track)].into_iter().collect()));
+        let mut beat_sorter = BeatSorter::new(
+            tracks.clone(),
+            Arc::new(RwLock::new(ProjectSettings::default())),
+        );
+        let mut events = vec![];
+        let mut now = BeatTime::zero();
+        while now < beat_time(4, 1) {
+            if now == beat_time(3, 2) {
+                tracks.write().unwrap()[&id].set_muted(true);
+            }
+            now = now.add_fraction(F::new(1u64, 2u64));
+            events.extend(beat_sorter.advance(now));
+        }
+        assert!(beat_sorter.flush().is_empty());
+        let events: Vec<_> = events
+            .into_iter()
+            .flat_map(|(beat_time, events)| {
+                events.into_iter().map(move |(_, event)| match event {
+                    ChannelVoiceEvent::NoteOn { .. } => (beat_time, true),
+                    ChannelVoiceEvent::NoteOff { .. } => (beat_time, false),
+                    event => panic!("Unexpected event {:?}", event),
+                })
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (beat_time(0, 1), true),
+                (beat_time(1, 1), false),
+                (beat_time(1, 1), true),
+                (beat_time(3, 2), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_change_transposes_degrees() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 2);
+        let degree = |degree| DrumTrackBeat::Degree(Degree { degree, octave: 3 });
+        track.assign_beat(0, degree(1));
+        track.assign_beat(1, degree(3));
+        let tracks = Arc::new(RwLock::new([(new_id(), track)].into_iter().collect()));
+        let project_settings = Arc::new(RwLock::new(ProjectSettings::default()));
+        let mut beat_sorter = BeatSorter::new(tracks, project_settings.clone());
+        let mut keys = vec![];
+        let mut now = BeatTime::zero();
+        while now < beat_time(4, 1) {
+            if now == beat_time(2, 1) {
+                let mut project_settings = project_settings.write().unwrap();
+                project_settings.tonality.root = PitchClass::D;
+                project_settings.tonality.scale = Scale::Minor;
+            }
+            now = now.add_fraction(F::new(1u64, 2u64));
+            for (_, events) in beat_sorter.advance(now) {
+                for (_, event) in events {
+                    if let ChannelVoiceEvent::NoteOn { key, .. } = event {
+                        keys.push(Note::from(key));
+                    }
+                }
+            }
+        }
+        assert_eq!(keys, vec![Note::C(3), Note::E(3), Note::D(3), Note::F(3)]);
+    }
+
+    fn arpeggiated_notes(events: &[(BeatTime, bool, u8)]) -> Vec<(BeatTime, Note)> {
+        events
+            .iter()
+            .filter(|(_, note_on, _)| *note_on)
+            .map(|(beat_time, _, key)| (*beat_time, Note::from(*key)))
+            .collect()
+    }
+
+    #[test]
+    fn test_arpeggiate_step_chord() {
+        // 2 steps a beat, the first one holding a chord for a beat
+        let mut track = DrumTrack::with_beats_for_test(F::from(2), Note::C(1), 2);
+        let chord = [Note::E(3), Note::C(3), Note::G(3)]
+            .into_iter()
+            .map(|note| Beat {
+                channel: 1,
+                note,
+                velocity: 100,
+                gate: Some(Gate::Beats(F::from(1))),
+            })
+            .collect();
+        track.assign_beat(0, DrumTrackBeat::OverrideBeat(chord));
+        track.remove_beat(1);
+        track.set_arpeggiator(Some(Arpeggiator {
+            order: ArpOrder::Up,
+            octaves: 1,
+            rate: F::new(1u64, 4u64),
+            gate: F::new(1u64, 2u64),
+        }));
+        let events = play(vec![track], beat_time(1, 1));
+        // A quarter of a step of the track is an eighth of a beat
+        let notes = [Note::C(3), Note::E(3), Note::G(3)];
+        let expected: Vec<_> = (0..8)
+            .map(|i| (beat_time(i, 8), notes[i as usize % 3]))
+            .collect();
+        assert_eq!(arpeggiated_notes(&events), expected);
+        let note_offs: Vec<_> = events.iter().filter(|(_, note_on, _)| !note_on).collect();
+        assert_eq!(note_offs.len(), 8);
+        assert_eq!(note_offs[0].0, beat_time(1, 16));
+    }
+
+    #[test]
+    fn test_arpeggiate_held_notes() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.set_arpeggiator(Some(Arpeggiator {
+            order: ArpOrder::Down,
+            octaves: 2,
+            rate: F::new(1u64, 2u64),
+            gate: F::new(1u64, 2u64),
+        }));
+        track.hold_notes(vec![Note::C(3), Note::G(3)]);
+        let events = play(vec![track], beat_time(3, 1));
+        let expected: Vec<_> = [Note::G(4), Note::C(4), Note::G(3), Note::C(3)]
+            .into_iter()
+            .cycle()
+            .take(6)
+            .enumerate()
+            .map(|(i, note)| (beat_time(i as u64, 2), note))
+            .collect();
+        assert_eq!(arpeggiated_notes(&events), expected);
+    }
+
+    #[test]
+    fn test_strum_chord() {
+        let strummed = |strum: F| {
+            let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 2);
+            track.assign_beat(
+                0,
+                DrumTrackBeat::Chord(ChordBeat {
+                    chord: "C".parse().unwrap(),
+                    octave: 3,
+                    voicing: Voicing::Close,
+                }),
+            );
+            track.remove_beat(1);
+            track.set_strum(0, strum);
+            arpeggiated_notes(&play(vec![track], beat_time(2, 1)))
+        };
+        assert_eq!(
+            strummed(F::new(1u64, 4u64)),
+            vec![
+                (beat_time(0, 4), Note::C(3)),
+                (beat_time(1, 4), Note::E(3)),
+                (beat_time(2, 4), Note::G(3)),
+            ]
+        );
+        assert_eq!(
+            strummed(-F::new(1u64, 4u64)),
+            vec![
+                (beat_time(0, 4), Note::G(3)),
+                (beat_time(1, 4), Note::E(3)),
+                (beat_time(2, 4), Note::C(3)),
+            ]
+        );
+        // All notes start within the step
+        assert_eq!(
+            strummed(F::from(1)),
+            vec![
+                (beat_time(0, 2), Note::C(3)),
+                (beat_time(1, 2), Note::E(3)),
+                (beat_time(2, 2), Note::G(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_locks() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        let Beat {
+            channel, velocity, ..
+        } = track.get_default_beat();
+        let key = Note::C(1).into();
+        track.set_cc_default(74, Some(64));
+        track.set_cc_lock(1, 74, Some(100));
+        track.set_cc_lock(1, 1, Some(20));
+        track.set_pitch_bend_lock(1, Some(10000));
+        track.set_cc_lock(3, 1, Some(20));
+        let tracks = [(new_id(), track)].into_iter().collect();
+        let mut beat_sorter = BeatSorter::new(
+            Arc::new(RwLock::new(tracks)),
+            Arc::new(RwLock::new(ProjectSettings::default())),
+        );
+        let events: Vec<_> = beat_sorter
+            .advance(beat_time(4, 1))
+            .into_iter()
+            .flat_map(|(beat_time, events)| {
+                events.into_iter().map(move |(_, event)| (beat_time, event))
+            })
+            .filter(|(_, event)| !matches!(event, ChannelVoiceEvent::NoteOff { .. }))
+            .collect();
+        let cc = |controller, value| ChannelVoiceEvent::ControlChange {
+            channel,
+            controller,
+            value,
+        };
+        let note_on = ChannelVoiceEvent::NoteOn {
+            channel,
+            key,
+            velocity,
+        };
+        assert_eq!(
+            events,
+            vec![
+                (beat_time(0, 1), cc(74, 64)),
+                (beat_time(0, 1), note_on),
+                (beat_time(1, 1), cc(1, 20)),
+                (beat_time(1, 1), cc(74, 100)),
+                (
+                    beat_time(1, 1),
+                    ChannelVoiceEvent::PitchBend {
+                        channel,
+                        value: 10000
+                    }
+                ),
+                (beat_time(1, 1), note_on),
+                // Defaults are restored, unlocked controllers left alone
+                (beat_time(2, 1), cc(74, 64)),
+                (
+                    beat_time(2, 1),
+                    ChannelVoiceEvent::PitchBend {
+                        channel,
+                        value: PITCH_BEND_CENTER
+                    }
+                ),
+                (beat_time(2, 1), note_on),
+                // Values already sent are not repeated
+                (beat_time(3, 1), note_on),
+            ]
+        );
     }
 }
@@ -19,6 +19,10 @@ impl BeatTime {
         Default::default()
     }
 
+    pub fn as_fraction(&self) -> F {
+        self.frac
+    }
+
     pub fn integral(&self) -> usize {
         usize::try_from(self.frac.trunc()).unwrap() as usize
     }
@@ -1,10 +1,14 @@
 pub mod beat_sorter;
 pub mod beat_time;
 pub mod pattern;
+pub mod renderer;
 
-use std::thread;
+use std::{
+    sync::{Arc, RwLock},
+    thread,
+};
 
-use beat_sorter::BeatSorter;
+use beat_sorter::{BeatSorter, SortedEvents};
 use beat_time::BeatTime;
 use crossbeam::{
     channel::{bounded, Receiver, Sender},
@@ -13,10 +17,17 @@ use crossbeam::{
 use log::{debug, info};
 
 use crate::{
+    arrangement::SongPosition,
+    clock::ClockSource,
     consts,
+    engine::scheduler::Timestamp,
+    meter::Position,
     midi::ChannelVoiceEvent,
-    models::channel_subscription::{ChannelEventSubscription, ChannelEventSubscriptionModel},
-    project::{Project, F},
+    models::channel_subscription::{
+        ChannelEventSubscriberMap, ChannelEventSubscription, ChannelEventSubscriptionModel,
+    },
+    project::Project,
+    tempo::TempoMap,
     timeline::{TimelineEvent, TimelineSubscription},
 };
 
@@ -26,17 +37,62 @@ enum InternalSignal {
 
 #[derive(Clone, Debug, PartialEq, serde::Serialize)]
 pub enum BeatMakerEvent {
-    Pause,                        // Timeline pause
-    Stop,                         // Timeline reset
-    Tick(u64),                    // Passthru of timeline ticks
-    Step(u64),                    // Global steps elapsed
-    Beat(BeatTime),               // Track beat
-    MIDIEvent(ChannelVoiceEvent), // MIDI event to be processed by audio server
+    Pause,                                   // Timeline pause
+    Stop,                                    // Timeline reset
+    Tick(u64),                               // Timeline tick, sent after its events
+    Step(u64),                               // Global steps elapsed
+    Beat(BeatTime),                          // Track beat
+    Position(Position),                      // Bar and beat entered, ticks left at zero
+    MIDIEvent(ChannelVoiceEvent, Timestamp), // MIDI event to be played by audio server at the time
+    SongPosition(SongPosition),              // Section and bar entered in song mode
+    SongEnd,                                 // End of the song reached in song mode
 }
 
 pub type BeatMakerSubscriptionModel = ChannelEventSubscriptionModel<BeatMakerEvent>;
 pub type BeatMakerSubscription = ChannelEventSubscription<BeatMakerEvent>;
 
+/// Sends out events to be played right away, at `now`, regardless of their
+/// beat time, e.g. NoteOffs held back by BeatSorter when playback stops.
+fn send_midi_events(
+    subscriber_map: &Arc<RwLock<ChannelEventSubscriberMap<BeatMakerEvent>>>,
+    events: SortedEvents,
+    now: Timestamp,
+) {
+    for (_beat_time, events) in events {
+        for (_id, event) in events {
+            let _ = BeatMakerSubscriptionModel::send_all(
+                subscriber_map,
+                BeatMakerEvent::MIDIEvent(event, now),
+            );
+        }
+    }
+}
+
+/// Sends out the events held back by `beat_sorter` and rebuilds it from the
+/// tracks, carrying on from `current_beat_time` rather than from the start.
+/// Patterns switched to keep playing in phase.
+fn reset_sorter(
+    subscriber_map: &Arc<RwLock<ChannelEventSubscriberMap<BeatMakerEvent>>>,
+    beat_sorter: &mut BeatSorter,
+    current_beat_time: BeatTime,
+    now: Timestamp,
+) {
+    send_midi_events(subscriber_map, beat_sorter.flush(), now);
+    beat_sorter.reload(current_beat_time);
+}
+
+/// Time at which to play an event at `beat_time` of the range of beat time
+/// starting at `range_start`, which the timeline has just passed at `tick_time`.
+/// Events are played a tick late so that none of the range lies in the past.
+fn event_timestamp(
+    tick_time: Timestamp,
+    range_start: BeatTime,
+    beat_time: BeatTime,
+    tempo_map: &TempoMap,
+) -> Timestamp {
+    tick_time + tempo_map.duration(range_start, beat_time).as_micros() as Timestamp
+}
+
 /// BeatMaker sends walks along the timeline and send out beats of every track
 /// as MIDI notes.
 /// Internally, it maintains a search tree of the next upcoming notes of each track.
@@ -54,21 +110,39 @@ impl BeatMaker {
         self.subscription_model.subscribe()
     }
 
-    pub fn start(&self, project: &Project, timeline_subscription: TimelineSubscription) {
+    /// Plays the project along the ticks of the timeline. MIDI events are
+    /// stamped with times on `clock`, the clock source of the timeline.
+    pub fn start(
+        &self,
+        project: &Project,
+        timeline_subscription: TimelineSubscription,
+        clock: Arc<dyn ClockSource>,
+    ) {
         let project_settings = project.project_settings();
         let tracks = project.tracks();
         let internal_signal_receiver = self.internal_signal.1.clone();
         let subscriber_map = self.subscription_model.subscriber_map().clone();
         thread::spawn(move || {
             info!("BeatMaker started");
-            let mut beat_sorter = BeatSorter::with_tracks(tracks);
+            let mut beat_sorter = BeatSorter::new(tracks, project_settings.clone());
             let mut current_beat_time = BeatTime::zero();
+            let mut song_position = None;
+            let mut song_ended = false;
+            let mut position: Option<Position> = None;
+            // Tick and time of the first tick since the timeline started,
+            // from which the times of later ticks are derived
+            let mut tick_anchor: Option<(u64, Timestamp)> = None;
+            // Last tick played, from which the beat time moves on by the
+            // time elapsed since along the tempo map
+            let mut last_tick: Option<u64> = None;
             loop {
                 select! {
                     recv(internal_signal_receiver) -> signal => {
                         match signal {
                             Ok(InternalSignal::ResetSorter) => {
-                                beat_sorter.reset();
+                                reset_sorter(&subscriber_map, &mut beat_sorter, current_beat_time, clock.now());
+                                song_position = None;
+                                song_ended = false;
                             }
                             Err(err) => {
                                 info!("Internal signal sender: {}. Exiting BeatMaker thread.", err);
@@ -80,29 +154,61 @@ impl BeatMaker {
                     recv(timeline_subscription.receiver) -> tick => {
                         match tick.unwrap() {
                             TimelineEvent::Tick(tick) => {
-                                BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Tick(tick));
-                                let tempo = {
-                                    let project_settings = project_settings.read().unwrap();
-                                    *project_settings.current_beat_time.write().unwrap() = current_beat_time;
-                                    project_settings.tempo
-                                };
-
-                                let beat_time_per_tick =
-                                    F::new(tempo as u64 * consts::TIMELINE_TICK_DURATION.as_millis() as u64, 60_000u64);
-                                let beat_time = BeatTime::new(beat_time_per_tick * tick);
+                                // Changes made before the tick apply to it
+                                while let Ok(InternalSignal::ResetSorter) = internal_signal_receiver.try_recv() {
+                                    reset_sorter(&subscriber_map, &mut beat_sorter, current_beat_time, clock.now());
+                                    song_position = None;
+                                    song_ended = false;
+                                }
+                                let (anchor_tick, anchor_time) = *tick_anchor.get_or_insert((tick, clock.now()));
+                                let tick_time = anchor_time
+                                    + tick.saturating_sub(anchor_tick) * consts::TIMELINE_TICK_DURATION.as_micros() as Timestamp;
+                                let tempo_map = project_settings.read().unwrap().tempo_map.clone();
+                                let elapsed = tick.saturating_sub(last_tick.unwrap_or(0)) as u32;
+                                let beat_time = tempo_map.advance(current_beat_time, consts::TIMELINE_TICK_DURATION * elapsed);
+                                last_tick = Some(tick);
                                 let beats = beat_sorter.advance(beat_time);
-                                for (_beat_time, beats) in beats.iter() {
-                                    BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Beat(beat_time));
+                                beat_sorter.apply_pattern_switches();
+                                for (event_beat_time, beats) in beats.iter() {
+                                    let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Beat(beat_time));
+                                    let timestamp = event_timestamp(tick_time, current_beat_time, *event_beat_time, &tempo_map);
                                     for (_id, beat) in beats {
-                                        BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::MIDIEvent(*beat));
+                                        let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::MIDIEvent(*beat, timestamp));
                                     }
 
                                 }
 
                                 current_beat_time = beat_time;
+                                *project_settings.read().unwrap().current_beat_time.write().unwrap() = current_beat_time;
+
+                                let beat_position = Position {
+                                    tick: 0,
+                                    ..project_settings.read().unwrap().meter.position(beat_time)
+                                };
+                                if position != Some(beat_position) {
+                                    position = Some(beat_position);
+                                    let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Position(beat_position));
+                                }
+
+                                let position = beat_sorter.song_position(beat_time);
+                                *project_settings.read().unwrap().song_position.write().unwrap() = position;
+                                if position != song_position {
+                                    song_position = position;
+                                    if let Some(position) = position {
+                                        info!("Song position: section {}, bar {}", position.section + 1, position.bar + 1);
+                                        let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::SongPosition(position));
+                                    }
+                                }
+                                if !song_ended && beat_sorter.song_ended(beat_time) {
+                                    song_ended = true;
+                                    info!("Song ended");
+                                    let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::SongEnd);
+                                }
+                                let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Tick(tick));
                             }
                             TimelineEvent::Pause => {
-                                BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Pause);
+                                tick_anchor = None;
+                                let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Pause);
                             }
                             TimelineEvent::Stop => {
                                 *project_settings
@@ -111,9 +217,16 @@ impl BeatMaker {
                                     .current_beat_time
                                     .write()
                                     .unwrap() = BeatTime::zero();
+                                *project_settings.read().unwrap().song_position.write().unwrap() = None;
                                 current_beat_time = BeatTime::zero();
+                                send_midi_events(&subscriber_map, beat_sorter.flush(), clock.now());
                                 beat_sorter.reset();
-                                BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Stop);
+                                song_position = None;
+                                song_ended = false;
+                                position = None;
+                                tick_anchor = None;
+                                last_tick = None;
+                                let _ = BeatMakerSubscriptionModel::send_all(&subscriber_map, BeatMakerEvent::Stop);
                             }
                         }
                     }
@@ -16,6 +16,7 @@ macro_rules! beat {
             channel: $channel,
             note: $note,
             velocity: $velocity,
+            gate: None,
         }
     };
 }
@@ -0,0 +1,191 @@
+//! Offline rendering of a project into MIDI events.
+//!
+//! The renderer drives the same `BeatSorter` as `BeatMaker`, only without
+//! a `Timeline`: instead of advancing tick by tick, it advances to the end of
+//! the requested range at once. Since `BeatSorter::advance` returns the same
+//! events no matter how a range is split up, the result matches what is
+//! played back in real time.
+
+use crate::{
+    id::SSId,
+    midi::ChannelVoiceEvent,
+    project::{Project, F},
+};
+
+use super::{beat_sorter::BeatSorter, beat_time::BeatTime};
+
+/// Renders all events of notes starting from `start` (inclusive) to `end`
+/// (exclusive), keeping the id of the track each event comes from.
+/// Notes starting in the range are always ended, so their NoteOffs may lie
+/// beyond `end`.
+pub fn render_track_events(
+    project: &Project,
+    start: BeatTime,
+    end: BeatTime,
+) -> Vec<(BeatTime, SSId, ChannelVoiceEvent)> {
+    let mut beat_sorter = BeatSorter::new(project.tracks(), project.project_settings());
+    beat_sorter.jump(start);
+    let mut events = beat_sorter.advance(end);
+    events.extend(beat_sorter.flush());
+    events
+        .into_iter()
+        .flat_map(|(beat_time, events)| {
+            events
+                .into_iter()
+                .map(move |(id, event)| (beat_time, id, event))
+        })
+        .collect()
+}
+
+/// Renders all events of notes starting from `start` (inclusive) to `end`
+/// (exclusive). See `render_track_events`.
+pub fn render_range(
+    project: &Project,
+    start: BeatTime,
+    end: BeatTime,
+) -> Vec<(BeatTime, ChannelVoiceEvent)> {
+    render_track_events(project, start, end)
+        .into_iter()
+        .map(|(beat_time, _id, event)| (beat_time, event))
+        .collect()
+}
+
+/// Renders `loops` full loops of the project (see `Project::loop_length`)
+/// from the start.
+pub fn render_loops(project: &Project, loops: usize) -> Vec<(BeatTime, ChannelVoiceEvent)> {
+    let end = BeatTime::new(project.loop_length().as_fraction() * F::from(loops));
+    render_range(project, BeatTime::zero(), end)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        arrangement::{Section, SectionLength},
+        beatmaker::{beat_sorter::BeatSorter, beat_time::BeatTime},
+        drum_track::{DrumTrack, PatternSlot},
+        midi::{note::Note, ChannelVoiceEvent},
+        project::{Project, F},
+    };
+
+    use super::{render_loops, render_range};
+
+    fn test_project() -> Project {
+        let project = Project::new();
+        project.add_track(DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 2));
+        project.add_track(DrumTrack::with_beats_for_test(
+            F::new(3u64, 2u64),
+            Note::D(1),
+            3,
+        ));
+        project
+    }
+
+    fn beat_time(numer: u64, denom: u64) -> BeatTime {
+        BeatTime::new(F::new(numer, denom))
+    }
+
+    #[test]
+    fn test_render_range() {
+        let project = test_project();
+        let events = render_range(&project, BeatTime::zero(), beat_time(1, 1));
+        let note_ons: Vec<_> = events
+            .iter()
+            .filter(|(_, event)| matches!(event, ChannelVoiceEvent::NoteOn { .. }))
+            .map(|(beat_time, _)| *beat_time)
+            .collect();
+        assert_eq!(
+            note_ons,
+            vec![BeatTime::zero(), BeatTime::zero(), beat_time(2, 3)]
+        );
+        // The NoteOff of the note at 2/3 comes after the end of the range
+        assert_eq!(events.last().unwrap().0, beat_time(11, 12));
+        assert!(events.is_sorted_by_key(|(beat_time, _)| *beat_time));
+    }
+
+    #[test]
+    fn test_render_range_from_offset() {
+        let project = test_project();
+        let events = render_range(&project, beat_time(1, 1), beat_time(2, 1));
+        assert_eq!(events.first().unwrap().0, beat_time(1, 1));
+        assert_eq!(events.len(), 4);
+    }
+
+    #[test]
+    fn test_render_leaves_queued_pattern() {
+        let project = test_project();
+        let b1 = PatternSlot::parse("B1").unwrap();
+        project
+            .tracks()
+            .write()
+            .unwrap()
+            .values_mut()
+            .for_each(|track| track.queue_pattern(b1));
+        render_loops(&project, 2);
+        let tracks = project.tracks();
+        let tracks = tracks.read().unwrap();
+        for track in tracks.values() {
+            assert_eq!(track.get_pattern(), PatternSlot::default());
+            assert_eq!(track.get_queued_pattern(), Some(b1));
+        }
+    }
+
+    #[test]
+    fn test_render_song_leaves_patterns() {
+        let project = test_project();
+        let b1 = PatternSlot::parse("B1").unwrap();
+        {
+            let project_settings = project.project_settings();
+            let arrangement = &mut project_settings.write().unwrap().arrangement;
+            arrangement.sections = vec![
+                Section::new("Verse", PatternSlot::default(), SectionLength::Bars(1)),
+                Section::new("Chorus", b1, SectionLength::Bars(1)),
+            ];
+            arrangement.enabled = true;
+        }
+        render_range(&project, BeatTime::zero(), beat_time(8, 1));
+        let tracks = project.tracks();
+        let tracks = tracks.read().unwrap();
+        assert!(tracks
+            .values()
+            .all(|track| track.get_pattern() == PatternSlot::default()));
+    }
+
+    #[test]
+    fn test_render_matches_playback() {
+        let project = test_project();
+        let end = beat_time(6, 1);
+        // Advance in steps of an odd size like the timeline does
+        let mut beat_sorter = BeatSorter::new(project.tracks(), project.project_settings());
+        let mut played = vec![];
+        let mut now = BeatTime::zero();
+        while now < end {
+            now = now.add_fraction(F::new(7u64, 100u64)).min(end);
+            played.extend(beat_sorter.advance(now));
+        }
+        played.extend(beat_sorter.flush());
+        let played: Vec<_> = played
+            .into_iter()
+            .flat_map(|(beat_time, events)| {
+                events.into_iter().map(move |(_, event)| (beat_time, event))
+            })
+            .collect();
+        assert_eq!(render_range(&project, BeatTime::zero(), end), played);
+    }
+
+    #[test]
+    fn test_render_loops() {
+        let project = test_project();
+        let once = render_loops(&project, 1);
+        let twice = render_loops(&project, 2);
+        assert_eq!(once.len(), 10);
+        assert_eq!(twice.len(), 20);
+        let loop_length = project.loop_length().as_fraction();
+        for ((first, first_event), (second, second_event)) in
+            twice.iter().zip(twice.iter().skip(once.len()))
+        {
+            assert_eq!(second.as_fraction() - first.as_fraction(), loop_length);
+            assert_eq!(first_event, second_event);
+        }
+        assert!(render_loops(&project, 0).is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+//! Clock sources driving the ticks of the `Timeline`.
+//!
+//! The timeline sends a tick whenever its clock source reports that one has
+//! elapsed. The clock source decides what time is: the wall clock, the
+//! frames processed by the audio device, or whatever a test says it is.
+//! `BeatMaker` stamps MIDI events with times on the same clock, so that an
+//! audio engine driving the timeline can play them in phase with it.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Condvar, Mutex,
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    consts,
+    engine::scheduler::{self, Frames, Timestamp},
+};
+
+pub trait ClockSource: Send + Sync {
+    /// Called when the timeline starts or resumes. The first tick is sent
+    /// right away, the next one after a tick has elapsed from now on.
+    fn start(&self);
+
+    /// Blocks until at least a tick has elapsed since the last call and
+    /// returns the number of ticks elapsed, or returns 0 once interrupted.
+    fn wait(&self) -> u64;
+
+    /// Makes a blocked `wait` return, e.g. when the timeline pauses.
+    fn interrupt(&self);
+
+    /// Current time on this clock. Unless the clock keeps time on its own,
+    /// this is `scheduler::now`.
+    fn now(&self) -> Timestamp {
+        scheduler::now()
+    }
+}
+
+/// Ticks along with the wall clock. Ticks are skipped when the timeline
+/// falls behind.
+pub struct WallClock {
+    interval: Duration,
+    next_tick_time: Mutex<Instant>,
+}
+
+impl WallClock {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_tick_time: Mutex::new(Instant::now() + interval),
+        }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new(consts::TIMELINE_TICK_DURATION)
+    }
+}
+
+impl ClockSource for WallClock {
+    fn start(&self) {
+        *self.next_tick_time.lock().unwrap() = Instant::now() + self.interval;
+    }
+
+    fn wait(&self) -> u64 {
+        let mut next_tick_time = self.next_tick_time.lock().unwrap();
+        let now = Instant::now();
+        if now < *next_tick_time {
+            thread::sleep(*next_tick_time - now);
+        }
+        let mut elapsed = 1;
+        *next_tick_time += self.interval;
+        while Instant::now() > *next_tick_time {
+            elapsed += 1;
+            *next_tick_time += self.interval;
+        }
+        elapsed
+    }
+
+    // Waits for a tick at most, so there is nothing to interrupt
+    fn interrupt(&self) {}
+}
+
+/// Ticks along with the frames processed by the audio device, so that the
+/// timeline stays in phase with it. The audio callback reports the frames
+/// of every cycle with `advance`, which never blocks, as it runs on the
+/// real-time thread. It wakes the thread parked in `wait` if it can do so
+/// without waiting for a lock. Every tick elapsed is reported, as the
+/// timeline can catch up with a cycle spanning several ticks right away.
+/// Its time is the time of the frames processed since it started, which
+/// `frame_at` maps back to the frames of the audio device.
+pub struct FrameClock {
+    interval: Duration,
+    /// Frame of the audio device at which the clock started counting
+    origin: AtomicU32,
+    /// Frames processed since the clock started
+    frames: AtomicU64,
+    sample_rate: AtomicU64,
+    /// Ticks reported by `wait` since the clock started
+    ticks: AtomicU64,
+    interrupted: AtomicBool,
+    /// Thread parked in `wait`, if any
+    waiter: Mutex<Option<Thread>>,
+}
+
+impl FrameClock {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            origin: AtomicU32::new(0),
+            frames: AtomicU64::new(0),
+            sample_rate: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            interrupted: AtomicBool::new(false),
+            waiter: Mutex::new(None),
+        }
+    }
+
+    /// Reports a cycle of `n_frames` frames starting at frame `cycle_start`
+    /// of the audio device, processed at `sample_rate`.
+    pub fn advance(&self, cycle_start: Frames, n_frames: Frames, sample_rate: usize) {
+        let sample_rate = sample_rate as u64;
+        let previous_rate = self.sample_rate.swap(sample_rate, Ordering::Relaxed);
+        if previous_rate != sample_rate {
+            // Keep the time elapsed so far at the new sample rate
+            if let Some(frames) =
+                (self.frames.load(Ordering::Relaxed) * sample_rate).checked_div(previous_rate)
+            {
+                self.frames.store(frames, Ordering::Relaxed);
+            }
+        }
+        if self.frames.fetch_add(n_frames as u64, Ordering::Release) == 0 {
+            self.origin.store(cycle_start, Ordering::Release);
+        }
+        // The waiter checks the frames after registering, so a wake-up
+        // skipped while it holds the lock is not lost
+        if let Ok(waiter) = self.waiter.try_lock()
+            && let Some(thread) = waiter.as_ref()
+        {
+            thread.unpark();
+        }
+    }
+
+    /// Frame of the audio device at `timestamp` on this clock.
+    pub fn frame_at(&self, timestamp: Timestamp) -> Frames {
+        let frames = timestamp * self.sample_rate.load(Ordering::Relaxed) / 1_000_000;
+        // Frames of the audio device wrap around
+        self.origin.load(Ordering::Acquire).wrapping_add(frames as Frames)
+    }
+
+    /// Ticks elapsed since the clock started
+    fn elapsed_ticks(&self) -> u64 {
+        self.now() / self.interval.as_micros().max(1) as u64
+    }
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new(consts::TIMELINE_TICK_DURATION)
+    }
+}
+
+impl ClockSource for FrameClock {
+    fn start(&self) {
+        self.frames.store(0, Ordering::Relaxed);
+        self.ticks.store(0, Ordering::Relaxed);
+        self.interrupted.store(false, Ordering::Relaxed);
+    }
+
+    fn wait(&self) -> u64 {
+        *self.waiter.lock().unwrap() = Some(thread::current());
+        let elapsed = loop {
+            if self.interrupted.swap(false, Ordering::AcqRel) {
+                break 0;
+            }
+            if self.elapsed_ticks() > self.ticks.load(Ordering::Relaxed) {
+                self.ticks.fetch_add(1, Ordering::Relaxed);
+                break 1;
+            }
+            thread::park();
+        };
+        *self.waiter.lock().unwrap() = None;
+        elapsed
+    }
+
+    fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Release);
+        if let Some(thread) = self.waiter.lock().unwrap().as_ref() {
+            thread.unpark();
+        }
+    }
+
+    /// Time of the frames processed since the clock started
+    fn now(&self) -> Timestamp {
+        let frames = self.frames.load(Ordering::Acquire);
+        (frames * 1_000_000)
+            .checked_div(self.sample_rate.load(Ordering::Relaxed))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+struct ManualClockState {
+    /// Ticks advanced but not yet reported by `wait`
+    pending: u64,
+    interrupted: bool,
+}
+
+/// Ticks only when told to, e.g. by tests. Every tick is reported.
+#[derive(Default)]
+pub struct ManualClock {
+    state: Mutex<ManualClockState>,
+    condvar: Condvar,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Lets `ticks` ticks elapse.
+    pub fn advance(&self, ticks: u64) {
+        self.state.lock().unwrap().pending += ticks;
+        self.condvar.notify_one();
+    }
+}
+
+impl ClockSource for ManualClock {
+    /// Ticks advanced while the timeline was not running are dropped.
+    fn start(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pending = 0;
+        state.interrupted = false;
+    }
+
+    fn wait(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.interrupted {
+                state.interrupted = false;
+                return 0;
+            }
+            if state.pending > 0 {
+                state.pending -= 1;
+                return 1;
+            }
+            state = self.condvar.wait(state).unwrap();
+        }
+    }
+
+    fn interrupt(&self) {
+        self.state.lock().unwrap().interrupted = true;
+        self.condvar.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::timeline::{Timeline, TimelineEvent};
+
+    use super::{ClockSource, FrameClock, ManualClock, WallClock};
+
+    fn ticks(events: impl Iterator<Item = TimelineEvent>) -> Vec<u64> {
+        events
+            .filter_map(|event| match event {
+                TimelineEvent::Tick(tick) => Some(tick),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_frame_clock() {
+        let clock = FrameClock::new(Duration::from_millis(10));
+        clock.start();
+        // 1024 frames at 48kHz are 21.3ms
+        clock.advance(0, 1024, 48_000);
+        assert_eq!(clock.wait(), 1);
+        assert_eq!(clock.wait(), 1);
+        clock.advance(1024, 1024, 48_000);
+        assert_eq!(clock.wait(), 1);
+        assert_eq!(clock.now(), 42_666);
+        clock.interrupt();
+        assert_eq!(clock.wait(), 0);
+        // Restarting rewinds to zero, from the frame of the next cycle on
+        clock.start();
+        clock.advance(5000, 480, 48_000);
+        assert_eq!(clock.wait(), 1);
+        assert_eq!(clock.frame_at(clock.now()), 5480);
+        assert_eq!(clock.frame_at(5_000), 5240);
+        clock.interrupt();
+        assert_eq!(clock.wait(), 0);
+    }
+
+    #[test]
+    fn test_frame_clock_wakes_waiter() {
+        let clock = Arc::new(FrameClock::new(Duration::from_millis(10)));
+        clock.start();
+        let waiter = {
+            let clock = clock.clone();
+            std::thread::spawn(move || (clock.wait(), clock.wait()))
+        };
+        std::thread::sleep(Duration::from_millis(5));
+        clock.advance(0, 480, 48_000);
+        std::thread::sleep(Duration::from_millis(5));
+        clock.interrupt();
+        assert_eq!(waiter.join().unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn test_wall_clock_skips_ticks() {
+        let clock = WallClock::new(Duration::from_millis(1));
+        clock.start();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(clock.wait() >= 4);
+    }
+
+    #[test]
+    fn test_timeline_with_manual_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let timeline = Timeline::with_clock(clock.clone());
+        let subscription = timeline.subscribe();
+        timeline.start();
+        clock.advance(3);
+        let received = ticks(subscription.receiver.iter().take(4));
+        assert_eq!(received, vec![0, 1, 2, 3]);
+        // Pausing does not wait for another tick
+        timeline.pause();
+        assert!(
+            subscription
+                .receiver
+                .try_iter()
+                .any(|event| event == TimelineEvent::Pause)
+        );
+        // Resuming sends the next tick right away
+        timeline.start();
+        clock.advance(1);
+        let received = ticks(subscription.receiver.iter().take(2));
+        assert_eq!(received, vec![4, 5]);
+        timeline.stop();
+    }
+}
@@ -6,6 +6,7 @@ pub const TRACK_DEFAULT_BEAT: Beat = Beat {
     channel: 9,
     note: Note::C(1),
     velocity: 72,
+    gate: None,
 };
 pub const BEAT_TIME_MICRO: u32 = 1_000_000;
 
@@ -1,38 +1,285 @@
-use serde::Serialize;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use self::DrumTrackBeat::*;
 use crate::{
+    arpeggiator::Arpeggiator,
     consts,
-    midi::{note::Note, Channel, Velocity},
+    euclidean::euclidean_pattern,
+    midi::{
+        chord::{Chord, Voicing},
+        note::Note,
+        Channel, Controller, Velocity, PITCH_BEND_CENTER, PITCH_BEND_MAX,
+    },
     project::TempoScale,
+    scale::{Degree, Tonality},
 };
 
 use crate::project::F;
 
-#[derive(PartialEq, Clone, Debug, Serialize)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum DrumTrackBeat {
     Unset,
     DefaultBeat,
     OverrideBeat(Vec<Beat>),
+    /// Default beat of the track playing a degree of the scale of the project
+    Degree(Degree),
+    /// Default beat of the track playing the notes of a chord
+    Chord(ChordBeat),
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ChordBeat {
+    pub chord: Chord,
+    /// Octave of the root of the chord
+    pub octave: i8,
+    #[serde(default)]
+    pub voicing: Voicing,
+}
+
+/// A step of a track: what to play, and how far off the grid.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+    pub beat: DrumTrackBeat,
+    /// Timing offset as a fraction of a step, see `DrumTrack::set_offset`
+    #[serde(default)]
+    pub offset: F,
+    /// Chance of the step to fire in percent
+    #[serde(default = "default_probability")]
+    pub probability: u8,
+    #[serde(default)]
+    pub condition: Condition,
+    #[serde(default)]
+    pub ratchet: Ratchet,
+    /// Delay between the notes of the step from the lowest up as a fraction
+    /// of a step, or from the highest down if negative
+    #[serde(default)]
+    pub strum: F,
+    #[serde(default)]
+    pub locks: ParameterLocks,
+}
+
+fn default_probability() -> u8 {
+    100
+}
+
+impl From<DrumTrackBeat> for Step {
+    fn from(beat: DrumTrackBeat) -> Self {
+        Self {
+            beat,
+            offset: F::from(0),
+            probability: default_probability(),
+            condition: Condition::default(),
+            ratchet: Ratchet::default(),
+            strum: F::from(0),
+            locks: ParameterLocks::default(),
+        }
+    }
+}
+
+/// Controller values sent on the channel of a track right before the notes
+/// of a step.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ParameterLocks {
+    /// Values of Control Change controllers
+    #[serde(default)]
+    pub cc: BTreeMap<Controller, u8>,
+    #[serde(default)]
+    pub pitch_bend: Option<u16>,
+}
+
+/// Retriggers of a step, evenly spaced within the step.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Ratchet {
+    /// Number of times the step is played, 1 for no retriggers
+    pub count: u8,
+    /// Velocity added from the first to the last retrigger,
+    /// e.g. -40 for a fade out
+    pub velocity_ramp: i8,
+}
+
+impl Default for Ratchet {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            velocity_ramp: 0,
+        }
+    }
+}
+
+impl Ratchet {
+    /// Velocity of the `i`th retrigger of a step played at `velocity`.
+    pub fn velocity(&self, i: u8, velocity: Velocity) -> Velocity {
+        if self.count <= 1 {
+            return velocity;
+        }
+        let ramp = self.velocity_ramp as i16 * i as i16 / (self.count as i16 - 1);
+        (velocity as i16 + ramp).clamp(1, 127) as Velocity
+    }
+}
+
+/// Condition of a step to play, evaluated against the loop iteration
+/// of its track.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum Condition {
+    #[default]
+    Always,
+    /// `Loop(a, b)` ("a:b") plays on the `a`th of every `b` loops.
+    Loop(usize, usize),
+    /// Only while fill mode is on
+    Fill,
+    /// Only while fill mode is off
+    NotFill,
+    /// Only when the previous condition on the track was met
+    Pre,
+    /// Only when the previous condition on the track was not met
+    NotPre,
+}
+
+impl Condition {
+    /// Parses "a:b" (1 <= a <= b), "fill", "!fill", "pre", "!pre" and "always".
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "always" => Some(Self::Always),
+            "fill" => Some(Self::Fill),
+            "!fill" => Some(Self::NotFill),
+            "pre" => Some(Self::Pre),
+            "!pre" => Some(Self::NotPre),
+            s => {
+                let (a, b) = s.split_once(':')?;
+                let (a, b) = (a.parse().ok()?, b.parse().ok()?);
+                (1..=b).contains(&a).then_some(Self::Loop(a, b))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Always => write!(f, "always"),
+            Self::Loop(a, b) => write!(f, "{}:{}", a, b),
+            Self::Fill => write!(f, "fill"),
+            Self::NotFill => write!(f, "!fill"),
+            Self::Pre => write!(f, "pre"),
+            Self::NotPre => write!(f, "!pre"),
+        }
+    }
+}
+
+/// Slot of a pattern of a track, written as a bank letter and a number
+/// like "A1" to "H16".
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Default, Hash)]
+pub struct PatternSlot(u8);
+
+const PATTERNS_PER_BANK: u8 = 16;
+const BANKS: u8 = 8;
+
+impl PatternSlot {
+    /// `bank` counts from 0 for "A", `number` from 1.
+    pub fn new(bank: u8, number: u8) -> Option<Self> {
+        (bank < BANKS && (1..=PATTERNS_PER_BANK).contains(&number))
+            .then(|| Self(bank * PATTERNS_PER_BANK + number - 1))
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut chars = s.chars();
+        let bank = chars.next()?.to_ascii_uppercase();
+        if !bank.is_ascii_uppercase() {
+            return None;
+        }
+        let number = chars.as_str().parse().ok()?;
+        Self::new(bank as u8 - b'A', number)
+    }
+}
+
+impl std::fmt::Display for PatternSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bank = (b'A' + self.0 / PATTERNS_PER_BANK) as char;
+        write!(f, "{}{}", bank, self.0 % PATTERNS_PER_BANK + 1)
+    }
+}
+
+impl Serialize for PatternSlot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PatternSlot {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s)
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid pattern slot: {}", s)))
+    }
+}
+
+/// Steps can be nudged by at most half a step, so they never pass their neighbours.
+fn max_step_offset() -> F {
+    F::new(1u64, 2u64)
+}
+
+/// Length of the notes of a track or a single beat.
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Gate {
+    /// Fraction of a step of the track, e.g. `Steps(2)` ties a note over two steps.
+    Steps(F),
+    /// Length in (global) beats, regardless of the track's tempo scale.
+    Beats(F),
+    /// Held until the next beat of the track starts.
+    Legato,
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Self::Beats(F::new(1u64, 4u64))
+    }
 }
 
 /// Represents a track.
-/// Each track contains a series of beats (can be empty)
+/// Each track contains a series of steps (can be empty)
 /// and a tempo scale which is respected by BeatMaker
 /// to stretch its own tempo in relation to the global tempo.
-#[derive(Clone, Debug, Serialize)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DrumTrack {
     name: String,
     tempo_scale: TempoScale,
     default_beat: Beat,
-    beats: Vec<DrumTrackBeat>,
+    gate: Gate,
+    /// Overrides the swing of the project
+    swing: Option<F>,
+    /// Name of a groove template of the project
+    groove: Option<String>,
+    /// Steps of the active pattern
+    steps: Vec<Step>,
+    pattern: PatternSlot,
+    /// Steps of all other patterns that have been used
+    patterns: BTreeMap<PatternSlot, Vec<Step>>,
+    /// Pattern to switch to at the next loop boundary, see `BeatSorter`
+    #[serde(skip)]
+    queued_pattern: Option<PatternSlot>,
+    muted: bool,
+    /// While any track is soloed, only soloed tracks are played
+    soloed: bool,
+    /// Arpeggiates the beats of each step, or the held notes
+    arpeggiator: Option<Arpeggiator>,
+    /// Notes held with `hold_notes`, arpeggiated instead of the steps
+    #[serde(skip)]
+    held_notes: Vec<Note>,
+    /// Controller values restored on steps that do not lock them
+    cc_defaults: BTreeMap<Controller, u8>,
 }
 
-#[derive(PartialEq, Copy, Clone, Debug, Serialize)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Beat {
     pub channel: Channel,
     pub note: Note,
     pub velocity: Velocity,
+    /// Overrides the gate of the track
+    #[serde(default)]
+    pub gate: Option<Gate>,
 }
 
 impl DrumTrack {
@@ -47,7 +294,7 @@ impl DrumTrack {
         Self {
             name: name.to_string(),
             default_beat,
-            beats: beats.to_vec(),
+            steps: beats.iter().cloned().map(Step::from).collect(),
             ..Default::default()
         }
     }
@@ -93,8 +340,147 @@ impl DrumTrack {
         }
     }
 
+    pub fn get_gate(&self) -> Gate {
+        self.gate
+    }
+
+    pub fn set_gate(&mut self, gate: Gate) {
+        self.gate = gate;
+    }
+
+    pub fn get_swing(&self) -> Option<F> {
+        self.swing
+    }
+
+    pub fn set_swing(&mut self, swing: Option<F>) {
+        self.swing = swing;
+    }
+
+    pub fn get_groove(&self) -> Option<&str> {
+        self.groove.as_deref()
+    }
+
+    pub fn set_groove(&mut self, groove: Option<String>) {
+        self.groove = groove;
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn is_soloed(&self) -> bool {
+        self.soloed
+    }
+
+    pub fn set_soloed(&mut self, soloed: bool) {
+        self.soloed = soloed;
+    }
+
+    pub fn get_arpeggiator(&self) -> Option<&Arpeggiator> {
+        self.arpeggiator.as_ref()
+    }
+
+    pub fn set_arpeggiator(&mut self, arpeggiator: Option<Arpeggiator>) {
+        self.arpeggiator = arpeggiator;
+    }
+
+    /// Holds `notes` in the order they were played, or releases all notes
+    /// if empty. Only arpeggiated tracks play held notes.
+    pub fn hold_notes(&mut self, notes: Vec<Note>) {
+        self.held_notes = notes;
+    }
+
+    /// Held notes as beats of the default beat of the track
+    pub fn held_chord(&self) -> Vec<Beat> {
+        self.held_notes
+            .iter()
+            .map(|note| Beat {
+                note: *note,
+                ..self.default_beat
+            })
+            .collect()
+    }
+
+    pub fn get_cc_defaults(&self) -> &BTreeMap<Controller, u8> {
+        &self.cc_defaults
+    }
+
+    /// Sets the value a controller is restored to outside of steps locking
+    /// it, or removes it if `None`. Controllers and values are 7 bits.
+    pub fn set_cc_default(&mut self, controller: Controller, value: Option<u8>) {
+        match value {
+            Some(value) => self.cc_defaults.insert(controller, value),
+            None => self.cc_defaults.remove(&controller),
+        };
+    }
+
+    pub fn get_locks(&self, idx: usize) -> Option<&ParameterLocks> {
+        self.steps.get(idx).map(|step| &step.locks)
+    }
+
+    /// Locks a controller to `value` on the step at `idx`, or unlocks it if
+    /// `None`. Controllers and values are 7 bits.
+    pub fn set_cc_lock(&mut self, idx: usize, controller: Controller, value: Option<u8>) {
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
+        }
+        let locks = &mut self.steps[idx].locks;
+        match value {
+            Some(value) => locks.cc.insert(controller, value),
+            None => locks.cc.remove(&controller),
+        };
+    }
+
+    /// Locks the pitch bend of the step at `idx`, or unlocks it if `None`.
+    pub fn set_pitch_bend_lock(&mut self, idx: usize, value: Option<u16>) {
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
+        }
+        self.steps[idx].locks.pitch_bend = value.map(|value| value.min(PITCH_BEND_MAX));
+    }
+
+    /// Controller values in effect while the step at `idx` plays: its locks
+    /// on top of the defaults of the track, with the pitch bend centered
+    /// unless locked. Without a step, e.g. for held notes, the defaults.
+    pub fn controls(&self, idx: Option<usize>) -> ParameterLocks {
+        let locks = idx.and_then(|idx| self.get_locks(idx));
+        let mut cc = self.cc_defaults.clone();
+        if let Some(locks) = locks {
+            cc.extend(locks.cc.iter());
+        }
+        ParameterLocks {
+            cc,
+            pitch_bend: Some(
+                locks
+                    .and_then(|locks| locks.pitch_bend)
+                    .unwrap_or(PITCH_BEND_CENTER),
+            ),
+        }
+    }
+
+    /// Length of a beat at `idx` in global beat time.
+    pub fn gate_length(&self, idx: usize, beat: &Beat) -> F {
+        match beat.gate.unwrap_or(self.gate) {
+            Gate::Steps(steps) => steps / self.tempo_scale,
+            Gate::Beats(beats) => beats,
+            Gate::Legato => F::from(self.steps_until_next_beat(idx)) / self.tempo_scale,
+        }
+    }
+
+    /// Distance from `idx` to the next step holding a beat, wrapping around
+    /// the end of the track. A track with a single beat yields its length.
+    fn steps_until_next_beat(&self, idx: usize) -> usize {
+        (1..self.len())
+            .find(|distance| !matches!(self.steps[(idx + distance) % self.len()].beat, Unset))
+            .unwrap_or(self.len())
+    }
+
     pub fn toggle_beat(&mut self, idx: usize) {
-        if let Unset = self.beats.get(idx).unwrap_or(&Unset) {
+        if let Unset = self.get(idx).unwrap_or(&Unset) {
             self.assign_beat(idx, DrumTrackBeat::DefaultBeat);
         } else {
             self.remove_beat(idx);
@@ -102,21 +488,188 @@ impl DrumTrack {
     }
 
     pub fn assign_beat(&mut self, idx: usize, beat: DrumTrackBeat) {
-        if self.beats.len() < idx + 1 {
-            self.beats.resize(idx + 1, Unset);
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
         }
 
-        self.beats[idx] = beat;
+        self.steps[idx].beat = beat;
     }
 
     pub fn remove_beat(&mut self, idx: usize) {
-        if let Some(v) = self.beats.get_mut(idx) {
-            *v = Unset;
+        if let Some(v) = self.steps.get_mut(idx) {
+            *v = Unset.into();
         }
     }
 
+    /// Slot of the active pattern, which all other methods refer to
+    pub fn get_pattern(&self) -> PatternSlot {
+        self.pattern
+    }
+
+    /// Loop length of a pattern like `loop_length`. Patterns that have not
+    /// been used yet have the length of the active one.
+    pub fn pattern_loop_length(&self, slot: PatternSlot) -> F {
+        let len = self.patterns.get(&slot).map_or(self.len(), Vec::len);
+        F::from(len) / self.tempo_scale
+    }
+
+    /// Slots of all patterns with steps, including the active one
+    pub fn pattern_slots(&self) -> Vec<PatternSlot> {
+        let mut slots: Vec<_> = self.patterns.keys().copied().collect();
+        slots.push(self.pattern);
+        slots.sort();
+        slots
+    }
+
+    pub fn get_queued_pattern(&self) -> Option<PatternSlot> {
+        self.queued_pattern
+    }
+
+    /// Queues a switch to another pattern, which `BeatSorter` carries out
+    /// at the next loop boundary of the track.
+    pub fn queue_pattern(&mut self, slot: PatternSlot) {
+        self.queued_pattern = Some(slot);
+    }
+
+    /// Switches to another pattern right away. Patterns that have not been
+    /// used yet start out empty with the length of the active one.
+    pub fn select_pattern(&mut self, slot: PatternSlot) {
+        self.queued_pattern = None;
+        if slot == self.pattern {
+            return;
+        }
+        let steps = self
+            .patterns
+            .remove(&slot)
+            .unwrap_or_else(|| vec![Unset.into(); self.steps.len()]);
+        let previous = std::mem::replace(&mut self.steps, steps);
+        self.patterns.insert(self.pattern, previous);
+        self.pattern = slot;
+    }
+
+    /// Replaces the steps of pattern `to` with those of pattern `from`.
+    /// Patterns that have not been used yet are empty with the length of
+    /// the active one, like in `select_pattern`.
+    pub fn copy_pattern(&mut self, from: PatternSlot, to: PatternSlot) {
+        let steps = if from == self.pattern {
+            self.steps.clone()
+        } else {
+            self.patterns
+                .get(&from)
+                .cloned()
+                .unwrap_or_else(|| vec![Unset.into(); self.steps.len()])
+        };
+        if to == self.pattern {
+            self.steps = steps;
+        } else {
+            self.patterns.insert(to, steps);
+        }
+    }
+
+    /// Unsets all steps of a pattern, keeping its length.
+    pub fn clear_pattern(&mut self, slot: PatternSlot) {
+        let steps = if slot == self.pattern {
+            &mut self.steps
+        } else if let Some(steps) = self.patterns.get_mut(&slot) {
+            steps
+        } else {
+            return;
+        };
+        let len = steps.len();
+        *steps = vec![Unset.into(); len];
+    }
+
+    /// Resizes the track to `steps` steps and spreads `hits` beats over them
+    /// evenly, shifted `rotation` steps later (see `euclidean_pattern`).
+    /// Steps that are hits keep an `OverrideBeat`, `Degree` or `Chord` they
+    /// already have.
+    pub fn fill_euclidean(&mut self, hits: usize, steps: usize, rotation: usize) {
+        self.resize(steps);
+        for (step, hit) in self
+            .steps
+            .iter_mut()
+            .zip(euclidean_pattern(hits, steps, rotation))
+        {
+            step.beat = match step.beat {
+                OverrideBeat(_) | Degree(_) | Chord(_) if hit => continue,
+                _ if hit => DefaultBeat,
+                _ => Unset,
+            };
+        }
+    }
+
+    /// Nudges the step at `idx` early (negative) or late (positive) by a
+    /// fraction of a step, clamped to half a step either way.
+    pub fn set_offset(&mut self, idx: usize, offset: F) {
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
+        }
+
+        self.steps[idx].offset = offset.clamp(-max_step_offset(), max_step_offset());
+    }
+
+    pub fn get_offset(&self, idx: usize) -> F {
+        self.steps.get(idx).map_or(F::from(0), |step| step.offset)
+    }
+
+    /// Sets the chance of the step at `idx` to fire, in percent (at most 100).
+    pub fn set_probability(&mut self, idx: usize, probability: u8) {
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
+        }
+
+        self.steps[idx].probability = probability.min(100);
+    }
+
+    pub fn get_probability(&self, idx: usize) -> u8 {
+        self.steps
+            .get(idx)
+            .map_or(default_probability(), |step| step.probability)
+    }
+
+    /// Strums the notes of the step at `idx`, clamped to a step either way.
+    pub fn set_strum(&mut self, idx: usize, strum: F) {
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
+        }
+
+        self.steps[idx].strum = strum.clamp(-F::from(1), F::from(1));
+    }
+
+    pub fn get_strum(&self, idx: usize) -> F {
+        self.steps.get(idx).map_or(F::from(0), |step| step.strum)
+    }
+
+    pub fn set_condition(&mut self, idx: usize, condition: Condition) {
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
+        }
+
+        self.steps[idx].condition = condition;
+    }
+
+    pub fn get_condition(&self, idx: usize) -> Condition {
+        self.steps
+            .get(idx)
+            .map_or(Condition::default(), |step| step.condition)
+    }
+
+    pub fn set_ratchet(&mut self, idx: usize, ratchet: Ratchet) {
+        if self.steps.len() < idx + 1 {
+            self.resize(idx + 1);
+        }
+
+        self.steps[idx].ratchet = ratchet;
+    }
+
+    pub fn get_ratchet(&self, idx: usize) -> Ratchet {
+        self.steps
+            .get(idx)
+            .map_or(Ratchet::default(), |step| step.ratchet)
+    }
+
     pub fn resize(&mut self, size: usize) {
-        self.beats.resize(size, Unset);
+        self.steps.resize(size, Unset.into());
     }
 
     pub fn set_tempo_scale(&mut self, tempo_scale: F) {
@@ -124,37 +677,74 @@ impl DrumTrack {
     }
 
     pub fn len(&self) -> usize {
-        self.beats.len()
+        self.steps.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.beats.is_empty()
+        self.steps.is_empty()
+    }
+
+    /// Length of one pass through the track in global beat time,
+    /// i.e. with the tempo scale applied.
+    pub fn loop_length(&self) -> F {
+        F::from(self.len()) / self.tempo_scale
     }
 
     pub fn get(&self, idx: usize) -> Option<&DrumTrackBeat> {
-        self.beats.get(idx)
+        self.steps.get(idx).map(|step| &step.beat)
     }
 
-    pub fn get_as_beats(&self, idx: usize) -> Option<Option<Vec<Beat>>> {
-        self.beats
-            .get(idx)
-            .map(|b| self.drum_track_beat_to_beats(b))
+    pub fn get_step(&self, idx: usize) -> Option<&Step> {
+        self.steps.get(idx)
     }
 
-    fn drum_track_beat_to_beats(&self, beat: &DrumTrackBeat) -> Option<Vec<Beat>> {
+    /// Beats of the step at `idx`, with scale degrees played in `tonality`.
+    pub fn get_as_beats(&self, idx: usize, tonality: &Tonality) -> Option<Option<Vec<Beat>>> {
+        self.get(idx)
+            .map(|b| self.drum_track_beat_to_beats(b, tonality))
+    }
+
+    fn drum_track_beat_to_beats(
+        &self,
+        beat: &DrumTrackBeat,
+        tonality: &Tonality,
+    ) -> Option<Vec<Beat>> {
         match beat {
             Unset => None,
             DefaultBeat => Some(vec![self.default_beat]),
             OverrideBeat(beat) => Some(beat.clone()),
+            Degree(degree) => Some(vec![Beat {
+                note: tonality.note(*degree),
+                ..self.default_beat
+            }]),
+            Chord(chord) => Some(
+                chord
+                    .chord
+                    .notes(chord.octave, chord.voicing)
+                    .into_iter()
+                    .map(|note| Beat {
+                        note,
+                        ..self.default_beat
+                    })
+                    .collect(),
+            ),
         }
     }
 
-    pub fn iter(&self) -> std::slice::Iter<DrumTrackBeat> {
-        self.beats.iter()
+    pub fn iter(&self) -> impl Iterator<Item = &DrumTrackBeat> + use<'_> {
+        self.steps.iter().map(|step| &step.beat)
+    }
+
+    pub fn iter_steps(&self) -> std::slice::Iter<Step> {
+        self.steps.iter()
     }
 
-    pub fn iter_as_beats(&self) -> impl Iterator<Item = Option<Vec<Beat>>> + use<'_> {
-        self.beats.iter().map(|b| self.drum_track_beat_to_beats(b))
+    pub fn iter_as_beats<'a>(
+        &'a self,
+        tonality: &'a Tonality,
+    ) -> impl Iterator<Item = Option<Vec<Beat>>> + use<'a> {
+        self.iter()
+            .map(|b| self.drum_track_beat_to_beats(b, tonality))
     }
 
     #[cfg(test)]
@@ -168,7 +758,8 @@ impl DrumTrack {
                 ..TRACK_DEFAULT_BEAT
             },
             tempo_scale,
-            beats: vec![DrumTrackBeat::DefaultBeat; count],
+            steps: vec![DrumTrackBeat::DefaultBeat.into(); count],
+            ..Default::default()
         }
     }
 }
@@ -179,7 +770,103 @@ impl Default for DrumTrack {
             name: Default::default(),
             tempo_scale: F::from(1),
             default_beat: consts::TRACK_DEFAULT_BEAT,
-            beats: Vec::new(),
+            gate: Gate::default(),
+            swing: None,
+            groove: None,
+            steps: Vec::new(),
+            pattern: PatternSlot::default(),
+            patterns: BTreeMap::new(),
+            queued_pattern: None,
+            muted: false,
+            soloed: false,
+            arpeggiator: None,
+            held_notes: Vec::new(),
+            cc_defaults: BTreeMap::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{midi::note::Note, project::F};
+
+    use super::{Beat, Condition, DrumTrack, DrumTrackBeat, PatternSlot};
+
+    #[test]
+    fn test_fill_euclidean_keeps_override_beats() {
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        let accent = DrumTrackBeat::OverrideBeat(vec![Beat {
+            velocity: 127,
+            ..track.get_default_beat()
+        }]);
+        track.assign_beat(0, accent.clone());
+        track.assign_beat(1, accent.clone());
+        track.set_offset(3, F::new(1u64, 8u64));
+        track.fill_euclidean(3, 8, 0);
+        assert_eq!(track.len(), 8);
+        let beats: Vec<_> = track.iter().cloned().collect();
+        assert_eq!(
+            beats,
+            vec![
+                accent,
+                DrumTrackBeat::Unset,
+                DrumTrackBeat::Unset,
+                DrumTrackBeat::DefaultBeat,
+                DrumTrackBeat::Unset,
+                DrumTrackBeat::Unset,
+                DrumTrackBeat::DefaultBeat,
+                DrumTrackBeat::Unset,
+            ]
+        );
+        // Only the beats are regenerated
+        assert_eq!(track.get_offset(3), F::new(1u64, 8u64));
+    }
+
+    #[test]
+    fn test_parse_condition() {
+        assert_eq!(Condition::parse("3:4"), Some(Condition::Loop(3, 4)));
+        assert_eq!(Condition::parse("!FILL"), Some(Condition::NotFill));
+        assert_eq!(Condition::parse("pre"), Some(Condition::Pre));
+        assert_eq!(Condition::parse("0:2"), None);
+        assert_eq!(Condition::parse("3:2"), None);
+        assert_eq!(Condition::parse("x"), None);
+        for condition in [Condition::Loop(1, 2), Condition::NotPre, Condition::Always] {
+            assert_eq!(Condition::parse(&condition.to_string()), Some(condition));
+        }
+    }
+
+    #[test]
+    fn test_pattern_slot() {
+        assert_eq!(PatternSlot::parse("A1"), Some(PatternSlot::default()));
+        assert_eq!(PatternSlot::parse("b16").unwrap().to_string(), "B16");
+        assert_eq!(PatternSlot::parse("A17"), None);
+        assert_eq!(PatternSlot::parse("A0"), None);
+        assert_eq!(PatternSlot::parse("I1"), None);
+        assert_eq!(PatternSlot::parse("1"), None);
+    }
+
+    #[test]
+    fn test_select_copy_and_clear_patterns() {
+        let a1 = PatternSlot::parse("A1").unwrap();
+        let a2 = PatternSlot::parse("A2").unwrap();
+        let a3 = PatternSlot::parse("A3").unwrap();
+        let mut track = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4);
+        track.select_pattern(a2);
+        // New patterns start out empty with the same length
+        assert_eq!(track.len(), 4);
+        assert!(track.iter().all(|beat| *beat == DrumTrackBeat::Unset));
+        track.copy_pattern(a1, a3);
+        track.clear_pattern(a1);
+        assert_eq!(track.pattern_slots(), vec![a1, a2, a3]);
+        track.select_pattern(a3);
+        assert!(track.iter().all(|beat| *beat == DrumTrackBeat::DefaultBeat));
+        track.select_pattern(a1);
+        assert_eq!(track.len(), 4);
+        assert!(track.iter().all(|beat| *beat == DrumTrackBeat::Unset));
+        // Copying an unused pattern clears the steps, keeping their number
+        track.assign_beat(0, DrumTrackBeat::DefaultBeat);
+        track.copy_pattern(PatternSlot::parse("C5").unwrap(), a1);
+        assert_eq!(track.len(), 4);
+        assert!(track.iter().all(|beat| *beat == DrumTrackBeat::Unset));
+    }
+}
@@ -48,7 +48,7 @@ impl SSClient for SSCoreAudioClient {
                     recv(beatmaker_subscription.receiver) -> event => {
                         let event = event?;
                         match event {
-                            BeatMakerEvent::MIDIEvent(evt) => {
+                            BeatMakerEvent::MIDIEvent(evt, _timestamp) => {
                                 let data = evt.to_data()?;
                                 debug!("BeatMaker: MIDI data: {:?}", data);
                                 let packet_buffer = PacketBuffer::new(0, &data);
@@ -8,7 +8,11 @@ use jack::{Frames, RawMidi};
 use log::{debug, error, info};
 
 use crate::{
-    engine::SSClient,
+    clock::{ClockSource, FrameClock},
+    engine::{
+        scheduler::MidiScheduler,
+        SSClient,
+    },
     beatmaker::{BeatMaker, BeatMakerSubscription},
     midi::ChannelVoiceEvent,
     SSResult,
@@ -17,6 +21,8 @@ use crate::{
 pub struct SSJackClient {
     beatmaker_subscription: Arc<BeatMakerSubscription>,
     jack_client_condvar: Arc<(Mutex<bool>, Condvar)>,
+    /// Advanced by the process callback to drive the timeline
+    frame_clock: Arc<FrameClock>,
 }
 
 impl SSJackClient {
@@ -24,6 +30,7 @@ impl SSJackClient {
         Self {
             beatmaker_subscription: Arc::new(beatmaker_subscription),
             jack_client_condvar: Arc::new((Mutex::new(false), Condvar::new())),
+            frame_clock: Arc::new(FrameClock::default()),
         }
     }
 }
@@ -32,6 +39,7 @@ impl SSClient for SSJackClient {
     fn start(&mut self) -> SSResult<()> {
         let jack_client_condvar = self.jack_client_condvar.clone();
         let beatmaker_subscription = self.beatmaker_subscription.clone();
+        let frame_clock = self.frame_clock.clone();
         thread::spawn(move || -> SSResult<()> {
             {
                 // 1. Create client
@@ -64,6 +72,11 @@ impl SSClient for SSJackClient {
                                              client: &jack::Client,
                                              process_scope: &jack::ProcessScope|
                       -> jack::Control {
+                    frame_clock.advance(
+                        process_scope.last_frame_time(),
+                        process_scope.n_frames(),
+                        client.sample_rate(),
+                    );
                     let out_a_p = out_a.as_mut_slice(process_scope);
                     let out_b_p = out_b.as_mut_slice(process_scope);
                     let in_a_p = in_a.as_slice(process_scope);
@@ -78,8 +91,8 @@ impl SSClient for SSJackClient {
                     // let _ = process_midi(state, client, &mut out_midi, process_scope);
                     let _ = process_beatmaker(
                         &beatmaker_subscription,
+                        &frame_clock,
                         state,
-                        client,
                         &mut out_midi,
                         process_scope,
                     );
@@ -97,6 +110,7 @@ impl SSClient for SSJackClient {
                 let process = jack::contrib::ClosureProcessHandler::with_state(
                     TestState {
                         last_event_midi_seconds: 0,
+                        midi_scheduler: MidiScheduler::new(),
                     },
                     process_callback,
                     move |_, _, _| jack::Control::Continue,
@@ -132,6 +146,10 @@ impl SSClient for SSJackClient {
         Ok(())
     }
 
+    fn clock_source(&self) -> Option<Arc<dyn ClockSource>> {
+        Some(self.frame_clock.clone())
+    }
+
     fn stop(&mut self) -> SSResult<()> {
         // 5. Not needed as the async client will cease processing on `drop`.
         // if let Err(err) = active_client.deactivate() {
@@ -151,6 +169,8 @@ impl SSClient for SSJackClient {
 
 struct TestState {
     last_event_midi_seconds: u32,
+    /// MIDI events of BeatMaker held back until the cycle they are due in
+    midi_scheduler: MidiScheduler,
 }
 
 fn frame_to_time(frames: Frames, sample_rate: usize) -> f64 {
@@ -216,29 +236,35 @@ fn process_midi(
 
 fn process_beatmaker(
     subscription: &BeatMakerSubscription,
+    frame_clock: &FrameClock,
     state: &mut TestState,
-    client: &jack::Client,
     port: &mut jack::Port<jack::MidiOut>,
     process_scope: &jack::ProcessScope,
 ) -> SSResult<()> {
-    // TODO: Can NOT use while loop to process all messages in the channel.
-    // Find out why.
-    let mut midi_writer = port.writer(process_scope);
-    let mut idx = 0;
+    // Timestamps of BeatMaker are on the frame clock driving the timeline,
+    // which maps them back to frames.
     while let Ok(event) = &subscription.receiver.try_recv() {
         match *event {
-            crate::beatmaker::BeatMakerEvent::MIDIEvent(evt) => {
-                let data = evt.to_data()?;
-                debug!("BeatMaker: MIDI data: {:?}", data);
-                let raw_midi = RawMidi {
-                    time: idx,
-                    bytes: &data,
-                };
-                midi_writer.write(&raw_midi)?;
-                idx += 1;
+            crate::beatmaker::BeatMakerEvent::MIDIEvent(evt, timestamp) => {
+                state
+                    .midi_scheduler
+                    .push(frame_clock.frame_at(timestamp), evt);
             }
             _ => {}
         }
     }
+    let mut midi_writer = port.writer(process_scope);
+    let due = state
+        .midi_scheduler
+        .take_due(process_scope.last_frame_time(), process_scope.n_frames());
+    for &(offset, evt) in due {
+        let data = evt.to_data()?;
+        debug!("BeatMaker: MIDI data at frame {}: {:?}", offset, data);
+        let raw_midi = RawMidi {
+            time: offset,
+            bytes: &data,
+        };
+        midi_writer.write(&raw_midi)?;
+    }
     Ok(())
 }
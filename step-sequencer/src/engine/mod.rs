@@ -1,6 +1,10 @@
+use std::sync::Arc;
+
 use crate::beatmaker::BeatMakerSubscription;
+use crate::clock::ClockSource;
 use crate::SSResult;
 pub mod adapter;
+pub mod scheduler;
 #[cfg(feature = "coreaudio")]
 mod coreaudio;
 #[cfg(feature = "jack")]
@@ -11,6 +15,12 @@ mod cpal;
 pub trait SSClient {
     fn start(&mut self) -> SSResult<()>;
     fn stop(&mut self) -> SSResult<()>;
+
+    /// Clock of the audio device to drive the timeline with, if any.
+    /// Otherwise the timeline runs on the wall clock.
+    fn clock_source(&self) -> Option<Arc<dyn ClockSource>> {
+        None
+    }
 }
 
 #[cfg(feature = "jack")]
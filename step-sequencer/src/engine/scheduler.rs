@@ -0,0 +1,218 @@
+//! Timing of MIDI events within the cycles of an audio engine.
+//!
+//! BeatMaker stamps every MIDI event with the time it is meant to be heard
+//! at. Engines that process audio in cycles convert it into a frame and
+//! hold the event back until the cycle containing that frame, instead of
+//! writing it at the start of whichever cycle it arrives in.
+
+use std::{collections::VecDeque, sync::OnceLock, time::Instant};
+
+use crate::midi::ChannelVoiceEvent;
+
+/// Microseconds on the clock source driving the timeline. Unless it keeps
+/// time on its own, since the first call to `now` on a monotonic clock.
+pub type Timestamp = u64;
+
+/// Frame count of an audio engine, wrapping around like JACK's.
+pub type Frames = u32;
+
+/// Current time on the clock of `Timestamp`.
+pub fn now() -> Timestamp {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_micros() as Timestamp
+}
+
+/// Events a `MidiScheduler` holds without allocating, e.g. 16 tracks with
+/// 64 notes on and off each.
+pub const MIDI_SCHEDULER_CAPACITY: usize = 2048;
+
+/// Holds back MIDI events until the cycle they are due in. Its buffers are
+/// allocated up front, so that audio callbacks do not allocate as long as
+/// no more than its capacity of events are held at a time.
+#[derive(Debug)]
+pub struct MidiScheduler {
+    /// Events along with the frame they are due at, in order of arrival
+    pending: VecDeque<(Frames, ChannelVoiceEvent)>,
+    /// Events returned by the last `take_due`, reused from cycle to cycle
+    due: Vec<(Frames, ChannelVoiceEvent)>,
+}
+
+impl Default for MidiScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiScheduler {
+    pub fn new() -> Self {
+        Self::with_capacity(MIDI_SCHEDULER_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            pending: VecDeque::with_capacity(capacity),
+            due: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, frame: Frames, event: ChannelVoiceEvent) {
+        self.pending.push_back((frame, event));
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Takes the events due before the end of the cycle of `n_frames` frames
+    /// starting at frame `cycle_start`, along with their offset within the
+    /// cycle. Late events are due right at the start of the cycle. Events at
+    /// the same offset keep their order of arrival.
+    pub fn take_due(
+        &mut self,
+        cycle_start: Frames,
+        n_frames: Frames,
+    ) -> &[(Frames, ChannelVoiceEvent)] {
+        let due = &mut self.due;
+        due.clear();
+        self.pending.retain(|(frame, event)| {
+            // Frame counts wrap around, so compare their distance
+            let offset = frame.wrapping_sub(cycle_start) as i32;
+            if offset >= n_frames as i32 {
+                return true;
+            }
+            let offset = offset.max(0) as Frames;
+            // Sorted as they come, as a stable sort would allocate
+            let idx = due.partition_point(|(due_offset, _)| *due_offset <= offset);
+            due.insert(idx, (offset, *event));
+            false
+        });
+        &self.due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::midi::ChannelVoiceEvent;
+
+    use super::{Frames, MidiScheduler, Timestamp};
+
+    const SAMPLE_RATE: u64 = 48_000;
+
+    fn event(key: u8) -> ChannelVoiceEvent {
+        ChannelVoiceEvent::NoteOn {
+            channel: 0,
+            key,
+            velocity: 100,
+        }
+    }
+
+    fn to_frames(timestamp: Timestamp) -> Frames {
+        (timestamp * SAMPLE_RATE / 1_000_000) as Frames
+    }
+
+    #[test]
+    fn test_hold_back_until_cycle() {
+        let mut scheduler = MidiScheduler::new();
+        scheduler.push(300, event(1));
+        scheduler.push(100, event(2));
+        scheduler.push(100, event(3));
+        scheduler.push(50, event(4));
+        // Late
+        assert_eq!(scheduler.take_due(64, 32), vec![(0, event(4))]);
+        assert_eq!(
+            scheduler.take_due(96, 32),
+            vec![(4, event(2)), (4, event(3))]
+        );
+        assert_eq!(scheduler.len(), 1);
+        assert!(scheduler.take_due(128, 128).is_empty());
+        assert_eq!(scheduler.take_due(256, 256), vec![(44, event(1))]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_keeps_capacity() {
+        let mut scheduler = MidiScheduler::with_capacity(8);
+        let capacities = (scheduler.pending.capacity(), scheduler.due.capacity());
+        for (frame, key) in [(40, 1), (20, 2), (40, 3), (10, 4), (20, 5), (30, 6)] {
+            scheduler.push(frame, event(key));
+        }
+        assert_eq!(
+            scheduler.take_due(0, 64),
+            vec![
+                (10, event(4)),
+                (20, event(2)),
+                (20, event(5)),
+                (30, event(6)),
+                (40, event(1)),
+                (40, event(3))
+            ]
+        );
+        assert_eq!(
+            (scheduler.pending.capacity(), scheduler.due.capacity()),
+            capacities
+        );
+    }
+
+    #[test]
+    fn test_frames_wrap_around() {
+        let mut scheduler = MidiScheduler::new();
+        scheduler.push(10, event(1));
+        scheduler.push(Frames::MAX - 10, event(2));
+        assert_eq!(
+            scheduler.take_due(Frames::MAX - 100, 256),
+            vec![(90, event(2)), (111, event(1))]
+        );
+    }
+
+    /// Events arrive ahead of time at irregular times and are
+    /// written cycle by cycle. Every event is to be heard within a frame of
+    /// its target time, where writing them at the start of the cycle they
+    /// arrive in would be off by up to a whole cycle.
+    #[test]
+    fn test_jitter() {
+        let n_frames: Frames = 256;
+        let cycle_usecs = n_frames as u64 * 1_000_000 / SAMPLE_RATE;
+        let mut scheduler = MidiScheduler::new();
+        // Target times of 16th notes at 137 BPM
+        let targets: Vec<Timestamp> = (1..200).map(|i| i * 60_000_000 / 137 / 4).collect();
+        let mut arrivals = targets.iter().enumerate().map(|(i, target)| {
+            // A cycle to 10ms more ahead of time
+            let ahead = cycle_usecs + (i as u64 * 7_919) % 10_000;
+            (target.saturating_sub(ahead), *target)
+        });
+        let mut next = arrivals.next();
+        let mut written = vec![];
+        let mut cycle_start: Frames = 0;
+        while written.len() < targets.len() {
+            // Events arriving before the cycle
+            while let Some((arrival, target)) = next
+                && arrival < cycle_start as u64 * 1_000_000 / SAMPLE_RATE
+            {
+                scheduler.push(to_frames(target), event(0));
+                next = arrivals.next();
+            }
+            for (offset, _) in scheduler.take_due(cycle_start, n_frames) {
+                written.push(cycle_start + offset);
+            }
+            cycle_start += n_frames;
+        }
+        let jitter: Vec<_> = written
+            .iter()
+            .zip(targets.iter())
+            .map(|(frame, target)| {
+                let heard = *frame as u64 * 1_000_000 / SAMPLE_RATE;
+                heard.abs_diff(*target)
+            })
+            .collect();
+        let max_jitter = jitter.iter().max().unwrap();
+        assert!(
+            *max_jitter <= 1_000_000 / SAMPLE_RATE + 1,
+            "Max jitter: {}us",
+            max_jitter
+        );
+    }
+}
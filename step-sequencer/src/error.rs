@@ -1,4 +1,7 @@
-use crate::{launcher::Command, midi::note::ParseNoteError};
+use crate::{
+    launcher::Command,
+    midi::{chord::ParseChordError, note::ParseNoteError, Channel, Key},
+};
 use std::{io, num::ParseIntError};
 
 use thiserror::Error;
@@ -24,6 +27,16 @@ pub enum SSError {
     ParseIntError(#[from] ParseIntError),
     #[error("Parse note error: `{0}`")]
     ParseNoteError(#[from] ParseNoteError),
+    #[error("Parse chord error: `{0}`")]
+    ParseChordError(#[from] ParseChordError),
+    #[error("MIDI error: `{0}`")]
+    MidiError(#[from] MidiError),
+    #[error("Project file error: `{0}`")]
+    ProjectFileError(#[from] ProjectFileError),
+    #[error("MIDI file error: `{0}`")]
+    SMFError(#[from] SMFError),
+    #[error("Groove error: `{0}`")]
+    GrooveError(#[from] GrooveError),
     #[error("Channel recv error: `{0}`")]
     RecvError(#[from] crossbeam::channel::RecvError),
     #[error("Unsupported platform: `{0}`")]
@@ -44,6 +57,66 @@ pub enum CommandError {
     CommandExecutionError(Command, String),
 }
 
+#[derive(Error, Debug, PartialEq)]
+pub enum MidiError {
+    #[error("Invalid MIDI channel: `{0}`")]
+    InvalidChannel(u8),
+    #[error("Invalid MIDI data byte: `{0:#04x}`")]
+    InvalidDataByte(u8),
+    #[error("Invalid pitch bend value: `{0}`")]
+    InvalidPitchBend(u16),
+    #[error("Data byte without status: `{0:#04x}`")]
+    UnexpectedDataByte(u8),
+    #[error("Unsupported MIDI status: `{0:#04x}`")]
+    UnsupportedStatus(u8),
+    #[error("Incomplete MIDI message with status `{0:#04x}`")]
+    IncompleteMessage(u8),
+    #[error("System Exclusive message without end")]
+    UnterminatedSysEx,
+    #[error("End of System Exclusive message without start")]
+    UnexpectedSysExEnd,
+    #[error("Buffer too small for MIDI message: `{needed}` bytes needed, `{available}` available")]
+    BufferTooSmall { needed: usize, available: usize },
+}
+
+#[derive(Error, Debug)]
+pub enum ProjectFileError {
+    #[error("JSON error: `{0}`")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Unrecognized project file format: `{0}`")]
+    InvalidFormat(String),
+    #[error("Unsupported project file version: `{0}`")]
+    UnsupportedVersion(u64),
+}
+
+#[derive(Error, Debug)]
+pub enum GrooveError {
+    #[error("JSON error: `{0}`")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Groove template has no steps: `{0}`")]
+    EmptyTemplate(String),
+}
+
+#[derive(Error, Debug)]
+pub enum SMFError {
+    #[error("Invalid MIDI file: `{0}`")]
+    InvalidFile(String),
+    #[error("Unsupported MIDI file format: `{0}`")]
+    UnsupportedFormat(u16),
+    #[error("Unsupported time division: `{0:#06x}`")]
+    UnsupportedDivision(u16),
+    #[error("Invalid import grid: `{0}`")]
+    InvalidGrid(String),
+    #[error("MIDI file too long, over `{0}` steps per track")]
+    TooManySteps(usize),
+    #[error("Unquantizable note: key `{key}` on channel `{channel}` at tick `{ticks}`")]
+    UnquantizableNote {
+        channel: Channel,
+        key: Key,
+        ticks: u64,
+    },
+}
+
 #[cfg(feature = "coreaudio")]
 impl From<coreaudio::sys::OSStatus> for SSError {
     fn from(value: coreaudio::sys::OSStatus) -> Self {
@@ -0,0 +1,65 @@
+//! Euclidean rhythms: `hits` onsets spread over `steps` steps as evenly
+//! as possible, e.g. 3 over 8 for the tresillo `x..x..x.`.
+
+/// Returns which of `steps` steps are hits when distributing `hits` of them
+/// evenly, shifted `rotation` steps later. The first hit is on the first step
+/// without rotation. Hits beyond the number of steps are ignored.
+pub fn euclidean_pattern(hits: usize, steps: usize, rotation: usize) -> Vec<bool> {
+    if steps == 0 {
+        return vec![];
+    }
+    let hits = hits.min(steps);
+    let rotation = rotation % steps;
+    (0..steps)
+        .map(|idx| {
+            let unrotated = (idx + steps - rotation) % steps;
+            (unrotated * hits) % steps < hits
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::euclidean_pattern;
+
+    fn pattern_string(hits: usize, steps: usize, rotation: usize) -> String {
+        euclidean_pattern(hits, steps, rotation)
+            .into_iter()
+            .map(|hit| if hit { 'x' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn test_euclidean_pattern() {
+        assert_eq!(pattern_string(3, 8, 0), "x..x..x.");
+        assert_eq!(pattern_string(4, 16, 0), "x...x...x...x...");
+        assert_eq!(pattern_string(5, 8, 0), "x.x.xx.x");
+        assert_eq!(pattern_string(3, 8, 2), "x.x..x..");
+        assert_eq!(pattern_string(3, 8, 10), pattern_string(3, 8, 2));
+        assert_eq!(pattern_string(0, 4, 1), "....");
+        assert_eq!(pattern_string(6, 4, 0), "xxxx");
+        assert_eq!(pattern_string(3, 0, 0), "");
+    }
+
+    #[test]
+    fn test_euclidean_pattern_spacing() {
+        // Hits are never more than one step closer or further apart than others
+        for steps in 1..=32 {
+            for hits in 1..=steps {
+                let positions: Vec<_> = euclidean_pattern(hits, steps, 0)
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(idx, hit)| hit.then_some(idx))
+                    .collect();
+                assert_eq!(positions.len(), hits);
+                let gaps: Vec<_> = positions
+                    .iter()
+                    .zip(positions.iter().cycle().skip(1))
+                    .map(|(a, b)| (b + steps - a - 1) % steps + 1)
+                    .collect();
+                let (min, max) = (gaps.iter().min().unwrap(), gaps.iter().max().unwrap());
+                assert!(max - min <= 1, "{} over {}: {:?}", hits, steps, gaps);
+            }
+        }
+    }
+}
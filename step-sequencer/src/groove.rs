@@ -0,0 +1,158 @@
+//! Swing and groove templates.
+//!
+//! Both shift steps off the straight grid by a fraction of a step, so that
+//! the resulting `BeatTime`s stay exact. Swing delays every second step,
+//! while a groove template holds timing and velocity offsets for a cycle of
+//! any number of steps. Steps are counted from the start of the timeline
+//! rather than the start of a track, so that the groove stays in phase with
+//! other tracks even if the track length does not divide the cycle.
+
+use std::{fs, path::Path};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    error::GrooveError,
+    midi::Velocity,
+    project::{parse_fraction, F},
+    SSResult,
+};
+
+pub type GrooveLibrary = IndexMap<String, GrooveTemplate>;
+
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GrooveStep {
+    /// Offset from the grid as a fraction of a step, e.g. "1/12" or "-0.05"
+    #[serde(
+        default,
+        serialize_with = "serialize_fraction",
+        deserialize_with = "deserialize_fraction"
+    )]
+    pub timing: F,
+    /// Added to the velocity of the step
+    #[serde(default)]
+    pub velocity: i8,
+}
+
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GrooveTemplate {
+    pub steps: Vec<GrooveStep>,
+}
+
+/// Offsets are limited to one step in either direction.
+fn max_offset() -> F {
+    F::from(1)
+}
+
+fn serialize_fraction<S: Serializer>(value: &F, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+fn deserialize_fraction<'de, D: Deserializer<'de>>(deserializer: D) -> Result<F, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_fraction(&s).ok_or_else(|| serde::de::Error::custom(format!("Invalid fraction: {}", s)))
+}
+
+/// Reads a groove library, i.e. a JSON object mapping names to templates:
+///
+/// ```json
+/// { "MPC 58%": { "steps": [{}, { "timing": "2/25", "velocity": -12 }] } }
+/// ```
+pub fn load_groove_library(path: impl AsRef<Path>) -> SSResult<GrooveLibrary> {
+    let library: GrooveLibrary =
+        serde_json::from_str(&fs::read_to_string(path)?).map_err(GrooveError::from)?;
+    if let Some((name, _)) = library.iter().find(|(_, t)| t.steps.is_empty()) {
+        return Err(GrooveError::EmptyTemplate(name.clone()).into());
+    }
+    Ok(library)
+}
+
+/// Swing and groove template in effect for a track.
+#[derive(Clone, Copy, Default)]
+pub struct Groove<'a> {
+    /// Delay of every second step as a fraction of a step
+    pub swing: F,
+    pub template: Option<&'a GrooveTemplate>,
+}
+
+impl Groove<'_> {
+    fn template_step(&self, step: usize) -> Option<&GrooveStep> {
+        self.template
+            .filter(|template| !template.steps.is_empty())
+            .map(|template| &template.steps[step % template.steps.len()])
+    }
+
+    /// Offset of the `step`th step from the grid as a fraction of a step.
+    pub fn timing(&self, step: usize) -> F {
+        let swing = if step % 2 == 1 {
+            self.swing
+        } else {
+            F::from(0)
+        };
+        let timing = self.template_step(step).map_or(F::from(0), |s| s.timing);
+        (swing + timing).clamp(-max_offset(), max_offset())
+    }
+
+    pub fn velocity(&self, step: usize, velocity: Velocity) -> Velocity {
+        let offset = self.template_step(step).map_or(0, |s| s.velocity);
+        (velocity as i16 + offset as i16).clamp(1, 127) as Velocity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        error::{GrooveError, SSError},
+        project::F,
+    };
+
+    use super::{load_groove_library, Groove, GrooveStep, GrooveTemplate};
+
+    #[test]
+    fn test_groove_timing_and_velocity() {
+        let template = GrooveTemplate {
+            steps: vec![
+                GrooveStep::default(),
+                GrooveStep::default(),
+                GrooveStep {
+                    timing: -F::new(1u64, 10u64),
+                    velocity: -100,
+                },
+            ],
+        };
+        let groove = Groove {
+            swing: F::new(1u64, 3u64),
+            template: Some(&template),
+        };
+        assert_eq!(groove.timing(0), F::from(0));
+        assert_eq!(groove.timing(1), F::new(1u64, 3u64));
+        assert_eq!(groove.timing(2), -F::new(1u64, 10u64));
+        // Swung and shifted by the template at once
+        assert_eq!(groove.timing(5), F::new(7u64, 30u64));
+        assert_eq!(groove.velocity(4, 100), 100);
+        assert_eq!(groove.velocity(5, 80), 1);
+    }
+
+    #[test]
+    fn test_load_groove_library() {
+        let path = std::env::temp_dir().join(format!("{}.json", crate::id::new_id()));
+        fs::write(
+            &path,
+            r#"{ "Shuffle": { "steps": [{}, { "timing": "1/6", "velocity": -10 }] } }"#,
+        )
+        .unwrap();
+        let library = load_groove_library(&path).unwrap();
+        assert_eq!(library["Shuffle"].steps[1].timing, F::new(1u64, 6u64));
+        assert_eq!(library["Shuffle"].steps[1].velocity, -10);
+
+        fs::write(&path, r#"{ "Empty": { "steps": [] } }"#).unwrap();
+        assert!(matches!(
+            load_groove_library(&path),
+            Err(SSError::GrooveError(GrooveError::EmptyTemplate(name))) if name == "Empty"
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,15 +1,25 @@
 use std::fmt;
+use std::path::PathBuf;
 use std::{rc::Rc, sync::RwLockWriteGuard};
 
 use log::{error, info};
 
 use crate::{
+    arpeggiator::Arpeggiator,
+    arrangement::{Section, SectionLength},
     engine::{create_ss_client, SSClient},
     beatmaker::{BeatMaker, BeatMakerSubscription},
-    drum_track::DrumTrack,
+    drum_track::{ChordBeat, Condition, DrumTrack, DrumTrackBeat, Gate, PatternSlot, Ratchet},
     error::SSError,
-    midi::{note::Note, Channel, Velocity},
-    project::{Project, Tempo, TrackMap, F},
+    groove::load_groove_library,
+    midi::{
+        note::{Note, PitchClass},
+        Channel, Controller, Velocity,
+    },
+    project::{ProbabilityMode, Project, Tempo, TimeSignature, TrackMap, F},
+    scale::{Degree, Scale},
+    tempo::{Ramp, TempoPoint},
+    smf,
     timeline::Timeline,
     SSResult,
 };
@@ -19,16 +29,86 @@ pub enum Command {
     PlayOrPause,
     Stop,
     Quit,
+    /// Sets the tempo at the start of the tempo map
     ChangeTempo(Tempo),
+    /// Sets the tempo from a beat on, optionally ramping to the next point
+    SetTempoPoint(F, Tempo, Ramp),
+    /// Removes the point of the tempo map at a beat
+    RemoveTempoPoint(F),
+    /// Sets the time signature from a bar on, counting bars from zero
+    SetTimeSignature(usize, TimeSignature),
+    /// Removes the time signature change at a bar
+    RemoveTimeSignature(usize),
     AddTrack,
     RemoveTrack(usize),
     RenameTrack(usize, String),
     ToggleBeat(usize, usize),
+    /// Sets a step of a track to a degree of the scale of the project
+    SetDegree(usize, usize, Degree),
+    /// Sets a step of a track to a chord in the given octave and voicing
+    SetChord(usize, usize, ChordBeat),
+    /// Strums the notes of a step of a track by a fraction of a step
+    SetStrum(usize, usize, F),
+    /// Locks a controller to a value on a step of a track, or unlocks it
+    SetCCLock(usize, usize, Controller, Option<u8>),
+    /// Locks the pitch bend of a step of a track, or unlocks it
+    SetPitchBendLock(usize, usize, Option<u16>),
+    /// Sets the value a controller of a track is restored to, or removes it
+    SetCCDefault(usize, Controller, Option<u8>),
+    /// Nudges a step of a track by a fraction of a step
+    SetOffset(usize, usize, F),
+    /// Sets the chance of a step of a track to fire in percent
+    SetProbability(usize, usize, u8),
+    SetCondition(usize, usize, Condition),
+    SetRatchet(usize, usize, Ratchet),
+    ToggleFill,
+    ToggleMute(usize),
+    /// Solos a track, or stops doing so. While any track is soloed,
+    /// only soloed tracks are played.
+    ToggleSolo(usize),
     Resize(usize, usize),
+    /// Fills a track with a Euclidean rhythm of the given hits, steps and rotation
+    Euclidean(usize, usize, usize, usize),
     TempoScale(usize, F),
     SetChannel(usize, Channel),
     SetVelocity(usize, Velocity),
     SetNote(usize, Note),
+    SetGate(usize, Gate),
+    /// Arpeggiates the steps of a track, or stops doing so
+    SetArpeggiator(usize, Option<Arpeggiator>),
+    /// Holds notes on an arpeggiated track, or releases them if none are given
+    HoldNotes(usize, Vec<Note>),
+    /// Queues a pattern of a track, or of all tracks if none is given,
+    /// to be switched to at the next loop boundary
+    SelectPattern(Option<usize>, PatternSlot),
+    /// Copies a pattern of a track, or of all tracks, to another slot
+    CopyPattern(Option<usize>, PatternSlot, PatternSlot),
+    ClearPattern(Option<usize>, PatternSlot),
+    /// Appends a section playing a pattern on all tracks to the song
+    AddSection(String, PatternSlot, SectionLength),
+    RemoveSection(usize),
+    /// Sets the pattern a track plays in a section of the song
+    SetSectionPattern(usize, usize, PatternSlot),
+    ToggleSong,
+    /// Repeats a section of the song until the loop is turned off
+    LoopSection(Option<usize>),
+    SetSwing(F),
+    /// Overrides the swing of the project for a track, or stops doing so
+    SetTrackSwing(usize, Option<F>),
+    SetGroove(usize, Option<String>),
+    SetSeed(u64),
+    SetProbabilityMode(ProbabilityMode),
+    /// Sets the key of the project, transposing all melodic tracks
+    SetKey(PitchClass),
+    SetScale(Scale),
+    /// Adds the groove templates of a groove library file to the project
+    LoadGrooves(PathBuf),
+    SaveProject(PathBuf),
+    LoadProject(PathBuf),
+    ExportMIDI(PathBuf),
+    /// Replaces the project with the drum patterns of a MIDI file,
+    /// quantized to steps of the given length in beats.
+    ImportMIDI(PathBuf, F),
     Debug,
 }
 
@@ -73,6 +153,33 @@ fn get_track_mut<'a>(
     tracks.next()
 }
 
+/// Applies `f` to the track at `track_idx`, or to every track if it is `None`.
+/// Returns false if the track does not exist.
+fn for_tracks_mut(
+    track_map: &mut RwLockWriteGuard<TrackMap>,
+    track_idx: Option<usize>,
+    mut f: impl FnMut(&mut DrumTrack),
+) -> bool {
+    match track_idx {
+        Some(track_idx) => match get_track_mut(track_map, track_idx) {
+            Some(track) => {
+                f(track);
+                true
+            }
+            None => false,
+        },
+        None => {
+            track_map.values_mut().for_each(f);
+            true
+        }
+    }
+}
+
+/// Track number for logs, "*" for all tracks
+fn display_track_idx(track_idx: Option<usize>) -> String {
+    track_idx.map_or("*".to_string(), |track_idx| (track_idx + 1).to_string())
+}
+
 fn get_track<'a>(
     track_map: &'a mut RwLockWriteGuard<TrackMap>,
     track_idx: usize,
@@ -83,10 +190,13 @@ fn get_track<'a>(
 
 impl SSLauncher {
     pub fn new() -> Self {
-        let timeline = Timeline::new();
         let beatmaker = BeatMaker::new();
         let project = Project::new();
         let ss_client = create_ss_client(beatmaker.subscribe());
+        let timeline = match ss_client.clock_source() {
+            Some(clock) => Timeline::with_clock(clock),
+            None => Timeline::new(),
+        };
         Self {
             timeline,
             beatmaker,
@@ -97,8 +207,11 @@ impl SSLauncher {
 
     pub fn start(&mut self) -> SSResult<()> {
         self.ss_client.start()?;
-        self.beatmaker
-            .start(&self.project, self.timeline.subscribe());
+        self.beatmaker.start(
+            &self.project,
+            self.timeline.subscribe(),
+            self.timeline.clock(),
+        );
         Ok(())
     }
 
@@ -126,7 +239,66 @@ impl SSLauncher {
             Command::ChangeTempo(tempo) => {
                 info!("Global tempo -> {}", tempo);
                 let project_settings = self.project.project_settings();
-                project_settings.write().unwrap().tempo = tempo;
+                project_settings
+                    .write()
+                    .unwrap()
+                    .tempo_map
+                    .set_initial_tempo(tempo);
+            }
+            Command::SetTempoPoint(beat, tempo, ramp) => {
+                info!("Tempo at beat {} -> {} ({:?})", beat, tempo, ramp);
+                let project_settings = self.project.project_settings();
+                let inserted = project_settings
+                    .write()
+                    .unwrap()
+                    .tempo_map
+                    .insert(TempoPoint::new(beat, tempo, ramp));
+                if !inserted {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Tempo point at beat {} is before the start", beat),
+                        ),
+                    ));
+                }
+            }
+            Command::RemoveTempoPoint(beat) => {
+                info!("Remove tempo point at beat {}", beat);
+                let project_settings = self.project.project_settings();
+                let removed = project_settings.write().unwrap().tempo_map.remove(beat);
+                if removed.is_none() {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("No removable tempo point at beat {}", beat),
+                        ),
+                    ));
+                }
+            }
+            Command::SetTimeSignature(bar, time_signature) => {
+                info!("Time signature at bar {} -> {}", bar + 1, time_signature);
+                let project_settings = self.project.project_settings();
+                project_settings
+                    .write()
+                    .unwrap()
+                    .meter
+                    .insert(bar, time_signature);
+                // Sections measured in bars change length
+                self.beatmaker.reload_beat_sorter();
+            }
+            Command::RemoveTimeSignature(bar) => {
+                info!("Remove time signature at bar {}", bar + 1);
+                let project_settings = self.project.project_settings();
+                let removed = project_settings.write().unwrap().meter.remove(bar);
+                if removed.is_none() {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("No removable time signature at bar {}", bar + 1),
+                        ),
+                    ));
+                }
+                self.beatmaker.reload_beat_sorter();
             }
             Command::AddTrack => {
                 info!("Add track");
@@ -171,6 +343,209 @@ impl SSLauncher {
                 ))?;
                 track.toggle_beat(beat);
             }
+            Command::SetDegree(track_idx, beat, degree) => {
+                info!("[🛤️ {}] Degree {} -> {}", track_idx + 1, beat + 1, degree);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.assign_beat(beat, DrumTrackBeat::Degree(degree));
+            }
+            Command::SetChord(track_idx, beat, ref chord) => {
+                info!(
+                    "[🛤️ {}] Chord {} -> {} ({})",
+                    track_idx + 1,
+                    beat + 1,
+                    chord.chord,
+                    chord.voicing
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command.clone(),
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.assign_beat(beat, DrumTrackBeat::Chord(chord.clone()));
+            }
+            Command::SetStrum(track_idx, beat, strum) => {
+                info!("[🛤️ {}] Strum {} -> {}", track_idx + 1, beat + 1, strum);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_strum(beat, strum);
+            }
+            Command::SetCCLock(track_idx, beat, controller, value) => {
+                info!(
+                    "[🛤️ {}] CC {} lock {} -> {:?}",
+                    track_idx + 1,
+                    controller,
+                    beat + 1,
+                    value
+                );
+                if controller > 127 || value.is_some_and(|value| value > 127) {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            "Controllers and values range from 0 to 127".to_string(),
+                        ),
+                    ));
+                }
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_cc_lock(beat, controller, value);
+            }
+            Command::SetPitchBendLock(track_idx, beat, value) => {
+                info!(
+                    "[🛤️ {}] Pitch bend lock {} -> {:?}",
+                    track_idx + 1,
+                    beat + 1,
+                    value
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_pitch_bend_lock(beat, value);
+            }
+            Command::SetCCDefault(track_idx, controller, value) => {
+                info!(
+                    "[🛤️ {}] CC {} default -> {:?}",
+                    track_idx + 1,
+                    controller,
+                    value
+                );
+                if controller > 127 || value.is_some_and(|value| value > 127) {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            "Controllers and values range from 0 to 127".to_string(),
+                        ),
+                    ));
+                }
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_cc_default(controller, value);
+            }
+            Command::SetOffset(track_idx, beat, offset) => {
+                info!("[🛤️ {}] Offset @ {} -> {}", track_idx + 1, beat + 1, offset);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_offset(beat, offset);
+            }
+            Command::SetProbability(track_idx, beat, probability) => {
+                info!(
+                    "[🛤️ {}] Probability @ {} -> {}%",
+                    track_idx + 1,
+                    beat + 1,
+                    probability
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_probability(beat, probability);
+            }
+            Command::SetCondition(track_idx, beat, condition) => {
+                info!(
+                    "[🛤️ {}] Condition @ {} -> {}",
+                    track_idx + 1,
+                    beat + 1,
+                    condition
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_condition(beat, condition);
+            }
+            Command::SetRatchet(track_idx, beat, ratchet) => {
+                info!(
+                    "[🛤️ {}] Ratchet @ {} -> {:?}",
+                    track_idx + 1,
+                    beat + 1,
+                    ratchet
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_ratchet(beat, ratchet);
+            }
+            Command::ToggleFill => {
+                let project_settings = self.project.project_settings();
+                let mut project_settings = project_settings.write().unwrap();
+                project_settings.fill = !project_settings.fill;
+                info!("Fill -> {}", project_settings.fill);
+            }
+            Command::ToggleMute(track_idx) => {
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_muted(!track.is_muted());
+                info!("[🛤️ {}] Mute -> {}", track_idx + 1, track.is_muted());
+            }
+            Command::ToggleSolo(track_idx) => {
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_soloed(!track.is_soloed());
+                info!("[🛤️ {}] Solo -> {}", track_idx + 1, track.is_soloed());
+            }
             Command::Resize(track_idx, size) => {
                 info!("[🛤️ {}] Resize -> {}", track_idx + 1, size);
                 let binding = self.project.tracks();
@@ -183,6 +558,24 @@ impl SSLauncher {
                 ))?;
                 track.resize(size);
             }
+            Command::Euclidean(track_idx, hits, steps, rotation) => {
+                info!(
+                    "[🛤️ {}] Euclidean -> E({}, {}) rotated by {}",
+                    track_idx + 1,
+                    hits,
+                    steps,
+                    rotation
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.fill_euclidean(hits, steps, rotation);
+            }
             Command::TempoScale(track_idx, scale) => {
                 info!("[🛤️ {}] Tempo scale -> {}", track_idx + 1, scale);
                 let binding = self.project.tracks();
@@ -232,6 +625,280 @@ impl SSLauncher {
                 ))?;
                 track.set_default_velocity(velocity);
             }
+            Command::SetGate(track_idx, gate) => {
+                info!("[🛤️ {}] Gate -> {:?}", track_idx + 1, gate);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_gate(gate);
+            }
+            Command::SetArpeggiator(track_idx, arpeggiator) => {
+                info!("[🛤️ {}] Arpeggiator -> {:?}", track_idx + 1, arpeggiator);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_arpeggiator(arpeggiator);
+            }
+            Command::HoldNotes(track_idx, ref notes) => {
+                info!("[🛤️ {}] Hold notes -> {:?}", track_idx + 1, notes);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command.clone(),
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.hold_notes(notes.clone());
+            }
+            Command::SelectPattern(track_idx, slot) => {
+                info!("[🛤️ {}] Pattern -> {}", display_track_idx(track_idx), slot);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                if !for_tracks_mut(&mut trackmap, track_idx, |track| track.queue_pattern(slot)) {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Track {} does not exist", track_idx.unwrap()),
+                        ),
+                    ));
+                }
+            }
+            Command::CopyPattern(track_idx, from, to) => {
+                info!(
+                    "[🛤️ {}] Copy pattern {} -> {}",
+                    display_track_idx(track_idx),
+                    from,
+                    to
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                if !for_tracks_mut(&mut trackmap, track_idx, |track| {
+                    track.copy_pattern(from, to)
+                }) {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Track {} does not exist", track_idx.unwrap()),
+                        ),
+                    ));
+                }
+            }
+            Command::ClearPattern(track_idx, slot) => {
+                info!(
+                    "[🛤️ {}] Clear pattern {}",
+                    display_track_idx(track_idx),
+                    slot
+                );
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                if !for_tracks_mut(&mut trackmap, track_idx, |track| track.clear_pattern(slot)) {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Track {} does not exist", track_idx.unwrap()),
+                        ),
+                    ));
+                }
+            }
+            Command::AddSection(ref name, slot, length) => {
+                info!("Add section {} ({}, {:?})", name, slot, length);
+                let project_settings = self.project.project_settings();
+                project_settings
+                    .write()
+                    .unwrap()
+                    .arrangement
+                    .sections
+                    .push(Section::new(name, slot, length));
+                // A song that has ended carries on with the new section
+                self.beatmaker.reload_beat_sorter();
+            }
+            Command::RemoveSection(section_idx) => {
+                info!("Remove section {}", section_idx + 1);
+                let project_settings = self.project.project_settings();
+                let mut project_settings = project_settings.write().unwrap();
+                let arrangement = &mut project_settings.arrangement;
+                if section_idx >= arrangement.sections.len() {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Section {} does not exist", section_idx),
+                        ),
+                    ));
+                }
+                arrangement.sections.remove(section_idx);
+                arrangement.loop_section = match arrangement.loop_section {
+                    Some(idx) if idx == section_idx => None,
+                    Some(idx) if idx > section_idx => Some(idx - 1),
+                    loop_section => loop_section,
+                };
+                drop(project_settings);
+                self.beatmaker.reload_beat_sorter();
+            }
+            Command::SetSectionPattern(section_idx, track_idx, slot) => {
+                info!(
+                    "[🛤️ {}] Section {} pattern -> {}",
+                    track_idx + 1,
+                    section_idx + 1,
+                    slot
+                );
+                let Some(track_id) = self
+                    .project
+                    .tracks()
+                    .read()
+                    .unwrap()
+                    .get_index(track_idx)
+                    .map(|(id, _)| *id)
+                else {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Track {} does not exist", track_idx),
+                        ),
+                    ));
+                };
+                let project_settings = self.project.project_settings();
+                let mut project_settings = project_settings.write().unwrap();
+                let section = project_settings
+                    .arrangement
+                    .sections
+                    .get_mut(section_idx)
+                    .ok_or(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Section {} does not exist", section_idx),
+                        ),
+                    ))?;
+                section.track_patterns.insert(track_id, slot);
+            }
+            Command::ToggleSong => {
+                let project_settings = self.project.project_settings();
+                let mut project_settings = project_settings.write().unwrap();
+                let arrangement = &mut project_settings.arrangement;
+                arrangement.enabled = !arrangement.enabled;
+                info!("Song mode -> {}", arrangement.enabled);
+                drop(project_settings);
+                self.beatmaker.reload_beat_sorter();
+            }
+            Command::LoopSection(section_idx) => {
+                info!("Loop section -> {:?}", section_idx.map(|idx| idx + 1));
+                let project_settings = self.project.project_settings();
+                let mut project_settings = project_settings.write().unwrap();
+                let arrangement = &mut project_settings.arrangement;
+                if let Some(section_idx) = section_idx
+                    && section_idx >= arrangement.sections.len()
+                {
+                    return Err(SSError::CommandError(
+                        crate::error::CommandError::CommandExecutionError(
+                            command,
+                            format!("Section {} does not exist", section_idx),
+                        ),
+                    ));
+                }
+                arrangement.loop_section = section_idx;
+                drop(project_settings);
+                self.beatmaker.reload_beat_sorter();
+            }
+            Command::SetSwing(swing) => {
+                info!("Global swing -> {}", swing);
+                let project_settings = self.project.project_settings();
+                project_settings.write().unwrap().swing = swing;
+            }
+            Command::SetTrackSwing(track_idx, swing) => {
+                info!("[🛤️ {}] Swing -> {:?}", track_idx + 1, swing);
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command,
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_swing(swing);
+            }
+            Command::SetGroove(track_idx, ref groove) => {
+                info!("[🛤️ {}] Groove -> {:?}", track_idx + 1, groove);
+                if let Some(name) = groove {
+                    let project_settings = self.project.project_settings();
+                    if !project_settings.read().unwrap().grooves.contains_key(name) {
+                        return Err(SSError::CommandError(
+                            crate::error::CommandError::CommandExecutionError(
+                                command.clone(),
+                                format!("Groove {} does not exist", name),
+                            ),
+                        ));
+                    }
+                }
+                let binding = self.project.tracks();
+                let mut trackmap = binding.write().unwrap();
+                let track = get_track_mut(&mut trackmap, track_idx).ok_or(SSError::CommandError(
+                    crate::error::CommandError::CommandExecutionError(
+                        command.clone(),
+                        format!("Track {} does not exist", track_idx),
+                    ),
+                ))?;
+                track.set_groove(groove.clone());
+            }
+            Command::SetSeed(seed) => {
+                info!("Seed -> {}", seed);
+                let project_settings = self.project.project_settings();
+                project_settings.write().unwrap().seed = seed;
+            }
+            Command::SetProbabilityMode(probability_mode) => {
+                info!("Probability mode -> {:?}", probability_mode);
+                let project_settings = self.project.project_settings();
+                project_settings.write().unwrap().probability_mode = probability_mode;
+            }
+            Command::SetKey(root) => {
+                info!("Key -> {}", root);
+                let project_settings = self.project.project_settings();
+                project_settings.write().unwrap().tonality.root = root;
+            }
+            Command::SetScale(ref scale) => {
+                info!("Scale -> {}", scale);
+                let project_settings = self.project.project_settings();
+                project_settings.write().unwrap().tonality.scale = scale.clone();
+            }
+            Command::LoadGrooves(ref path) => {
+                info!("Load grooves <- {}", path.display());
+                let library = load_groove_library(path)?;
+                let project_settings = self.project.project_settings();
+                project_settings.write().unwrap().grooves.extend(library);
+            }
+            Command::SaveProject(ref path) => {
+                info!("Save project -> {}", path.display());
+                self.project.save(path)?;
+            }
+            Command::LoadProject(ref path) => {
+                info!("Load project <- {}", path.display());
+                let project = Project::load(path)?;
+                self.project.replace(project);
+                self.beatmaker.reload_beat_sorter();
+            }
+            Command::ExportMIDI(ref path) => {
+                info!("Export MIDI -> {}", path.display());
+                smf::export::write_project(&self.project, path)?;
+            }
+            Command::ImportMIDI(ref path, grid) => {
+                info!("Import MIDI <- {} (grid: {})", path.display(), grid);
+                let options = smf::import::ImportOptions {
+                    grid,
+                    ..Default::default()
+                };
+                let project = smf::import::read_project(path, &options)?;
+                self.project.replace(project);
+                self.beatmaker.reload_beat_sorter();
+            }
             _ => {
                 error!("Unsupported command: {}", command);
             }
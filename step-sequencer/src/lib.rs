@@ -1,13 +1,23 @@
 pub mod engine;
+pub mod arpeggiator;
+pub mod arrangement;
 pub mod beatmaker;
+pub mod clock;
 pub mod consts;
 pub mod drum_track;
 pub mod error;
+pub mod euclidean;
+pub mod groove;
 pub mod id;
 pub mod launcher;
+pub mod meter;
 pub mod midi;
 pub mod models;
 pub mod project;
+pub mod project_file;
+pub mod scale;
+pub mod smf;
+pub mod tempo;
 pub mod timeline;
 mod util;
 
@@ -0,0 +1,305 @@
+//! Meter and musical positions.
+//!
+//! The meter of a project is its initial time signature along with changes
+//! of the time signature, each starting on a bar. `BeatTime` counts quarter
+//! notes, while a `Position` counts bars, beats of the time signature in
+//! effect and ticks within a beat, e.g. the 2nd dotted quarter of a bar of
+//! 6/8 is beat 3 of 6.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    beatmaker::beat_time::BeatTime,
+    project::{TimeSignature, F},
+};
+
+/// Resolution of a `Position`, in ticks per beat of the time signature.
+pub const TICKS_PER_BEAT: u32 = 960;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct MeterChange {
+    /// Bar from which on the time signature applies, counting from zero
+    pub bar: usize,
+    pub time_signature: TimeSignature,
+}
+
+/// Changes ordered by bar, the first one at bar 0.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "Vec<MeterChange>", into = "Vec<MeterChange>")]
+pub struct Meter {
+    changes: Vec<MeterChange>,
+}
+
+/// Position in bars, beats and ticks, counting from zero.
+/// Displayed counting bars and beats from one, like "5:3:480".
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Default, Serialize)]
+pub struct Position {
+    pub bar: usize,
+    pub beat: usize,
+    pub tick: u32,
+}
+
+impl Position {
+    pub fn new(bar: usize, beat: usize, tick: u32) -> Self {
+        Self { bar, beat, tick }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.bar + 1, self.beat + 1, self.tick)
+    }
+}
+
+impl Meter {
+    pub fn new(time_signature: TimeSignature) -> Self {
+        Self {
+            changes: vec![MeterChange {
+                bar: 0,
+                time_signature,
+            }],
+        }
+    }
+
+    pub fn changes(&self) -> &[MeterChange] {
+        &self.changes
+    }
+
+    /// Time signature of the first bars, before any change
+    pub fn initial(&self) -> TimeSignature {
+        self.changes[0].time_signature
+    }
+
+    /// Changes the time signature from `bar` on, replacing any change there.
+    pub fn insert(&mut self, bar: usize, time_signature: TimeSignature) {
+        let change = MeterChange {
+            bar,
+            time_signature,
+        };
+        match self.changes.binary_search_by_key(&bar, |c| c.bar) {
+            Ok(i) => self.changes[i] = change,
+            Err(i) => self.changes.insert(i, change),
+        }
+    }
+
+    /// Removes the change at `bar`. The change at bar 0 cannot be removed.
+    pub fn remove(&mut self, bar: usize) -> Option<MeterChange> {
+        match self.changes.binary_search_by_key(&bar, |c| c.bar) {
+            Ok(i) if i > 0 => Some(self.changes.remove(i)),
+            _ => None,
+        }
+    }
+
+    /// Changes along with the beat time they start at
+    fn starts(&self) -> impl Iterator<Item = (F, &MeterChange)> {
+        let mut start = F::from(0);
+        let mut previous: Option<&MeterChange> = None;
+        self.changes.iter().map(move |change| {
+            if let Some(previous) = previous {
+                start +=
+                    previous.time_signature.beats_per_bar() * F::from(change.bar - previous.bar);
+            }
+            previous = Some(change);
+            (start, change)
+        })
+    }
+
+    /// Last change starting at or before `beat_time`, along with its start
+    fn change_at(&self, beat_time: F) -> (F, &MeterChange) {
+        self.starts()
+            .take_while(|(start, _)| *start <= beat_time)
+            .last()
+            .unwrap_or((F::from(0), &self.changes[0]))
+    }
+
+    pub fn time_signature_at(&self, beat_time: BeatTime) -> TimeSignature {
+        self.change_at(beat_time.as_fraction()).1.time_signature
+    }
+
+    /// Position of `beat_time`, rounded down to a tick.
+    pub fn position(&self, beat_time: BeatTime) -> Position {
+        let beat_time = beat_time.as_fraction().max(F::from(0));
+        let (start, change) = self.change_at(beat_time);
+        let time_signature = change.time_signature;
+        let bars = ((beat_time - start) / time_signature.beats_per_bar()).floor();
+        let within_bar = beat_time - start - bars * time_signature.beats_per_bar();
+        let beats = (within_bar / time_signature.beat_length()).floor();
+        let ticks = (within_bar - beats * time_signature.beat_length())
+            / time_signature.beat_length()
+            * F::from(TICKS_PER_BEAT);
+        Position {
+            bar: change.bar + BeatTime::new(bars).integral(),
+            beat: BeatTime::new(beats).integral(),
+            tick: BeatTime::new(ticks).integral() as u32,
+        }
+    }
+
+    pub fn beat_time(&self, position: Position) -> BeatTime {
+        let (start, change) = self
+            .starts()
+            .take_while(|(_, change)| change.bar <= position.bar)
+            .last()
+            .unwrap_or((F::from(0), &self.changes[0]));
+        let time_signature = change.time_signature;
+        BeatTime::new(
+            start
+                + time_signature.beats_per_bar() * F::from(position.bar - change.bar)
+                + time_signature.beat_length()
+                    * (F::from(position.beat) + F::new(position.tick, TICKS_PER_BEAT)),
+        )
+    }
+
+    pub fn bar_start(&self, bar: usize) -> BeatTime {
+        self.beat_time(Position::new(bar, 0, 0))
+    }
+
+    /// Length in beats of `bars` bars from `start`, each as long as the time
+    /// signature in effect where it starts.
+    pub fn bars_length(&self, start: BeatTime, bars: usize) -> F {
+        let mut end = start;
+        for _ in 0..bars {
+            end = end.add_fraction(self.time_signature_at(end).beats_per_bar());
+        }
+        end.as_fraction() - start.as_fraction()
+    }
+
+    /// Whole bars from `start` to `beat_time`, measured like `bars_length`.
+    pub fn bars_between(&self, start: BeatTime, beat_time: BeatTime) -> usize {
+        let mut bars = 0;
+        let mut end = start;
+        loop {
+            end = end.add_fraction(self.time_signature_at(end).beats_per_bar());
+            if end > beat_time {
+                return bars;
+            }
+            bars += 1;
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new(TimeSignature::default())
+    }
+}
+
+/// Sorts the changes and makes sure one starts at bar 0. Fails on a time
+/// signature without beats or with a denominator other than a power of two.
+impl TryFrom<Vec<MeterChange>> for Meter {
+    type Error = String;
+
+    fn try_from(changes: Vec<MeterChange>) -> Result<Self, Self::Error> {
+        if let Some(change) = changes.iter().find(|c| !c.time_signature.is_valid()) {
+            return Err(format!(
+                "Invalid time signature {} at bar {}",
+                change.time_signature, change.bar
+            ));
+        }
+        let mut meter = match changes.first() {
+            Some(change) => Self::new(change.time_signature),
+            None => Self::default(),
+        };
+        for change in changes {
+            meter.insert(change.bar, change.time_signature);
+        }
+        Ok(meter)
+    }
+}
+
+impl From<Meter> for Vec<MeterChange> {
+    fn from(meter: Meter) -> Self {
+        meter.changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        beatmaker::beat_time::BeatTime,
+        project::{TimeSignature, F},
+    };
+
+    use super::{Meter, MeterChange, Position};
+
+    fn beat_time(numer: u64, denom: u64) -> BeatTime {
+        BeatTime::new(F::new(numer, denom))
+    }
+
+    fn time_signature(numerator: u8, denominator: u8) -> TimeSignature {
+        TimeSignature {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// 2 bars of 4/4, 2 bars of 6/8, then 7/8
+    fn meter() -> Meter {
+        let mut meter = Meter::default();
+        meter.insert(4, time_signature(7, 8));
+        meter.insert(2, time_signature(6, 8));
+        meter
+    }
+
+    #[test]
+    fn test_position() {
+        let meter = meter();
+        assert_eq!(meter.position(BeatTime::zero()), Position::new(0, 0, 0));
+        assert_eq!(meter.position(beat_time(37, 4)), Position::new(2, 2, 480));
+        assert_eq!(meter.position(beat_time(7, 1)), Position::new(1, 3, 0));
+        assert_eq!(meter.position(beat_time(12, 1)), Position::new(3, 2, 0));
+        // 8 + 6 beats in, at the first 7/8 bar
+        assert_eq!(meter.position(beat_time(14, 1)), Position::new(4, 0, 0));
+        assert_eq!(meter.position(beat_time(35, 2)), Position::new(5, 0, 0));
+        // Rounded down to a tick
+        assert_eq!(meter.position(beat_time(1, 3000)), Position::new(0, 0, 0));
+        assert_eq!(meter.position(beat_time(1, 3000)).to_string(), "1:1:0");
+    }
+
+    #[test]
+    fn test_position_round_trip() {
+        let meter = meter();
+        for beat_time in (0..200u64).map(|i| BeatTime::new(F::new(i, 8u64))) {
+            let position = meter.position(beat_time);
+            assert_eq!(meter.beat_time(position), beat_time, "{}", position);
+        }
+        assert_eq!(meter.beat_time(Position::new(3, 5, 480)), beat_time(55, 4));
+        assert_eq!(meter.bar_start(5), beat_time(35, 2));
+    }
+
+    #[test]
+    fn test_bars() {
+        let meter = meter();
+        assert_eq!(meter.bars_length(BeatTime::zero(), 5), F::new(35u64, 2u64));
+        assert_eq!(meter.bars_length(beat_time(4, 1), 2), F::from(7));
+        assert_eq!(meter.bars_between(BeatTime::zero(), beat_time(14, 1)), 4);
+        assert_eq!(meter.bars_between(BeatTime::zero(), beat_time(27, 2)), 3);
+        assert_eq!(
+            meter.time_signature_at(beat_time(11, 1)),
+            time_signature(6, 8)
+        );
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut meter = Meter::try_from(vec![]).unwrap();
+        assert_eq!(meter.initial(), TimeSignature::default());
+        meter.insert(0, time_signature(3, 4));
+        meter.insert(8, time_signature(5, 4));
+        assert_eq!(meter.initial(), time_signature(3, 4));
+        assert!(meter.remove(0).is_none());
+        assert!(meter.remove(8).is_some());
+        assert_eq!(meter.changes().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_changes() {
+        let change = |bar, numerator, denominator| MeterChange {
+            bar,
+            time_signature: time_signature(numerator, denominator),
+        };
+        assert!(Meter::try_from(vec![change(0, 3, 4), change(4, 0, 4)]).is_err());
+        assert!(Meter::try_from(vec![change(0, 3, 0)]).is_err());
+        let json = r#"[{"bar":0,"time_signature":{"numerator":0,"denominator":4}}]"#;
+        assert!(serde_json::from_str::<Meter>(json).is_err());
+    }
+}
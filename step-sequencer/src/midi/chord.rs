@@ -0,0 +1,247 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use super::{
+    note::{Note, PitchClass},
+    Key,
+};
+
+/// Suffixes of chord symbols and the semitones of their notes above the root
+const QUALITIES: &[(&str, &[u8])] = &[
+    ("", &[0, 4, 7]),
+    ("maj", &[0, 4, 7]),
+    ("M", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("min", &[0, 3, 7]),
+    ("-", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("+", &[0, 4, 8]),
+    ("sus2", &[0, 2, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("sus", &[0, 5, 7]),
+    ("6", &[0, 4, 7, 9]),
+    ("m6", &[0, 3, 7, 9]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("M7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("min7", &[0, 3, 7, 10]),
+    ("-7", &[0, 3, 7, 10]),
+    ("mMaj7", &[0, 3, 7, 11]),
+    ("mM7", &[0, 3, 7, 11]),
+    ("m7b5", &[0, 3, 6, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("7sus4", &[0, 5, 7, 10]),
+    ("add9", &[0, 4, 7, 14]),
+    ("9", &[0, 4, 7, 10, 14]),
+    ("maj9", &[0, 4, 7, 11, 14]),
+    ("m9", &[0, 3, 7, 10, 14]),
+    ("11", &[0, 4, 7, 10, 14, 17]),
+    ("m11", &[0, 3, 7, 10, 14, 17]),
+    ("13", &[0, 4, 7, 10, 14, 21]),
+];
+
+/// Chord symbol like "Cmaj7/E" or "Dm9"
+#[derive(PartialEq, Clone, Debug)]
+pub struct Chord {
+    root: PitchClass,
+    quality: &'static str,
+    /// Bass note of a slash chord
+    bass: Option<PitchClass>,
+}
+
+/// How the notes of a chord are spread over the octaves
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum Voicing {
+    /// Stacked upwards from the root
+    #[default]
+    Close,
+    /// Close, with the second highest note an octave down
+    Drop2,
+    /// Root an octave down, and every second note above it an octave up
+    Spread,
+}
+
+impl Voicing {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "close" => Some(Self::Close),
+            "drop2" | "drop-2" => Some(Self::Drop2),
+            "spread" => Some(Self::Spread),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Voicing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Close => write!(f, "close"),
+            Self::Drop2 => write!(f, "drop2"),
+            Self::Spread => write!(f, "spread"),
+        }
+    }
+}
+
+impl Chord {
+    /// Semitones of the notes above the root, without the bass of a slash chord
+    pub fn intervals(&self) -> &'static [u8] {
+        QUALITIES
+            .iter()
+            .find(|(quality, _)| *quality == self.quality)
+            .map(|(_, intervals)| *intervals)
+            .unwrap()
+    }
+
+    /// Notes of the chord with its root in `octave`, from the lowest up.
+    /// The bass of a slash chord is played below all other notes, which
+    /// leave it out. Notes beyond the range of MIDI keys are left out.
+    pub fn notes(&self, octave: i8, voicing: Voicing) -> Vec<Note> {
+        let root = 24 + 12 * octave as i32 + Into::<i8>::into(self.root) as i32;
+        let mut keys: Vec<i32> = self
+            .intervals()
+            .iter()
+            .map(|interval| root + *interval as i32)
+            .collect();
+        match voicing {
+            Voicing::Close => {}
+            Voicing::Drop2 => {
+                if keys.len() >= 2 {
+                    let idx = keys.len() - 2;
+                    keys[idx] -= 12;
+                }
+            }
+            Voicing::Spread => {
+                keys[0] -= 12;
+                for key in keys.iter_mut().skip(2).step_by(2) {
+                    *key += 12;
+                }
+            }
+        }
+        keys.sort();
+        if let Some(bass) = self.bass {
+            let bass_class = Into::<i8>::into(bass) as i32;
+            keys.retain(|key| key.rem_euclid(12) != bass_class);
+            let lowest = keys.first().copied().unwrap_or(root);
+            let mut bass = lowest - (lowest - bass_class).rem_euclid(12);
+            if bass == lowest {
+                bass -= 12;
+            }
+            keys.insert(0, bass);
+        }
+        keys.into_iter()
+            .filter(|key| (0..=127).contains(key))
+            .map(|key| Note::from(key as Key))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.root, self.quality)?;
+        if let Some(bass) = self.bass {
+            write!(f, "/{}", bass)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Error)]
+pub struct ParseChordError(String);
+
+impl std::fmt::Display for ParseChordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid chord: {0}", self.0)
+    }
+}
+
+/// Splits a leading pitch class like "C", "F#" or "Bb" off `s`.
+fn split_pitch_class(s: &str) -> Option<(PitchClass, &str)> {
+    [2, 1].into_iter().find_map(|len| {
+        let (pitch_class, rest) = (s.get(..len)?, s.get(len..)?);
+        Some((pitch_class.parse().ok()?, rest))
+    })
+}
+
+impl FromStr for Chord {
+    type Err = ParseChordError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseChordError(format!("Invalid chord representation: `{0}`", s));
+        let (symbol, bass) = match s.rsplit_once('/') {
+            Some((symbol, bass)) => (symbol, Some(bass.parse().map_err(|_| err())?)),
+            None => (s, None),
+        };
+        let (root, quality) = split_pitch_class(symbol).ok_or_else(err)?;
+        let quality = QUALITIES
+            .iter()
+            .map(|(q, _)| *q)
+            .find(|q| *q == quality)
+            .ok_or_else(err)?;
+        Ok(Self {
+            root,
+            quality,
+            bass,
+        })
+    }
+}
+
+impl Serialize for Chord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Chord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_chord() {
+        assert_eq!("Cmaj7/E".parse::<Chord>().unwrap().to_string(), "Cmaj7/E");
+        assert_eq!(
+            "F#m7b5".parse::<Chord>().unwrap().intervals(),
+            &[0, 3, 6, 10]
+        );
+        assert_eq!("Bb".parse::<Chord>().unwrap().intervals(), &[0, 4, 7]);
+        assert!("Hmaj7".parse::<Chord>().is_err());
+        assert!("Cfoo".parse::<Chord>().is_err());
+        assert!("C/X".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn chord_voicings() {
+        let cmaj7: Chord = "Cmaj7".parse().unwrap();
+        assert_eq!(
+            cmaj7.notes(3, Voicing::Close),
+            vec![Note::C(3), Note::E(3), Note::G(3), Note::B(3)]
+        );
+        assert_eq!(
+            cmaj7.notes(3, Voicing::Drop2),
+            vec![Note::G(2), Note::C(3), Note::E(3), Note::B(3)]
+        );
+        assert_eq!(
+            cmaj7.notes(3, Voicing::Spread),
+            vec![Note::C(2), Note::E(3), Note::B(3), Note::G(4)]
+        );
+        let slash: Chord = "Cmaj7/E".parse().unwrap();
+        assert_eq!(
+            slash.notes(3, Voicing::Close),
+            vec![Note::E(2), Note::C(3), Note::G(3), Note::B(3)]
+        );
+        let dm9: Chord = "Dm9".parse().unwrap();
+        assert_eq!(
+            dm9.notes(3, Voicing::Close),
+            vec![Note::D(3), Note::F(3), Note::A(3), Note::C(4), Note::E(4)]
+        );
+    }
+}
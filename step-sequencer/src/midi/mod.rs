@@ -1,12 +1,26 @@
+pub mod chord;
 pub mod note;
+pub mod parser;
 
-use std::io::{self, Write};
+use crate::error::MidiError;
 
 use self::ChannelVoiceEvent::*;
 
-pub type Key = u8; // TODO:
+/// MIDI key number, 0-127.
+pub type Key = u8;
+/// MIDI channel, 0-15.
 pub type Channel = u8;
 pub type Velocity = u8;
+/// Control Change controller number, 0-127.
+pub type Controller = u8;
+
+/// Center of the 14-bit pitch bend range, i.e. no bend.
+pub const PITCH_BEND_CENTER: u16 = 0x2000;
+/// Largest value of the 14-bit pitch bend range.
+pub const PITCH_BEND_MAX: u16 = 0x3FFF;
+
+pub(crate) const SYSEX_START: u8 = 0xF0;
+pub(crate) const SYSEX_END: u8 = 0xF7;
 
 #[derive(Clone, Copy, PartialEq, Debug, serde::Serialize)]
 pub enum ChannelVoiceEvent {
@@ -20,66 +34,206 @@ pub enum ChannelVoiceEvent {
         key: Key,
         velocity: Velocity,
     },
+    PolyphonicAftertouch {
+        channel: Channel,
+        key: Key,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: Channel,
+        controller: Controller,
+        value: u8,
+    },
+    ProgramChange {
+        channel: Channel,
+        program: u8,
+    },
+    ChannelAftertouch {
+        channel: Channel,
+        pressure: u8,
+    },
+    /// 14-bit bend amount, `PITCH_BEND_CENTER` being no bend.
+    PitchBend {
+        channel: Channel,
+        value: u16,
+    },
 }
 
+/// System real-time messages and System Exclusive messages.
+/// These are not bound to a channel.
+#[derive(Clone, PartialEq, Debug, serde::Serialize)]
+pub enum SystemEvent {
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    /// Payload of a System Exclusive message, without the framing
+    /// `0xF0`/`0xF7` bytes.
+    SysEx(Vec<u8>),
+}
+
+/// Any MIDI event the sequencer can encode and decode.
+#[derive(Clone, PartialEq, Debug, serde::Serialize)]
+pub enum MidiEvent {
+    ChannelVoice(ChannelVoiceEvent),
+    System(SystemEvent),
+}
+
+/// A raw MIDI message: a status byte followed by its data bytes.
 pub struct Message {
-    cmd: u8,
-    channel: u8,
+    status: u8,
     data_bytes: Vec<u8>,
 }
 
+fn check_channel(channel: Channel) -> Result<Channel, MidiError> {
+    if channel > 0x0F {
+        return Err(MidiError::InvalidChannel(channel));
+    }
+    Ok(channel)
+}
+
+fn check_data_byte(byte: u8) -> Result<u8, MidiError> {
+    if byte > 0x7F {
+        return Err(MidiError::InvalidDataByte(byte));
+    }
+    Ok(byte)
+}
+
 impl ChannelVoiceEvent {
-    pub fn to_message(&self) -> Message {
-        let (cmd, channel, data_bytes) = match *self {
-            NoteOn {
-                channel,
-                key,
-                velocity,
-            } => (0b1001, channel, &[key, velocity]),
-            NoteOff {
-                channel,
-                key,
-                velocity,
-            } => (0b1000, channel, &[key, velocity]),
+    pub fn channel(&self) -> Channel {
+        match *self {
+            NoteOn { channel, .. }
+            | NoteOff { channel, .. }
+            | PolyphonicAftertouch { channel, .. }
+            | ControlChange { channel, .. }
+            | ProgramChange { channel, .. }
+            | ChannelAftertouch { channel, .. }
+            | PitchBend { channel, .. } => channel,
+        }
+    }
+
+    pub fn to_message(&self) -> Result<Message, MidiError> {
+        let (cmd, data_bytes) = match *self {
+            NoteOn { key, velocity, .. } => (0b1001, vec![key, velocity]),
+            NoteOff { key, velocity, .. } => (0b1000, vec![key, velocity]),
+            PolyphonicAftertouch { key, pressure, .. } => (0b1010, vec![key, pressure]),
+            ControlChange {
+                controller, value, ..
+            } => (0b1011, vec![controller, value]),
+            ProgramChange { program, .. } => (0b1100, vec![program]),
+            ChannelAftertouch { pressure, .. } => (0b1101, vec![pressure]),
+            PitchBend { value, .. } => {
+                if value > PITCH_BEND_MAX {
+                    return Err(MidiError::InvalidPitchBend(value));
+                }
+                // Least significant 7 bits first
+                (0b1110, vec![(value & 0x7F) as u8, (value >> 7) as u8])
+            }
         };
-        Message {
-            cmd,
-            channel,
-            data_bytes: data_bytes.to_vec(),
+        let channel = check_channel(self.channel())?;
+        for byte in &data_bytes {
+            check_data_byte(*byte)?;
         }
+        Ok(Message {
+            status: cmd << 4 | channel,
+            data_bytes,
+        })
+    }
+
+    pub fn to_data(&self) -> Result<Vec<u8>, MidiError> {
+        let msg = self.to_message()?;
+        msg.to_data()
+    }
+
+    pub fn write_to_buffer(&self, buf: &mut [u8]) -> Result<(), MidiError> {
+        let msg = self.to_message()?;
+        msg.write_to_buffer(buf)
+    }
+}
+
+impl SystemEvent {
+    pub fn to_message(&self) -> Result<Message, MidiError> {
+        let (status, data_bytes) = match self {
+            SystemEvent::TimingClock => (0xF8, vec![]),
+            SystemEvent::Start => (0xFA, vec![]),
+            SystemEvent::Continue => (0xFB, vec![]),
+            SystemEvent::Stop => (0xFC, vec![]),
+            SystemEvent::ActiveSensing => (0xFE, vec![]),
+            SystemEvent::SysEx(payload) => {
+                let mut data_bytes = Vec::with_capacity(payload.len() + 1);
+                for byte in payload {
+                    data_bytes.push(check_data_byte(*byte)?);
+                }
+                data_bytes.push(SYSEX_END);
+                (SYSEX_START, data_bytes)
+            }
+        };
+        Ok(Message { status, data_bytes })
     }
 
-    pub fn to_data(&self) -> io::Result<Vec<u8>> {
-        let msg = self.to_message();
+    pub fn to_data(&self) -> Result<Vec<u8>, MidiError> {
+        let msg = self.to_message()?;
         msg.to_data()
     }
 
-    pub fn write_to_buffer(&self, buf: &mut [u8]) -> io::Result<()> {
-        let msg = self.to_message();
+    pub fn write_to_buffer(&self, buf: &mut [u8]) -> Result<(), MidiError> {
+        let msg = self.to_message()?;
         msg.write_to_buffer(buf)
     }
 }
 
+impl MidiEvent {
+    pub fn to_data(&self) -> Result<Vec<u8>, MidiError> {
+        match self {
+            MidiEvent::ChannelVoice(event) => event.to_data(),
+            MidiEvent::System(event) => event.to_data(),
+        }
+    }
+}
+
+impl From<ChannelVoiceEvent> for MidiEvent {
+    fn from(value: ChannelVoiceEvent) -> Self {
+        MidiEvent::ChannelVoice(value)
+    }
+}
+
+impl From<SystemEvent> for MidiEvent {
+    fn from(value: SystemEvent) -> Self {
+        MidiEvent::System(value)
+    }
+}
+
 impl Message {
-    fn to_data(&self) -> io::Result<Vec<u8>> {
-        let mut result = vec![0; self.data_bytes.len() + 1];
+    /// Number of bytes of the encoded message
+    fn len(&self) -> usize {
+        self.data_bytes.len() + 1
+    }
+
+    fn to_data(&self) -> Result<Vec<u8>, MidiError> {
+        let mut result = vec![0; self.len()];
         self.write_to_buffer(&mut result)?;
         Ok(result)
     }
 
-    // Write is impl'd on &mut [u8], so write_all is actually called
-    // on &mut &mut buf. That's why buf itself needs to be declared `mut`.
-    fn write_to_buffer(&self, mut buf: &mut [u8]) -> io::Result<()> {
-        let mut data = vec![0; self.data_bytes.len() + 1];
-        data[0] = self.cmd << 4 | self.channel;
-        data[1..].clone_from_slice(&self.data_bytes);
-        (&mut buf).write_all(&data)
+    fn write_to_buffer(&self, buf: &mut [u8]) -> Result<(), MidiError> {
+        if buf.len() < self.len() {
+            return Err(MidiError::BufferTooSmall {
+                needed: self.len(),
+                available: buf.len(),
+            });
+        }
+        buf[0] = self.status;
+        buf[1..self.len()].copy_from_slice(&self.data_bytes);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ChannelVoiceEvent;
+    use crate::error::MidiError;
+
+    use super::{ChannelVoiceEvent, SystemEvent, PITCH_BEND_CENTER, PITCH_BEND_MAX, SYSEX_END};
 
     #[test]
     fn test_write_note_on() {
@@ -91,4 +245,170 @@ mod tests {
         let data = event.to_data().unwrap();
         assert_eq!(data, &[0b10010001, 0b00001010, 0b00101010]);
     }
+
+    #[test]
+    fn test_write_channel_voice_events() {
+        let cases = [
+            (
+                ChannelVoiceEvent::NoteOff {
+                    channel: 0,
+                    key: 60,
+                    velocity: 0,
+                },
+                vec![0x80, 60, 0],
+            ),
+            (
+                ChannelVoiceEvent::NoteOn {
+                    channel: 15,
+                    key: 127,
+                    velocity: 127,
+                },
+                vec![0x9F, 127, 127],
+            ),
+            (
+                ChannelVoiceEvent::PolyphonicAftertouch {
+                    channel: 2,
+                    key: 64,
+                    pressure: 100,
+                },
+                vec![0xA2, 64, 100],
+            ),
+            (
+                ChannelVoiceEvent::ControlChange {
+                    channel: 3,
+                    controller: 74,
+                    value: 12,
+                },
+                vec![0xB3, 74, 12],
+            ),
+            (
+                ChannelVoiceEvent::ProgramChange {
+                    channel: 4,
+                    program: 5,
+                },
+                vec![0xC4, 5],
+            ),
+            (
+                ChannelVoiceEvent::ChannelAftertouch {
+                    channel: 5,
+                    pressure: 99,
+                },
+                vec![0xD5, 99],
+            ),
+            (
+                ChannelVoiceEvent::PitchBend {
+                    channel: 6,
+                    value: PITCH_BEND_CENTER,
+                },
+                vec![0xE6, 0x00, 0x40],
+            ),
+            (
+                ChannelVoiceEvent::PitchBend {
+                    channel: 7,
+                    value: 0,
+                },
+                vec![0xE7, 0x00, 0x00],
+            ),
+            (
+                ChannelVoiceEvent::PitchBend {
+                    channel: 8,
+                    value: PITCH_BEND_MAX,
+                },
+                vec![0xE8, 0x7F, 0x7F],
+            ),
+            (
+                ChannelVoiceEvent::PitchBend {
+                    channel: 9,
+                    value: 0x1234,
+                },
+                vec![0xE9, 0x34, 0x24],
+            ),
+        ];
+        for (event, expected) in cases {
+            assert_eq!(event.to_data().unwrap(), expected, "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn test_write_system_events() {
+        let cases = [
+            (SystemEvent::TimingClock, vec![0xF8]),
+            (SystemEvent::Start, vec![0xFA]),
+            (SystemEvent::Continue, vec![0xFB]),
+            (SystemEvent::Stop, vec![0xFC]),
+            (SystemEvent::ActiveSensing, vec![0xFE]),
+            (SystemEvent::SysEx(vec![]), vec![0xF0, 0xF7]),
+            (
+                SystemEvent::SysEx(vec![0x7E, 0x7F, 0x09, 0x01]),
+                vec![0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7],
+            ),
+        ];
+        for (event, expected) in cases {
+            assert_eq!(event.to_data().unwrap(), expected, "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn test_reject_invalid_values() {
+        let invalid_channel = ChannelVoiceEvent::NoteOn {
+            channel: 16,
+            key: 60,
+            velocity: 100,
+        };
+        assert!(matches!(
+            invalid_channel.to_data(),
+            Err(MidiError::InvalidChannel(16))
+        ));
+        let invalid_key = ChannelVoiceEvent::NoteOff {
+            channel: 0,
+            key: 128,
+            velocity: 0,
+        };
+        assert!(matches!(
+            invalid_key.to_data(),
+            Err(MidiError::InvalidDataByte(128))
+        ));
+        let invalid_value = ChannelVoiceEvent::ControlChange {
+            channel: 0,
+            controller: 1,
+            value: 200,
+        };
+        assert!(matches!(
+            invalid_value.to_data(),
+            Err(MidiError::InvalidDataByte(200))
+        ));
+        let invalid_bend = ChannelVoiceEvent::PitchBend {
+            channel: 0,
+            value: PITCH_BEND_MAX + 1,
+        };
+        assert!(matches!(
+            invalid_bend.to_data(),
+            Err(MidiError::InvalidPitchBend(0x4000))
+        ));
+        let invalid_sysex = SystemEvent::SysEx(vec![0x01, SYSEX_END]);
+        assert!(matches!(
+            invalid_sysex.to_data(),
+            Err(MidiError::InvalidDataByte(SYSEX_END))
+        ));
+    }
+
+    #[test]
+    fn test_write_to_buffer() {
+        let event = ChannelVoiceEvent::ProgramChange {
+            channel: 0,
+            program: 1,
+        };
+        let mut buf = [0u8; 4];
+        event.write_to_buffer(&mut buf).unwrap();
+        assert_eq!(buf, [0xC0, 0x01, 0x00, 0x00]);
+
+        let mut buf = [0u8; 1];
+        assert!(matches!(
+            event.write_to_buffer(&mut buf),
+            Err(MidiError::BufferTooSmall {
+                needed: 2,
+                available: 1
+            })
+        ));
+    }
 }
@@ -1,14 +1,14 @@
 use std::{path::Display, str::FromStr, sync::LazyLock};
 
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use self::PitchClass::*;
 
 use super::Key;
 
-#[derive(PartialEq, Copy, Clone, Debug, Serialize)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum PitchClass {
     C,
     Cs,
@@ -57,6 +57,35 @@ impl std::fmt::Display for PitchClass {
     }
 }
 
+impl FromStr for PitchClass {
+    type Err = ParseNoteError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "c" | "C" => Ok(C),
+            "c#" | "C#" => Ok(Cs),
+            "db" | "Db" => Ok(Df),
+            "d" | "D" => Ok(D),
+            "d#" | "D#" => Ok(Ds),
+            "eb" | "Eb" => Ok(Ef),
+            "e" | "E" => Ok(E),
+            "f" | "F" => Ok(F),
+            "f#" | "F#" => Ok(Fs),
+            "gb" | "Gb" => Ok(Gf),
+            "g" | "G" => Ok(G),
+            "g#" | "G#" => Ok(Gs),
+            "ab" | "Ab" => Ok(Af),
+            "a" | "A" => Ok(A),
+            "a#" | "A#" => Ok(As),
+            "bb" | "Bb" => Ok(Bf),
+            "b" | "B" => Ok(B),
+            _ => Err(ParseNoteError(format!(
+                "Invalid pitch class representation: `{0}`",
+                s
+            ))),
+        }
+    }
+}
+
 impl Into<i8> for PitchClass {
     fn into(self) -> i8 {
         match self {
@@ -76,7 +105,7 @@ impl Into<i8> for PitchClass {
     }
 }
 
-#[derive(PartialEq, Copy, Clone, Debug, Serialize)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Note {
     pitch_class: PitchClass,
     octave: i8,
@@ -179,6 +208,17 @@ impl Into<Key> for Note {
     }
 }
 
+/// Spells black keys as sharps.
+impl From<Key> for Note {
+    fn from(key: Key) -> Self {
+        const PITCH_CLASSES: [PitchClass; 12] = [C, Cs, D, Ds, E, F, Fs, G, Gs, A, As, B];
+        Self {
+            pitch_class: PITCH_CLASSES[key as usize % 12],
+            octave: (key / 12) as i8 - 2,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -188,4 +228,15 @@ mod tests {
         assert_eq!("Gb-2".parse::<Note>().unwrap(), Note::Gf(-2));
         assert!("B1288".parse::<Note>().is_err());
     }
+
+    #[test]
+    fn key_to_note() {
+        assert_eq!(Note::from(0), Note::C(-2));
+        assert_eq!(Note::from(36), Note::C(1));
+        assert_eq!(Note::from(70), Note::As(3));
+        assert_eq!(Note::from(127), Note::G(8));
+        for key in 0..=127 {
+            assert_eq!(Into::<Key>::into(Note::from(key)), key);
+        }
+    }
 }
@@ -0,0 +1,397 @@
+//! Decoding of raw MIDI byte streams, e.g. from MIDI in-ports or sockets.
+
+use crate::error::MidiError;
+
+use super::{ChannelVoiceEvent, MidiEvent, SystemEvent, SYSEX_END, SYSEX_START};
+
+/// Number of data bytes following a channel voice status byte
+fn data_len(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        _ => 2,
+    }
+}
+
+fn real_time_event(byte: u8) -> Option<SystemEvent> {
+    match byte {
+        0xF8 => Some(SystemEvent::TimingClock),
+        0xFA => Some(SystemEvent::Start),
+        0xFB => Some(SystemEvent::Continue),
+        0xFC => Some(SystemEvent::Stop),
+        0xFE => Some(SystemEvent::ActiveSensing),
+        _ => None,
+    }
+}
+
+fn channel_voice_event(status: u8, data: &[u8]) -> ChannelVoiceEvent {
+    let channel = status & 0x0F;
+    match status & 0xF0 {
+        0x80 => ChannelVoiceEvent::NoteOff {
+            channel,
+            key: data[0],
+            velocity: data[1],
+        },
+        // Note on with velocity 0 is how many devices send note offs
+        0x90 if data[1] == 0 => ChannelVoiceEvent::NoteOff {
+            channel,
+            key: data[0],
+            velocity: 0,
+        },
+        0x90 => ChannelVoiceEvent::NoteOn {
+            channel,
+            key: data[0],
+            velocity: data[1],
+        },
+        0xA0 => ChannelVoiceEvent::PolyphonicAftertouch {
+            channel,
+            key: data[0],
+            pressure: data[1],
+        },
+        0xB0 => ChannelVoiceEvent::ControlChange {
+            channel,
+            controller: data[0],
+            value: data[1],
+        },
+        0xC0 => ChannelVoiceEvent::ProgramChange {
+            channel,
+            program: data[0],
+        },
+        0xD0 => ChannelVoiceEvent::ChannelAftertouch {
+            channel,
+            pressure: data[0],
+        },
+        _ => ChannelVoiceEvent::PitchBend {
+            channel,
+            value: data[0] as u16 | (data[1] as u16) << 7,
+        },
+    }
+}
+
+/// Decodes MIDI events from a byte stream, one byte at a time, so that
+/// messages may be split across buffers.
+///
+/// Running status is honored, real-time bytes are decoded wherever they
+/// occur, even in the middle of another message, and a note on with
+/// velocity 0 is decoded as a note off. Malformed input is reported as an
+/// error and skipped, after which decoding continues.
+#[derive(Debug, Default)]
+pub struct MidiParser {
+    running_status: Option<u8>,
+    data: Vec<u8>,
+    sysex: Option<Vec<u8>>,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Decodes the next byte of the stream, returning an event once the byte
+    /// completes one.
+    pub fn push(&mut self, byte: u8) -> Result<Option<MidiEvent>, MidiError> {
+        if byte >= 0xF8 {
+            return real_time_event(byte)
+                .map(|event| Some(event.into()))
+                .ok_or(MidiError::UnsupportedStatus(byte));
+        }
+        if byte & 0x80 == 0 {
+            return self.push_data(byte);
+        }
+        if byte == SYSEX_END {
+            if let Some(payload) = self.sysex.take() {
+                return Ok(Some(SystemEvent::SysEx(payload).into()));
+            }
+            let _ = self.abort();
+            return Err(MidiError::UnexpectedSysExEnd);
+        }
+        // Any other status byte ends the pending message
+        let pending = self.abort();
+        match byte {
+            SYSEX_START => self.sysex = Some(vec![]),
+            0x80..=0xEF => self.running_status = Some(byte),
+            _ => return Err(MidiError::UnsupportedStatus(byte)),
+        }
+        pending.map(|_| None)
+    }
+
+    /// Decodes `bytes`, collecting every event and error in stream order.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Result<MidiEvent, MidiError>> {
+        bytes
+            .iter()
+            .filter_map(|byte| self.push(*byte).transpose())
+            .collect()
+    }
+
+    /// Ends the stream, failing if it stopped in the middle of a message.
+    pub fn finish(&mut self) -> Result<(), MidiError> {
+        self.abort()
+    }
+
+    fn push_data(&mut self, byte: u8) -> Result<Option<MidiEvent>, MidiError> {
+        if let Some(payload) = self.sysex.as_mut() {
+            payload.push(byte);
+            return Ok(None);
+        }
+        let status = self
+            .running_status
+            .ok_or(MidiError::UnexpectedDataByte(byte))?;
+        self.data.push(byte);
+        if self.data.len() < data_len(status) {
+            return Ok(None);
+        }
+        let event = channel_voice_event(status, &self.data);
+        self.data.clear();
+        Ok(Some(event.into()))
+    }
+
+    /// Drops the pending message and running status.
+    fn abort(&mut self) -> Result<(), MidiError> {
+        let status = self.running_status.take();
+        if self.sysex.take().is_some() {
+            return Err(MidiError::UnterminatedSysEx);
+        }
+        if !self.data.is_empty() {
+            self.data.clear();
+            return Err(MidiError::IncompleteMessage(status.unwrap_or_default()));
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a complete byte stream into events.
+pub fn parse(bytes: &[u8]) -> Result<Vec<MidiEvent>, MidiError> {
+    let mut parser = MidiParser::new();
+    let events = parser.feed(bytes).into_iter().collect::<Result<_, _>>()?;
+    parser.finish()?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::MidiError,
+        midi::{ChannelVoiceEvent, MidiEvent, SystemEvent},
+        util::random,
+    };
+
+    use super::{parse, MidiParser};
+
+    fn note_on(channel: u8, key: u8, velocity: u8) -> MidiEvent {
+        ChannelVoiceEvent::NoteOn {
+            channel,
+            key,
+            velocity,
+        }
+        .into()
+    }
+
+    fn note_off(channel: u8, key: u8, velocity: u8) -> MidiEvent {
+        ChannelVoiceEvent::NoteOff {
+            channel,
+            key,
+            velocity,
+        }
+        .into()
+    }
+
+    fn random_event(seed: u64) -> MidiEvent {
+        let value = |field: u64, bound: u64| random::below(&[seed, field], bound) as u8;
+        let channel = value(1, 16);
+        match random::below(&[seed], 13) {
+            0 => note_on(channel, value(2, 128), value(3, 128)),
+            1 => note_off(channel, value(2, 128), value(3, 128)),
+            2 => ChannelVoiceEvent::PolyphonicAftertouch {
+                channel,
+                key: value(2, 128),
+                pressure: value(3, 128),
+            }
+            .into(),
+            3 => ChannelVoiceEvent::ControlChange {
+                channel,
+                controller: value(2, 128),
+                value: value(3, 128),
+            }
+            .into(),
+            4 => ChannelVoiceEvent::ProgramChange {
+                channel,
+                program: value(2, 128),
+            }
+            .into(),
+            5 => ChannelVoiceEvent::ChannelAftertouch {
+                channel,
+                pressure: value(2, 128),
+            }
+            .into(),
+            6 => ChannelVoiceEvent::PitchBend {
+                channel,
+                value: random::below(&[seed, 2], 0x4000) as u16,
+            }
+            .into(),
+            7 => SystemEvent::TimingClock.into(),
+            8 => SystemEvent::Start.into(),
+            9 => SystemEvent::Continue.into(),
+            10 => SystemEvent::Stop.into(),
+            11 => SystemEvent::ActiveSensing.into(),
+            _ => {
+                let len = random::below(&[seed, 2], 8);
+                let payload = (0..len).map(|i| value(3 + i, 128)).collect();
+                SystemEvent::SysEx(payload).into()
+            }
+        }
+    }
+
+    /// The event as it is decoded
+    fn normalized(event: MidiEvent) -> MidiEvent {
+        match event {
+            MidiEvent::ChannelVoice(ChannelVoiceEvent::NoteOn {
+                channel,
+                key,
+                velocity: 0,
+            }) => note_off(channel, key, 0),
+            event => event,
+        }
+    }
+
+    #[test]
+    fn test_parse_running_status() {
+        let bytes = [0x90, 60, 100, 64, 100, 60, 0, 0xB1, 7, 100, 10, 64];
+        let events = parse(&bytes).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                note_on(0, 60, 100),
+                note_on(0, 64, 100),
+                note_off(0, 60, 0),
+                ChannelVoiceEvent::ControlChange {
+                    channel: 1,
+                    controller: 7,
+                    value: 100
+                }
+                .into(),
+                ChannelVoiceEvent::ControlChange {
+                    channel: 1,
+                    controller: 10,
+                    value: 64
+                }
+                .into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_interleaved_real_time() {
+        let bytes = [0x90, 0xF8, 60, 0xFA, 100, 0xF0, 0x7E, 0xF8, 0x01, 0xF7];
+        let events = parse(&bytes).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                SystemEvent::TimingClock.into(),
+                SystemEvent::Start.into(),
+                note_on(0, 60, 100),
+                SystemEvent::TimingClock.into(),
+                SystemEvent::SysEx(vec![0x7E, 0x01]).into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_split_across_buffers() {
+        let mut parser = MidiParser::new();
+        assert!(parser.feed(&[0x92, 60]).is_empty());
+        assert_eq!(parser.feed(&[100, 62]), vec![Ok(note_on(2, 60, 100))]);
+        assert_eq!(parser.feed(&[90]), vec![Ok(note_on(2, 62, 90))]);
+        assert!(parser.finish().is_ok());
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let mut parser = MidiParser::new();
+        let results = parser.feed(&[
+            60, // data byte without status
+            0x90, 60, // note on cut short by the next status
+            0xC0, 5,    // program change
+            0xF7, // end without start
+            0xF4, // undefined system common
+            0x7F, // running status is cancelled
+            0xF9, // undefined real-time
+            0xF0, 1, 2, // SysEx cut short by the next status
+            0x80, 60, 0,
+        ]);
+        assert_eq!(
+            results,
+            vec![
+                Err(MidiError::UnexpectedDataByte(60)),
+                Err(MidiError::IncompleteMessage(0x90)),
+                Ok(ChannelVoiceEvent::ProgramChange {
+                    channel: 0,
+                    program: 5
+                }
+                .into()),
+                Err(MidiError::UnexpectedSysExEnd),
+                Err(MidiError::UnsupportedStatus(0xF4)),
+                Err(MidiError::UnexpectedDataByte(0x7F)),
+                Err(MidiError::UnsupportedStatus(0xF9)),
+                Err(MidiError::UnterminatedSysEx),
+                Ok(note_off(0, 60, 0)),
+            ]
+        );
+
+        assert!(matches!(
+            parse(&[0xE0, 0x00]),
+            Err(MidiError::IncompleteMessage(0xE0))
+        ));
+        assert!(matches!(
+            parse(&[0xF0, 0x00]),
+            Err(MidiError::UnterminatedSysEx)
+        ));
+    }
+
+    #[test]
+    fn test_fuzz_round_trip() {
+        for seed in 0..200 {
+            let events: Vec<_> = (0..50).map(|i| random_event(seed * 1000 + i)).collect();
+            let mut bytes = vec![];
+            let mut expected = vec![];
+            let mut running_status = None;
+            for (i, event) in events.iter().enumerate() {
+                let mut data = event.to_data().unwrap();
+                match event {
+                    MidiEvent::ChannelVoice(_) => {
+                        // Use running status whenever possible
+                        let status = data[0];
+                        if running_status == Some(status) {
+                            data.remove(0);
+                        }
+                        running_status = Some(status);
+                    }
+                    MidiEvent::System(SystemEvent::SysEx(_)) => running_status = None,
+                    MidiEvent::System(_) => {}
+                }
+                // Interleave a real-time message somewhere within the message
+                if random::below(&[seed, i as u64], 4) == 0 {
+                    let position = random::below(&[seed, i as u64, 1], data.len() as u64) as usize;
+                    data.insert(position, 0xF8);
+                    expected.push(SystemEvent::TimingClock.into());
+                }
+                bytes.extend(data);
+                expected.push(normalized(event.clone()));
+            }
+            assert_eq!(parse(&bytes).unwrap(), expected, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_garbage() {
+        for seed in 0..200 {
+            let bytes: Vec<_> = (0..256)
+                .map(|i| random::below(&[seed, i], 256) as u8)
+                .collect();
+            let mut parser = MidiParser::new();
+            for result in parser.feed(&bytes).into_iter().flatten() {
+                // Everything decoded has to encode again
+                assert!(result.to_data().is_ok(), "{:?}", result);
+            }
+            let _ = parser.finish();
+        }
+    }
+}
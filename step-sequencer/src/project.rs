@@ -1,39 +1,201 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    path::Path,
+    sync::{Arc, RwLock},
+};
 
-use fraction::Fraction;
+use fraction::{Fraction, Integer};
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    arrangement::{Arrangement, SongPosition},
     beatmaker::beat_time::BeatTime,
-    drum_track::DrumTrack,
+    drum_track::{DrumTrack, PatternSlot},
+    groove::{Groove, GrooveLibrary},
     id::{new_id, SSId},
+    meter::Meter,
+    project_file::ProjectFile,
+    scale::Tonality,
+    tempo::TempoMap,
+    SSResult,
 };
 
 pub type F = Fraction;
 
 pub type TrackMap = IndexMap<SSId, DrumTrack>;
-pub type Tempo = u16;
+/// Beats per minute, possibly fractional
+pub type Tempo = F;
 pub type TempoScale = F;
 
+/// Parses fractions like "1/12", "-1/8" or "0.05".
+pub fn parse_fraction(s: &str) -> Option<F> {
+    let s = s.trim();
+    match s.split_once('/') {
+        Some((numer, denom)) => {
+            let numer = numer.trim().parse::<i64>().ok()?;
+            let denom = denom.trim().parse::<u64>().ok().filter(|d| *d > 0)?;
+            let value = F::new(numer.unsigned_abs(), denom);
+            Some(if numer < 0 { -value } else { value })
+        }
+        None => s.parse::<F>().ok(),
+    }
+}
+
 pub struct Project {
     tracks: Arc<RwLock<TrackMap>>,
     project_settings: Arc<RwLock<ProjectSettings>>,
 }
 
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+impl TimeSignature {
+    /// Bar length in beats, where a beat is a quarter note.
+    pub fn beats_per_bar(&self) -> F {
+        F::new(self.numerator as u64 * 4, self.denominator as u64)
+    }
+
+    /// Length of a beat of the time signature, e.g. 1/2 for 6/8.
+    pub fn beat_length(&self) -> F {
+        F::new(4u64, self.denominator as u64)
+    }
+
+    /// Whether bars have beats and the denominator is a power of two
+    pub fn is_valid(&self) -> bool {
+        self.numerator > 0 && self.denominator.is_power_of_two()
+    }
+
+    /// Parses time signatures like "3/4", the denominator being a power of two.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (numerator, denominator) = s.trim().split_once('/')?;
+        let numerator = numerator.trim().parse::<u8>().ok().filter(|n| *n > 0)?;
+        let denominator = denominator
+            .trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|d| d.is_power_of_two())?;
+        Some(Self {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+impl std::fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+/// How steps with a probability below 100% are decided on
+#[derive(PartialEq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum ProbabilityMode {
+    /// Roll again on every loop of a track
+    #[default]
+    RerollEveryLoop,
+    /// Roll once and play the same steps on every loop of a track
+    FixedPerLoop,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ProjectSettings {
-    pub tempo: Tempo,
+    pub tempo_map: TempoMap,
+    pub meter: Meter,
+    /// Delay of every second step as a fraction of a step,
+    /// unless overridden by a track
+    pub swing: F,
+    pub grooves: GrooveLibrary,
+    /// Seed of the rolls of steps with a probability below 100%.
+    /// The same seed always leads to the same steps being played.
+    pub seed: u64,
+    pub probability_mode: ProbabilityMode,
+    /// Key and scale the degrees of melodic tracks are played in
+    pub tonality: Tonality,
+    pub arrangement: Arrangement,
+    /// Fill mode, which steps with a `Fill` or `NotFill` condition depend on
+    #[serde(skip)]
+    pub fill: bool,
+    #[serde(skip)]
     pub current_beat_time: Arc<RwLock<BeatTime>>,
+    /// Section and bar being played in song mode
+    #[serde(skip)]
+    pub song_position: Arc<RwLock<Option<SongPosition>>>,
+}
+
+impl ProjectSettings {
+    /// Takes over the persisted settings from `other` while keeping the
+    /// playback state (e.g. `current_beat_time`) shared with BeatMaker.
+    pub fn assign(&mut self, other: ProjectSettings) {
+        *self = ProjectSettings {
+            fill: self.fill,
+            current_beat_time: self.current_beat_time.clone(),
+            song_position: self.song_position.clone(),
+            ..other
+        };
+    }
+
+    /// Swing and groove template in effect for `track`. Tracks referring to
+    /// a groove template that does not exist play without one.
+    pub fn groove_for(&self, track: &DrumTrack) -> Groove<'_> {
+        Groove {
+            swing: track.get_swing().unwrap_or(self.swing),
+            template: track.get_groove().and_then(|name| self.grooves.get(name)),
+        }
+    }
+
+    /// Pattern the track `id` plays in the section being played in song
+    /// mode, rather than its active pattern.
+    pub fn song_pattern(&self, id: &SSId) -> Option<PatternSlot> {
+        let position = (*self.song_position.read().unwrap())?;
+        if !self.arrangement.is_active() {
+            return None;
+        }
+        self.arrangement
+            .sections
+            .get(position.section)
+            .map(|section| section.pattern_for(id))
+    }
 }
 
 impl Default for ProjectSettings {
     fn default() -> Self {
         Self {
-            tempo: 220,
+            tempo_map: TempoMap::default(),
+            meter: Meter::default(),
+            swing: F::from(0),
+            grooves: GrooveLibrary::new(),
+            seed: 0,
+            probability_mode: ProbabilityMode::default(),
+            tonality: Tonality::default(),
+            arrangement: Arrangement::default(),
+            fill: false,
             current_beat_time: Arc::new(RwLock::new(BeatTime::zero())),
+            song_position: Arc::new(RwLock::new(None)),
         }
     }
 }
 
+/// Least common multiple of two positive fractions:
+/// lcm(a/b, c/d) = lcm(a, c) / gcd(b, d)
+pub(crate) fn lcm(x: F, y: F) -> F {
+    let numer = x.numer().unwrap().lcm(y.numer().unwrap());
+    let denom = x.denom().unwrap().gcd(y.denom().unwrap());
+    F::new(numer, denom)
+}
+
 impl Project {
     pub fn new() -> Self {
         Self {
@@ -42,6 +204,29 @@ impl Project {
         }
     }
 
+    /// Reads a project file written by `save`. Files written by older
+    /// versions are migrated to the current format.
+    pub fn load(path: impl AsRef<Path>) -> SSResult<Self> {
+        Ok(ProjectFile::read(path)?.into_project()?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> SSResult<()> {
+        ProjectFile::from_project(self).write(path)
+    }
+
+    /// Replaces tracks and settings with those of `other`.
+    /// Unlike swapping the whole project, this keeps the shared state
+    /// handed out to BeatMaker and the UI valid.
+    pub fn replace(&self, other: Project) {
+        let tracks = std::mem::take(&mut *other.tracks.write().unwrap());
+        *self.tracks.write().unwrap() = tracks;
+        let project_settings = other.project_settings.read().unwrap().clone();
+        self.project_settings
+            .write()
+            .unwrap()
+            .assign(project_settings);
+    }
+
     pub fn add_track(&self, track: DrumTrack) -> SSId {
         let mut tracks = self.tracks.write().unwrap();
         let track_id = new_id();
@@ -58,6 +243,33 @@ impl Project {
         tracks.shift_remove(&track_id)
     }
 
+    /// Length after which all tracks line up again, i.e. the least common
+    /// multiple of the loop lengths of all non-empty tracks.
+    /// Returns zero if there are no such tracks.
+    pub fn loop_length(&self) -> BeatTime {
+        let tracks = self.tracks.read().unwrap();
+        let loop_length = tracks
+            .values()
+            .filter(|track| !track.is_empty())
+            .map(|track| track.loop_length())
+            .reduce(lcm)
+            .unwrap_or(F::from(0));
+        BeatTime::new(loop_length)
+    }
+
+    /// Length of the song in song mode, unless a section is looped.
+    pub fn song_length(&self) -> Option<BeatTime> {
+        let project_settings = self.project_settings.read().unwrap();
+        let arrangement = &project_settings.arrangement;
+        if !arrangement.is_active() || arrangement.loop_section.is_some() {
+            return None;
+        }
+        let tracks = self.tracks.read().unwrap();
+        Some(BeatTime::new(
+            arrangement.length(&tracks, &project_settings.meter),
+        ))
+    }
+
     pub fn project_settings(&self) -> Arc<RwLock<ProjectSettings>> {
         self.project_settings.clone()
     }
@@ -66,3 +278,27 @@ impl Project {
         self.tracks.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_fraction, TimeSignature, F};
+
+    #[test]
+    fn test_parse_fraction() {
+        assert_eq!(parse_fraction("1/12"), Some(F::new(1u64, 12u64)));
+        assert_eq!(parse_fraction(" -1/8"), Some(-F::new(1u64, 8u64)));
+        assert_eq!(parse_fraction("0.05"), Some(F::new(1u64, 20u64)));
+        assert_eq!(parse_fraction("1/0"), None);
+        assert_eq!(parse_fraction("abc"), None);
+    }
+
+    #[test]
+    fn test_parse_time_signature() {
+        let time_signature = TimeSignature::parse("7/8").unwrap();
+        assert_eq!(time_signature.beats_per_bar(), F::new(7u64, 2u64));
+        assert_eq!(time_signature.to_string(), "7/8");
+        assert_eq!(TimeSignature::parse("0/4"), None);
+        assert_eq!(TimeSignature::parse("3/6"), None);
+        assert_eq!(TimeSignature::parse("3"), None);
+    }
+}
@@ -0,0 +1,472 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    drum_track::DrumTrack,
+    error::ProjectFileError,
+    id::SSId,
+    meter::Meter,
+    project::{Project, ProjectSettings, TimeSignature, F},
+    tempo::TempoMap,
+    SSResult,
+};
+
+/// Version written by `ProjectFile::write`. Bump it whenever the shape of
+/// the file changes and add the corresponding step to `MIGRATIONS`.
+pub const PROJECT_FILE_VERSION: u64 = 4;
+
+type Migration = fn(Value) -> Result<Value, ProjectFileError>;
+
+/// `MIGRATIONS[n]` upgrades a file of version `n` to version `n + 1`.
+const MIGRATIONS: [Migration; PROJECT_FILE_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+];
+
+/// On-disk representation of a `Project`.
+/// Tracks are stored as a list so that track order survives a round trip.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectFile {
+    pub version: u64,
+    pub settings: ProjectSettings,
+    pub tracks: Vec<TrackEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TrackEntry {
+    pub id: SSId,
+    #[serde(flatten)]
+    pub track: DrumTrack,
+}
+
+impl ProjectFile {
+    pub fn from_project(project: &Project) -> Self {
+        let settings = project.project_settings().read().unwrap().clone();
+        let tracks = project
+            .tracks()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, track)| TrackEntry {
+                id: *id,
+                track: track.clone(),
+            })
+            .collect();
+        Self {
+            version: PROJECT_FILE_VERSION,
+            settings,
+            tracks,
+        }
+    }
+
+    pub fn into_project(self) -> Result<Project, ProjectFileError> {
+        let project = Project::new();
+        {
+            let binding = project.tracks();
+            let mut tracks = binding.write().unwrap();
+            for TrackEntry { id, track } in self.tracks {
+                let tempo_scale = track.get_tempo_scale();
+                if tempo_scale.is_nan() || tempo_scale.is_infinite() || tempo_scale <= F::from(0) {
+                    return Err(ProjectFileError::InvalidFormat(format!(
+                        "Invalid tempo scale {} of track {}",
+                        tempo_scale, id
+                    )));
+                }
+                if tracks.insert(id, track).is_some() {
+                    return Err(ProjectFileError::InvalidFormat(format!(
+                        "Duplicate track id {}",
+                        id
+                    )));
+                }
+            }
+        }
+        project
+            .project_settings()
+            .write()
+            .unwrap()
+            .assign(self.settings);
+        Ok(project)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, ProjectFileError> {
+        let value = migrate(serde_json::from_str(s)?)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, ProjectFileError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> SSResult<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::from_json(&content)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> SSResult<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+fn get_version(value: &Value) -> Result<u64, ProjectFileError> {
+    match value {
+        // Unversioned files are the bare `[id, track]` list
+        // returned by the GUI's `get_track_list`.
+        Value::Array(_) => Ok(0),
+        Value::Object(obj) => {
+            obj.get("version")
+                .and_then(Value::as_u64)
+                .ok_or(ProjectFileError::InvalidFormat(
+                    "Missing version".to_string(),
+                ))
+        }
+        _ => Err(ProjectFileError::InvalidFormat(
+            "Expected an object or an array".to_string(),
+        )),
+    }
+}
+
+/// Upgrades a file of any supported version to `PROJECT_FILE_VERSION`.
+fn migrate(mut value: Value) -> Result<Value, ProjectFileError> {
+    let version = get_version(&value)?;
+    if version > PROJECT_FILE_VERSION {
+        return Err(ProjectFileError::UnsupportedVersion(version));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        value = migration(value)?;
+    }
+    Ok(value)
+}
+
+fn migrate_v0_to_v1(value: Value) -> Result<Value, ProjectFileError> {
+    let Value::Array(pairs) = value else {
+        return Err(ProjectFileError::InvalidFormat(
+            "Expected a list of tracks".to_string(),
+        ));
+    };
+    let mut tracks = vec![];
+    for pair in pairs {
+        match pair {
+            Value::Array(pair) if pair.len() == 2 => {
+                let [id, track] = <[Value; 2]>::try_from(pair).unwrap();
+                let Value::Object(mut track) = track else {
+                    return Err(ProjectFileError::InvalidFormat(
+                        "Expected a track object".to_string(),
+                    ));
+                };
+                track.insert("id".to_string(), id);
+                tracks.push(Value::Object(track));
+            }
+            _ => {
+                return Err(ProjectFileError::InvalidFormat(
+                    "Expected an `[id, track]` pair".to_string(),
+                ));
+            }
+        }
+    }
+    Ok(json!({
+        "version": 1,
+        "settings": ProjectSettings::default(),
+        "tracks": tracks,
+    }))
+}
+
+/// Steps gained a timing offset, so the `beats` list of every track
+/// became a list of `steps` wrapping each beat.
+fn migrate_v1_to_v2(mut value: Value) -> Result<Value, ProjectFileError> {
+    let tracks = value
+        .get_mut("tracks")
+        .and_then(Value::as_array_mut)
+        .ok_or(ProjectFileError::InvalidFormat(
+            "Missing tracks".to_string(),
+        ))?;
+    for track in tracks {
+        let Value::Object(track) = track else {
+            return Err(ProjectFileError::InvalidFormat(
+                "Expected a track object".to_string(),
+            ));
+        };
+        if let Some(beats) = track.remove("beats") {
+            let Value::Array(beats) = beats else {
+                return Err(ProjectFileError::InvalidFormat(
+                    "Expected a list of beats".to_string(),
+                ));
+            };
+            let steps = beats.into_iter().map(|beat| json!({ "beat": beat }));
+            track.insert("steps".to_string(), Value::Array(steps.collect()));
+        }
+    }
+    value["version"] = json!(2);
+    Ok(value)
+}
+
+/// The tempo in whole BPM became a tempo map starting at that tempo.
+fn migrate_v2_to_v3(mut value: Value) -> Result<Value, ProjectFileError> {
+    let settings = value
+        .get_mut("settings")
+        .and_then(Value::as_object_mut)
+        .ok_or(ProjectFileError::InvalidFormat(
+            "Missing settings".to_string(),
+        ))?;
+    if let Some(tempo) = settings.remove("tempo") {
+        let tempo = tempo.as_u64().ok_or(ProjectFileError::InvalidFormat(
+            "Expected a tempo in BPM".to_string(),
+        ))?;
+        settings.insert(
+            "tempo_map".to_string(),
+            serde_json::to_value(TempoMap::new(F::from(tempo)))?,
+        );
+    }
+    value["version"] = json!(3);
+    Ok(value)
+}
+
+/// The time signature became a meter starting with that time signature.
+fn migrate_v3_to_v4(mut value: Value) -> Result<Value, ProjectFileError> {
+    let settings = value
+        .get_mut("settings")
+        .and_then(Value::as_object_mut)
+        .ok_or(ProjectFileError::InvalidFormat(
+            "Missing settings".to_string(),
+        ))?;
+    if let Some(time_signature) = settings.remove("time_signature") {
+        let time_signature: TimeSignature = serde_json::from_value(time_signature)?;
+        settings.insert(
+            "meter".to_string(),
+            serde_json::to_value(Meter::new(time_signature))?,
+        );
+    }
+    value["version"] = json!(4);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        arrangement::{Section, SectionLength},
+        beatmaker::pattern::{ExampleDrumTracks, EXAMPLE_DRUMTRACKS_BITWIG},
+        drum_track::{Beat, DrumTrack, DrumTrackBeat, PatternSlot},
+        error::{ProjectFileError, SSError},
+        meter::Meter,
+        midi::note::Note,
+        project::{Project, TimeSignature, F},
+        tempo::{Ramp, TempoMap, TempoPoint},
+    };
+
+    use super::{ProjectFile, PROJECT_FILE_VERSION};
+
+    fn example_project() -> Project {
+        let project = Project::new();
+        for track in EXAMPLE_DRUMTRACKS_BITWIG.all_tracks() {
+            project.add_track(track);
+        }
+        let mut track = DrumTrack::with_beats(
+            "Scaled",
+            Beat {
+                channel: 3,
+                note: Note::Gs(-1),
+                velocity: 100,
+                gate: None,
+            },
+            &[DrumTrackBeat::DefaultBeat, DrumTrackBeat::Unset],
+        );
+        track.set_tempo_scale(F::new(3u64, 2u64));
+        track.set_offset(0, -F::new(1u64, 8u64));
+        let track_id = project.add_track(track);
+        let mut verse = Section::new("Verse", PatternSlot::default(), SectionLength::Loops(2));
+        verse
+            .track_patterns
+            .insert(track_id, PatternSlot::parse("B3").unwrap());
+        let project_settings = project.project_settings();
+        let mut project_settings = project_settings.write().unwrap();
+        project_settings.tempo_map = TempoMap::new(F::new(267u64, 2u64));
+        project_settings.tempo_map.insert(TempoPoint::new(
+            F::from(8),
+            F::from(140),
+            Ramp::Exponential,
+        ));
+        project_settings
+            .tempo_map
+            .insert(TempoPoint::new(F::from(16), F::from(90), Ramp::Step));
+        project_settings
+            .meter
+            .insert(4, TimeSignature::parse("7/8").unwrap());
+        project_settings.arrangement.sections = vec![
+            Section::new("Intro", PatternSlot::default(), SectionLength::Bars(4)),
+            verse,
+        ];
+        project_settings.arrangement.enabled = true;
+        drop(project_settings);
+        project
+    }
+
+    fn assert_same_project(a: &Project, b: &Project) {
+        assert_eq!(
+            a.project_settings().read().unwrap().tempo_map,
+            b.project_settings().read().unwrap().tempo_map
+        );
+        assert_eq!(
+            a.project_settings().read().unwrap().meter,
+            b.project_settings().read().unwrap().meter
+        );
+        assert_eq!(
+            a.project_settings().read().unwrap().arrangement,
+            b.project_settings().read().unwrap().arrangement
+        );
+        let a_tracks = a.tracks();
+        let b_tracks = b.tracks();
+        let a_tracks = a_tracks.read().unwrap();
+        let b_tracks = b_tracks.read().unwrap();
+        assert!(a_tracks.iter().eq(b_tracks.iter()));
+    }
+
+    #[test]
+    fn test_round_trip_json() {
+        let project = example_project();
+        let json = ProjectFile::from_project(&project).to_json().unwrap();
+        let loaded = ProjectFile::from_json(&json)
+            .unwrap()
+            .into_project()
+            .unwrap();
+        assert_same_project(&project, &loaded);
+    }
+
+    #[test]
+    fn test_round_trip_file() {
+        let project = example_project();
+        let path = std::env::temp_dir().join(format!("{}.json", crate::id::new_id()));
+        project.save(&path).unwrap();
+        let loaded = Project::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_same_project(&project, &loaded);
+    }
+
+    #[test]
+    fn test_migrate_from_unversioned_track_list() {
+        let project = example_project();
+        let track_list: Vec<_> = project
+            .tracks()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, track)| (id.to_string(), track.clone()))
+            .collect();
+        let json = serde_json::to_string(&track_list).unwrap();
+        let file = ProjectFile::from_json(&json).unwrap();
+        assert_eq!(file.version, PROJECT_FILE_VERSION);
+        let loaded = file.into_project().unwrap();
+        // Track lists carry no settings
+        project
+            .project_settings()
+            .write()
+            .unwrap()
+            .assign(loaded.project_settings().read().unwrap().clone());
+        assert_same_project(&project, &loaded);
+    }
+
+    #[test]
+    fn test_migrate_beats_to_steps() {
+        let json = r#"{
+            "version": 1,
+            "settings": {},
+            "tracks": [{
+                "id": "b8f6c1d2-8a55-4a5e-9d4c-1f2e3a4b5c6d",
+                "name": "Kick",
+                "beats": ["DefaultBeat", "Unset"]
+            }]
+        }"#;
+        let project = ProjectFile::from_json(json)
+            .unwrap()
+            .into_project()
+            .unwrap();
+        let tracks = project.tracks();
+        let tracks = tracks.read().unwrap();
+        let track = tracks.values().next().unwrap();
+        assert_eq!(track.len(), 2);
+        assert_eq!(track.get(0), Some(&DrumTrackBeat::DefaultBeat));
+        assert_eq!(track.get_offset(0), F::from(0));
+    }
+
+    #[test]
+    fn test_migrate_tempo_to_tempo_map() {
+        let json = r#"{
+            "version": 2,
+            "settings": { "tempo": 133 },
+            "tracks": []
+        }"#;
+        let project = ProjectFile::from_json(json)
+            .unwrap()
+            .into_project()
+            .unwrap();
+        assert_eq!(
+            project.project_settings().read().unwrap().tempo_map,
+            TempoMap::new(F::from(133))
+        );
+    }
+
+    #[test]
+    fn test_migrate_time_signature_to_meter() {
+        let json = r#"{
+            "version": 3,
+            "settings": { "time_signature": { "numerator": 6, "denominator": 8 } },
+            "tracks": []
+        }"#;
+        let project = ProjectFile::from_json(json)
+            .unwrap()
+            .into_project()
+            .unwrap();
+        assert_eq!(
+            project.project_settings().read().unwrap().meter,
+            Meter::new(TimeSignature::parse("6/8").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_reject_newer_version() {
+        let json = format!(
+            r#"{{"version": {}, "settings": {{}}, "tracks": []}}"#,
+            PROJECT_FILE_VERSION + 1
+        );
+        assert!(matches!(
+            ProjectFile::from_json(&json),
+            Err(ProjectFileError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_duplicate_track_id() {
+        let project = example_project();
+        let mut file = ProjectFile::from_project(&project);
+        let id = file.tracks[0].id;
+        file.tracks[1].id = id;
+        assert!(matches!(
+            file.into_project(),
+            Err(ProjectFileError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_invalid_tempo_scale() {
+        for tempo_scale in [F::from(0), F::from(-2)] {
+            let project = example_project();
+            let mut file = ProjectFile::from_project(&project);
+            file.tracks[0].track.set_tempo_scale(tempo_scale);
+            assert!(matches!(
+                file.into_project(),
+                Err(ProjectFileError::InvalidFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        assert!(matches!(
+            Project::load("/nonexistent/project.json"),
+            Err(SSError::IOError(_))
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+//! Keys and scales of melodic tracks.
+//!
+//! Steps of melodic tracks hold a scale degree and an octave rather than a
+//! note (see `DrumTrackBeat::Degree`). They are turned into notes with the
+//! tonality of the project whenever they are played, so changing the key or
+//! the scale of the project transposes all melodic tracks right away.
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+
+use crate::midi::{
+    note::{Note, PitchClass},
+    Key,
+};
+
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum Scale {
+    #[default]
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    /// Semitones of the degrees above the root, in ascending order
+    /// within an octave, e.g. `[0, 2, 3, 7, 8]` for the hirajoshi scale
+    Custom(#[serde(deserialize_with = "deserialize_intervals")] Vec<u8>),
+}
+
+impl Scale {
+    /// Semitones of the degrees above the root.
+    pub fn intervals(&self) -> &[u8] {
+        match self {
+            Self::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Self::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Self::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Self::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Self::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Self::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Self::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Self::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Self::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Self::Custom(intervals) => intervals,
+        }
+    }
+
+    /// Parses the name of a scale, or the semitones of a custom scale
+    /// separated by commas like "0,2,3,7,8".
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "major" | "ionian" => Some(Self::Major),
+            "minor" | "aeolian" => Some(Self::Minor),
+            "dorian" => Some(Self::Dorian),
+            "phrygian" => Some(Self::Phrygian),
+            "lydian" => Some(Self::Lydian),
+            "mixolydian" => Some(Self::Mixolydian),
+            "locrian" => Some(Self::Locrian),
+            "pentatonic" | "major_pentatonic" => Some(Self::MajorPentatonic),
+            "minor_pentatonic" => Some(Self::MinorPentatonic),
+            s => {
+                let intervals = s
+                    .split(',')
+                    .map(|interval| interval.trim().parse::<u8>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                valid_intervals(&intervals).then_some(Self::Custom(intervals))
+            }
+        }
+    }
+}
+
+/// Whether custom scale intervals are non-empty, ascending and within an octave
+fn valid_intervals(intervals: &[u8]) -> bool {
+    let ascending = intervals.windows(2).all(|pair| pair[0] < pair[1]);
+    !intervals.is_empty() && ascending && intervals.iter().all(|i| *i < 12)
+}
+
+/// Rejects custom scales from project files that `Scale::parse` would reject
+fn deserialize_intervals<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let intervals = Vec::<u8>::deserialize(deserializer)?;
+    if !valid_intervals(&intervals) {
+        return Err(D::Error::custom(format!(
+            "invalid custom scale intervals: {:?}",
+            intervals
+        )));
+    }
+    Ok(intervals)
+}
+
+impl std::fmt::Display for Scale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Major => write!(f, "major"),
+            Self::Minor => write!(f, "minor"),
+            Self::Dorian => write!(f, "dorian"),
+            Self::Phrygian => write!(f, "phrygian"),
+            Self::Lydian => write!(f, "lydian"),
+            Self::Mixolydian => write!(f, "mixolydian"),
+            Self::Locrian => write!(f, "locrian"),
+            Self::MajorPentatonic => write!(f, "major_pentatonic"),
+            Self::MinorPentatonic => write!(f, "minor_pentatonic"),
+            Self::Custom(intervals) => {
+                let intervals: Vec<_> = intervals.iter().map(|i| i.to_string()).collect();
+                write!(f, "{}", intervals.join(","))
+            }
+        }
+    }
+}
+
+/// Scale degree of a step, counting from 1 for the root. Degrees beyond the
+/// scale continue in the octaves above, e.g. 8 is the root an octave up in
+/// a major scale.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Degree {
+    pub degree: u8,
+    /// Octave of the root the degree is counted from, as in `Note`
+    pub octave: i8,
+}
+
+impl std::fmt::Display for Degree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.degree, self.octave)
+    }
+}
+
+/// Key and scale of the project
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Tonality {
+    pub root: PitchClass,
+    pub scale: Scale,
+}
+
+impl Default for Tonality {
+    fn default() -> Self {
+        Self {
+            root: PitchClass::C,
+            scale: Scale::default(),
+        }
+    }
+}
+
+impl Tonality {
+    /// Note of a scale degree, clamped to the range of MIDI keys.
+    pub fn note(&self, degree: Degree) -> Note {
+        let intervals = self.scale.intervals();
+        let idx = degree.degree.max(1) as usize - 1;
+        let root: i8 = self.root.into();
+        let key = 24
+            + 12 * degree.octave as i32
+            + root as i32
+            + 12 * (idx / intervals.len()) as i32
+            + intervals[idx % intervals.len()] as i32;
+        Note::from(key.clamp(0, 127) as Key)
+    }
+}
+
+impl std::fmt::Display for Tonality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.root, self.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::midi::note::{Note, PitchClass};
+
+    use super::{Degree, Scale, Tonality};
+
+    fn degree(degree: u8, octave: i8) -> Degree {
+        Degree { degree, octave }
+    }
+
+    #[test]
+    fn test_note_of_degree() {
+        let c_major = Tonality::default();
+        assert_eq!(c_major.note(degree(1, 3)), Note::C(3));
+        assert_eq!(c_major.note(degree(3, 3)), Note::E(3));
+        assert_eq!(c_major.note(degree(8, 3)), Note::C(4));
+        assert_eq!(c_major.note(degree(10, 3)), Note::E(4));
+        let d_dorian = Tonality {
+            root: PitchClass::D,
+            scale: Scale::Dorian,
+        };
+        assert_eq!(d_dorian.note(degree(3, 3)), Note::F(3));
+        assert_eq!(d_dorian.note(degree(7, 3)), Note::C(4));
+        let a_pentatonic = Tonality {
+            root: PitchClass::A,
+            scale: Scale::MinorPentatonic,
+        };
+        assert_eq!(a_pentatonic.note(degree(6, 2)), Note::A(3));
+        assert_eq!(a_pentatonic.note(degree(1, 9)), Note::from(127));
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        assert_eq!(Scale::parse("Dorian"), Some(Scale::Dorian));
+        assert_eq!(
+            Scale::parse("0,2,3,7,8"),
+            Some(Scale::Custom(vec![0, 2, 3, 7, 8]))
+        );
+        assert_eq!(Scale::parse("0,3,2"), None);
+        assert_eq!(Scale::parse("0,12"), None);
+        assert_eq!(Scale::parse("blues"), None);
+        let custom = Scale::Custom(vec![0, 2, 3, 7, 8]);
+        assert_eq!(Scale::parse(&custom.to_string()), Some(custom));
+    }
+
+    #[test]
+    fn test_deserialize_custom_scale() {
+        let custom = Scale::Custom(vec![0, 2, 3, 7, 8]);
+        let json = serde_json::to_string(&custom).unwrap();
+        assert_eq!(serde_json::from_str::<Scale>(&json).unwrap(), custom);
+        for json in [
+            r#"{"Custom":[]}"#,
+            r#"{"Custom":[0,3,2]}"#,
+            r#"{"Custom":[0,12]}"#,
+        ] {
+            assert!(serde_json::from_str::<Scale>(json).is_err(), "{}", json);
+        }
+    }
+}
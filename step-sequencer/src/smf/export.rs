@@ -0,0 +1,371 @@
+use std::{fs, path::Path};
+
+use indexmap::IndexMap;
+
+use crate::{
+    beatmaker::{beat_time::BeatTime, renderer::render_track_events},
+    id::SSId,
+    meter::Meter,
+    midi::ChannelVoiceEvent,
+    project::{Project, Tempo, TimeSignature, F},
+    tempo::{Ramp, TempoMap},
+    SSResult,
+};
+
+use super::{
+    tempo_to_micros_per_beat, time_signature_to_meta, write_chunk, write_vlq, HEADER_CHUNK_ID,
+    META_END_OF_TRACK, META_EVENT, META_TEMPO, META_TIME_SIGNATURE, META_TRACK_NAME,
+    TICKS_PER_BEAT, TRACK_CHUNK_ID,
+};
+
+/// SMF format 1: several tracks played simultaneously
+const FORMAT_MULTI_TRACK: u16 = 1;
+
+/// Tempo changes written for every beat of a ramp, as files have no ramps
+const RAMP_CHANGES_PER_BEAT: u64 = 4;
+
+fn beat_time_to_ticks(beat_time: BeatTime) -> u64 {
+    let ticks = (beat_time.as_fraction() * F::from(TICKS_PER_BEAT)).round();
+    u64::try_from(ticks).unwrap()
+}
+
+struct TrackChunk {
+    data: Vec<u8>,
+    last_ticks: u64,
+}
+
+impl TrackChunk {
+    fn new(name: &str) -> Self {
+        let mut chunk = Self {
+            data: vec![],
+            last_ticks: 0,
+        };
+        chunk.write_meta(0, META_TRACK_NAME, name.as_bytes());
+        chunk
+    }
+
+    fn write_delta(&mut self, ticks: u64) {
+        // Absolute ticks are rounded individually so that rounding errors
+        // do not accumulate over the deltas.
+        let delta = ticks.saturating_sub(self.last_ticks);
+        write_vlq(&mut self.data, delta as u32);
+        self.last_ticks = self.last_ticks.max(ticks);
+    }
+
+    fn write_meta(&mut self, ticks: u64, meta_type: u8, meta_data: &[u8]) {
+        self.write_delta(ticks);
+        self.data.extend_from_slice(&[META_EVENT, meta_type]);
+        write_vlq(&mut self.data, meta_data.len() as u32);
+        self.data.extend_from_slice(meta_data);
+    }
+
+    fn write_event(&mut self, ticks: u64, event: ChannelVoiceEvent) -> SSResult<()> {
+        self.write_delta(ticks);
+        self.data.extend(event.to_data()?);
+        Ok(())
+    }
+
+    fn finish(mut self, buf: &mut Vec<u8>) {
+        let last_ticks = self.last_ticks;
+        self.write_meta(last_ticks, META_END_OF_TRACK, &[]);
+        write_chunk(buf, TRACK_CHUNK_ID, &self.data);
+    }
+}
+
+/// Tempo changes of the tempo map before `length`, with ramps broken up
+/// into `RAMP_CHANGES_PER_BEAT` changes per beat.
+fn tempo_changes(tempo_map: &TempoMap, length: BeatTime) -> Vec<(BeatTime, Tempo)> {
+    let mut changes = vec![];
+    let points = tempo_map.points();
+    for (i, point) in points.iter().enumerate() {
+        let mut beat_time = BeatTime::new(point.beat);
+        if i > 0 && beat_time >= length {
+            break;
+        }
+        let end = match (point.ramp, points.get(i + 1)) {
+            (Ramp::Step, _) | (_, None) => beat_time,
+            (_, Some(next)) => BeatTime::new(next.beat).min(length),
+        };
+        loop {
+            changes.push((beat_time, tempo_map.tempo_at(beat_time)));
+            beat_time = beat_time.add_fraction(F::new(1u64, RAMP_CHANGES_PER_BEAT));
+            if beat_time >= end {
+                break;
+            }
+        }
+    }
+    changes
+}
+
+/// Time signature changes of the meter before `length`.
+fn time_signature_changes(meter: &Meter, length: BeatTime) -> Vec<(BeatTime, TimeSignature)> {
+    meter
+        .changes()
+        .iter()
+        .map(|change| (meter.bar_start(change.bar), change.time_signature))
+        .enumerate()
+        .take_while(|(i, (beat_time, _))| *i == 0 || *beat_time < length)
+        .map(|(_, change)| change)
+        .collect()
+}
+
+/// Renders `length` beats of the project into a type-1 Standard MIDI File.
+///
+/// The first track is a conductor track holding the tempo and the time
+/// signatures, followed by one track per `DrumTrack` in project order. Notes
+/// still sounding at `length` are ended after it.
+pub fn export_project(project: &Project, length: BeatTime) -> SSResult<Vec<u8>> {
+    let (tempo_map, meter) = {
+        let project_settings = project.project_settings();
+        let project_settings = project_settings.read().unwrap();
+        (
+            project_settings.tempo_map.clone(),
+            project_settings.meter.clone(),
+        )
+    };
+    let mut track_chunks: IndexMap<SSId, TrackChunk> = project
+        .tracks()
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(id, track)| (*id, TrackChunk::new(&track.name())))
+        .collect();
+
+    for (beat_time, id, event) in render_track_events(project, BeatTime::zero(), length) {
+        if let Some(chunk) = track_chunks.get_mut(&id) {
+            chunk.write_event(beat_time_to_ticks(beat_time), event)?;
+        }
+    }
+
+    let mut buf = vec![];
+    let mut header = vec![];
+    header.extend_from_slice(&FORMAT_MULTI_TRACK.to_be_bytes());
+    header.extend_from_slice(&(track_chunks.len() as u16 + 1).to_be_bytes());
+    header.extend_from_slice(&TICKS_PER_BEAT.to_be_bytes());
+    write_chunk(&mut buf, HEADER_CHUNK_ID, &header);
+
+    // Time signatures come first at the same tick
+    let mut meta_events: Vec<(u64, u8, Vec<u8>)> = time_signature_changes(&meter, length)
+        .into_iter()
+        .map(|(beat_time, time_signature)| {
+            (
+                beat_time_to_ticks(beat_time),
+                META_TIME_SIGNATURE,
+                time_signature_to_meta(time_signature).to_vec(),
+            )
+        })
+        .collect();
+    meta_events.extend(
+        tempo_changes(&tempo_map, length)
+            .into_iter()
+            .map(|(beat_time, tempo)| {
+                (
+                    beat_time_to_ticks(beat_time),
+                    META_TEMPO,
+                    tempo_to_micros_per_beat(tempo).to_be_bytes()[1..].to_vec(),
+                )
+            }),
+    );
+    meta_events.sort_by_key(|(ticks, _, _)| *ticks);
+    let mut conductor = TrackChunk::new("Tempo");
+    for (ticks, meta_type, meta_data) in meta_events {
+        conductor.write_meta(ticks, meta_type, &meta_data);
+    }
+    conductor.finish(&mut buf);
+    for chunk in track_chunks.into_values() {
+        chunk.finish(&mut buf);
+    }
+    Ok(buf)
+}
+
+/// Writes the song in song mode (see `Project::song_length`), or else one
+/// full loop of the project (see `Project::loop_length`) to `path`.
+pub fn write_project(project: &Project, path: impl AsRef<Path>) -> SSResult<()> {
+    let length = project
+        .song_length()
+        .unwrap_or_else(|| project.loop_length());
+    let data = export_project(project, length)?;
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        beatmaker::beat_time::BeatTime,
+        drum_track::DrumTrack,
+        meter::Meter,
+        midi::note::Note,
+        project::{Project, TimeSignature, F},
+        tempo::{Ramp, TempoMap, TempoPoint},
+    };
+
+    use super::export_project;
+
+    /// (absolute ticks, event bytes) of every event in a track
+    type TrackEvents = Vec<(u64, Vec<u8>)>;
+
+    /// Splits an SMF into its header and the events of every track.
+    fn read_smf(data: &[u8]) -> (Vec<u8>, Vec<TrackEvents>) {
+        assert_eq!(&data[0..4], b"MThd");
+        let header = data[8..14].to_vec();
+        let mut pos = 14;
+        let mut tracks = vec![];
+        while pos < data.len() {
+            assert_eq!(&data[pos..pos + 4], b"MTrk");
+            let len = u32::from_be_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            let chunk = &data[pos + 8..pos + 8 + len];
+            pos += 8 + len;
+            let mut events = vec![];
+            let mut ticks = 0;
+            let mut i = 0;
+            let read_vlq = |i: &mut usize| {
+                let mut value = 0u64;
+                loop {
+                    let byte = chunk[*i];
+                    *i += 1;
+                    value = (value << 7) | (byte & 0x7F) as u64;
+                    if byte & 0x80 == 0 {
+                        return value;
+                    }
+                }
+            };
+            while i < chunk.len() {
+                ticks += read_vlq(&mut i);
+                let start = i;
+                if chunk[i] == 0xFF {
+                    i += 2;
+                    let len = read_vlq(&mut i) as usize;
+                    i += len;
+                } else {
+                    i += 3;
+                }
+                events.push((ticks, chunk[start..i].to_vec()));
+            }
+            tracks.push(events);
+        }
+        (header, tracks)
+    }
+
+    #[test]
+    fn test_export_header_and_tempo() {
+        let project = Project::new();
+        project.add_track(DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4));
+        project.project_settings().write().unwrap().tempo_map = TempoMap::new(F::from(120));
+        let data = export_project(&project, BeatTime::new(F::from(4))).unwrap();
+        let (header, tracks) = read_smf(&data);
+        // Format 1, two tracks, 960 ticks per beat
+        assert_eq!(header, vec![0, 1, 0, 2, 0x03, 0xC0]);
+        assert_eq!(tracks.len(), 2);
+        // 500000 microseconds per beat = 120 BPM
+        assert!(tracks[0].contains(&(0, vec![0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20])));
+        // 4/4
+        assert!(tracks[0].contains(&(0, vec![0xFF, 0x58, 0x04, 0x04, 0x02, 0x18, 0x08])));
+        assert_eq!(tracks[0].last().unwrap().1, vec![0xFF, 0x2F, 0x00]);
+    }
+
+    #[test]
+    fn test_export_tempo_ramp() {
+        let project = Project::new();
+        project.add_track(DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4));
+        {
+            let project_settings = project.project_settings();
+            let tempo_map = &mut project_settings.write().unwrap().tempo_map;
+            *tempo_map = TempoMap::new(F::from(120));
+            tempo_map.insert(TempoPoint::new(F::from(1), F::from(120), Ramp::Linear));
+            tempo_map.insert(TempoPoint::new(F::from(3), F::from(200), Ramp::Step));
+            // Beyond the end
+            tempo_map.insert(TempoPoint::new(F::from(4), F::from(60), Ramp::Step));
+        }
+        let data = export_project(&project, BeatTime::new(F::from(4))).unwrap();
+        let (_, tracks) = read_smf(&data);
+        let tempos: Vec<_> = tracks[0]
+            .iter()
+            .filter(|(_, bytes)| bytes[1] == 0x51)
+            .map(|(ticks, bytes)| {
+                let micros = u32::from_be_bytes([0, bytes[3], bytes[4], bytes[5]]);
+                (*ticks, 60_000_000 / micros)
+            })
+            .collect();
+        assert_eq!(
+            tempos,
+            vec![
+                (0, 120),
+                (960, 120),
+                (1200, 130),
+                (1440, 140),
+                (1680, 150),
+                (1920, 160),
+                (2160, 170),
+                (2400, 180),
+                (2640, 190),
+                (2880, 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_time_signature_changes() {
+        let project = Project::new();
+        project.add_track(DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 4));
+        {
+            let project_settings = project.project_settings();
+            let mut project_settings = project_settings.write().unwrap();
+            let mut meter = Meter::default();
+            meter.insert(1, TimeSignature::parse("6/8").unwrap());
+            // Beyond the end
+            meter.insert(3, TimeSignature::parse("3/4").unwrap());
+            project_settings.meter = meter;
+            project_settings.tempo_map.insert(TempoPoint::new(
+                F::from(4),
+                F::from(100),
+                Ramp::Step,
+            ));
+        }
+        let data = export_project(&project, BeatTime::new(F::from(10))).unwrap();
+        let (_, tracks) = read_smf(&data);
+        let meta_types: Vec<_> = tracks[0]
+            .iter()
+            .filter(|(_, bytes)| bytes[1] == 0x51 || bytes[1] == 0x58)
+            .map(|(ticks, bytes)| (*ticks, bytes[1]))
+            .collect();
+        assert_eq!(
+            meta_types,
+            vec![(0, 0x58), (0, 0x51), (3840, 0x58), (3840, 0x51)]
+        );
+        assert!(tracks[0].contains(&(3840, vec![0xFF, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08])));
+    }
+
+    #[test]
+    fn test_export_note_timing_with_tempo_scale() {
+        let project = Project::new();
+        project.add_track(DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 2));
+        project.add_track(DrumTrack::with_beats_for_test(
+            F::new(3u64, 2u64),
+            Note::D(1),
+            3,
+        ));
+        assert_eq!(project.loop_length(), BeatTime::new(F::from(2)));
+        let data = export_project(&project, project.loop_length()).unwrap();
+        let (_, tracks) = read_smf(&data);
+        let note_ons = |events: &TrackEvents| {
+            events
+                .iter()
+                .filter(|(_, bytes)| bytes[0] & 0xF0 == 0x90)
+                .map(|(ticks, bytes)| (*ticks, bytes[1]))
+                .collect::<Vec<_>>()
+        };
+        let note_offs = |events: &TrackEvents| {
+            events
+                .iter()
+                .filter(|(_, bytes)| bytes[0] & 0xF0 == 0x80)
+                .map(|(ticks, _)| *ticks)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(note_ons(&tracks[1]), vec![(0, 36), (960, 36)]);
+        assert_eq!(note_offs(&tracks[1]), vec![240, 1200]);
+        // Steps of the 3/2 track are 2/3 of a beat apart
+        assert_eq!(note_ons(&tracks[2]), vec![(0, 38), (640, 38), (1280, 38)]);
+        assert_eq!(note_offs(&tracks[2]), vec![240, 880, 1520]);
+    }
+}
@@ -0,0 +1,672 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    beatmaker::beat_time::BeatTime,
+    drum_track::{Beat, DrumTrack, DrumTrackBeat},
+    error::SMFError,
+    meter::{Meter, Position},
+    midi::{note::Note, Channel, Key, Velocity},
+    project::{Project, TimeSignature, F},
+    tempo::{Ramp, TempoMap, TempoPoint},
+    SSResult,
+};
+
+use super::{
+    micros_per_beat_to_tempo, HEADER_CHUNK_ID, META_END_OF_TRACK, META_EVENT, META_TEMPO,
+    META_TIME_SIGNATURE, TRACK_CHUNK_ID,
+};
+
+const FORMAT_SINGLE_TRACK: u16 = 0;
+const FORMAT_MULTI_TRACK: u16 = 1;
+
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_ESCAPE: u8 = 0xF7;
+
+const NOTE_ON: u8 = 0x90;
+
+/// Steps of an imported track beyond which a file is rejected, e.g. 4096
+/// bars of 4/4 on a sixteenth-note grid.
+const MAX_STEPS: usize = 1 << 16;
+
+pub struct ImportOptions {
+    /// Length of one step in beats. Also determines the tempo scale of the
+    /// imported tracks, e.g. a grid of 1/4 gives sixteenth-note steps.
+    pub grid: F,
+    /// How far a note may be off the grid, as a fraction of a step,
+    /// before it is rejected as unquantizable.
+    pub tolerance: F,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            grid: F::new(1u64, 4u64),
+            tolerance: F::new(1u64, 4u64),
+        }
+    }
+}
+
+struct Hit {
+    ticks: u64,
+    channel: Channel,
+    key: Key,
+    velocity: Velocity,
+}
+
+/// Everything the importer needs from a file, with times in ticks.
+#[derive(Default)]
+struct ParsedFile {
+    division: u16,
+    tempos: Vec<(u64, u32)>,
+    time_signatures: Vec<(u64, TimeSignature)>,
+    hits: Vec<Hit>,
+    end_ticks: u64,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SMFError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| SMFError::InvalidFile("Unexpected end of data".to_string()))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn peek_u8(&self) -> Result<u8, SMFError> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| SMFError::InvalidFile("Unexpected end of data".to_string()))
+    }
+
+    fn read_u8(&mut self) -> Result<u8, SMFError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, SMFError> {
+        Ok(u16::from_be_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, SMFError> {
+        Ok(u32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a variable-length quantity of at most four bytes.
+    fn read_vlq(&mut self) -> Result<u32, SMFError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.read_u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SMFError::InvalidFile(
+            "Variable-length quantity too long".to_string(),
+        ))
+    }
+
+    fn read_chunk(&mut self) -> Result<([u8; 4], Reader<'a>), SMFError> {
+        let id = self.read_bytes(4)?.try_into().unwrap();
+        let len = self.read_u32()? as usize;
+        Ok((id, Reader::new(self.read_bytes(len)?)))
+    }
+}
+
+fn parse_file(data: &[u8]) -> Result<ParsedFile, SMFError> {
+    let mut reader = Reader::new(data);
+    let (id, mut header) = reader.read_chunk()?;
+    if &id != HEADER_CHUNK_ID {
+        return Err(SMFError::InvalidFile("Missing header chunk".to_string()));
+    }
+    let format = header.read_u16()?;
+    if format != FORMAT_SINGLE_TRACK && format != FORMAT_MULTI_TRACK {
+        return Err(SMFError::UnsupportedFormat(format));
+    }
+    let _track_count = header.read_u16()?;
+    let division = header.read_u16()?;
+    // SMPTE-based divisions have the high bit set
+    if division == 0 || division & 0x8000 != 0 {
+        return Err(SMFError::UnsupportedDivision(division));
+    }
+
+    let mut parsed = ParsedFile {
+        division,
+        ..Default::default()
+    };
+    while !reader.is_empty() {
+        let (id, mut track) = reader.read_chunk()?;
+        // Unknown chunk types are to be ignored
+        if &id == TRACK_CHUNK_ID {
+            parse_track(&mut track, &mut parsed)?;
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_track(track: &mut Reader, parsed: &mut ParsedFile) -> Result<(), SMFError> {
+    let mut ticks = 0u64;
+    let mut running_status = None;
+    while !track.is_empty() {
+        ticks += track.read_vlq()? as u64;
+        let status = if track.peek_u8()? & 0x80 != 0 {
+            track.read_u8()?
+        } else {
+            running_status.ok_or_else(|| {
+                SMFError::InvalidFile(format!("Data byte without status at tick {}", ticks))
+            })?
+        };
+        match status {
+            META_EVENT => {
+                running_status = None;
+                let meta_type = track.read_u8()?;
+                let len = track.read_vlq()? as usize;
+                let meta_data = track.read_bytes(len)?;
+                match meta_type {
+                    META_TEMPO if len == 3 => {
+                        let micros =
+                            u32::from_be_bytes([0, meta_data[0], meta_data[1], meta_data[2]]);
+                        parsed.tempos.push((ticks, micros));
+                    }
+                    META_TIME_SIGNATURE if len >= 2 => {
+                        if meta_data[0] == 0 {
+                            return Err(SMFError::InvalidFile(format!(
+                                "Invalid time signature numerator at tick {}",
+                                ticks
+                            )));
+                        }
+                        let denominator =
+                            1u8.checked_shl(meta_data[1] as u32).ok_or_else(|| {
+                                SMFError::InvalidFile(format!(
+                                    "Invalid time signature denominator: 2^{}",
+                                    meta_data[1]
+                                ))
+                            })?;
+                        let time_signature = TimeSignature {
+                            numerator: meta_data[0],
+                            denominator,
+                        };
+                        parsed.time_signatures.push((ticks, time_signature));
+                    }
+                    META_END_OF_TRACK => break,
+                    _ => {}
+                }
+            }
+            SYSEX_START | SYSEX_ESCAPE => {
+                running_status = None;
+                let len = track.read_vlq()? as usize;
+                track.read_bytes(len)?;
+            }
+            0x80..=0xEF => {
+                running_status = Some(status);
+                // Program change and channel pressure have a single data byte
+                let data_len = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                let data = track.read_bytes(data_len)?;
+                // Note-ons with velocity 0 are note-offs
+                if status & 0xF0 == NOTE_ON && data[1] > 0 {
+                    parsed.hits.push(Hit {
+                        ticks,
+                        channel: status & 0x0F,
+                        key: data[0],
+                        velocity: data[1],
+                    });
+                }
+            }
+            _ => {
+                return Err(SMFError::InvalidFile(format!(
+                    "Unexpected status byte {:#04x} at tick {}",
+                    status, ticks
+                )))
+            }
+        }
+    }
+    parsed.end_ticks = parsed.end_ticks.max(ticks);
+    Ok(())
+}
+
+/// Most common velocity of the hits, preferring the louder one on ties.
+fn most_common_velocity(hits: &[&Hit]) -> Velocity {
+    let mut counts = BTreeMap::new();
+    for hit in hits {
+        *counts.entry(hit.velocity).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(velocity, count)| (count, velocity))
+        .map(|(velocity, _)| velocity)
+        .unwrap_or_default()
+}
+
+/// Builds a project from the note-ons of a type-0 or type-1 Standard MIDI File.
+///
+/// Every distinct (channel, key) pair becomes a track whose steps are
+/// `options.grid` beats apart. Hits at the track's most common velocity
+/// become `DefaultBeat`s, all others `OverrideBeat`s. If two hits fall on
+/// the same step, the louder one is kept. Tracks are padded to whole bars.
+/// The tempo and time signature changes of the file go into the project
+/// settings, each tempo change as a step.
+/// The grid must be positive, and files needing more than `MAX_STEPS` steps
+/// per track are rejected.
+pub fn import_project(data: &[u8], options: &ImportOptions) -> SSResult<Project> {
+    if options.grid.is_nan() || options.grid.is_infinite() || options.grid <= F::from(0) {
+        return Err(SMFError::InvalidGrid(options.grid.to_string()).into());
+    }
+    let parsed = parse_file(data)?;
+    let step_ticks = F::from(parsed.division) * options.grid;
+
+    let mut groups: BTreeMap<(Channel, Key), Vec<&Hit>> = BTreeMap::new();
+    for hit in parsed.hits.iter() {
+        groups.entry((hit.channel, hit.key)).or_default().push(hit);
+    }
+    let channels: BTreeSet<Channel> = groups.keys().map(|(channel, _)| *channel).collect();
+    let multiple_channels = channels.len() > 1;
+
+    let meter = meter(&parsed);
+    let file_length = BeatTime::new(F::from(parsed.end_ticks) / F::from(parsed.division));
+    let end = meter.position(file_length);
+    let bars = match end {
+        Position {
+            beat: 0, tick: 0, ..
+        } => end.bar.max(1),
+        _ => end.bar + 1,
+    };
+    let length = meter.bar_start(bars).as_fraction();
+    let steps = usize::try_from((length / options.grid).ceil())
+        .ok()
+        .filter(|steps| *steps <= MAX_STEPS)
+        .ok_or(SMFError::TooManySteps(MAX_STEPS))?;
+
+    let project = Project::new();
+    for ((channel, key), hits) in groups {
+        let note = Note::from(key);
+        let name = if multiple_channels {
+            format!("{} ch{}", note, channel)
+        } else {
+            note.to_string()
+        };
+        let default_beat = Beat {
+            channel,
+            note,
+            velocity: most_common_velocity(&hits),
+            gate: None,
+        };
+
+        let mut quantized: BTreeMap<usize, Velocity> = BTreeMap::new();
+        for hit in hits {
+            let pos = F::from(hit.ticks) / step_ticks;
+            let step = pos.round();
+            let error = if pos > step { pos - step } else { step - pos };
+            if error > options.tolerance {
+                return Err(SMFError::UnquantizableNote {
+                    channel,
+                    key,
+                    ticks: hit.ticks,
+                }
+                .into());
+            }
+            let step = usize::try_from(step)
+                .map_err(|_| SMFError::InvalidGrid(options.grid.to_string()))?;
+            let velocity = quantized.entry(step).or_default();
+            *velocity = (*velocity).max(hit.velocity);
+        }
+
+        let mut track = DrumTrack::with_default_beat(&name, default_beat);
+        track.set_tempo_scale(F::from(1) / options.grid);
+        for (&step, &velocity) in quantized.iter() {
+            let beat = if velocity == default_beat.velocity {
+                DrumTrackBeat::DefaultBeat
+            } else {
+                DrumTrackBeat::OverrideBeat(vec![Beat {
+                    velocity,
+                    ..default_beat
+                }])
+            };
+            track.assign_beat(step, beat);
+        }
+        track.resize(steps.max(track.len()));
+        project.add_track(track);
+    }
+
+    {
+        let project_settings = project.project_settings();
+        let mut project_settings = project_settings.write().unwrap();
+        if let Some(tempo_map) = tempo_map(&parsed) {
+            project_settings.tempo_map = tempo_map;
+        }
+        project_settings.meter = meter;
+    }
+    Ok(project)
+}
+
+/// Tempo map of the tempo changes of a file, if it has any. The first one
+/// holds from the start.
+fn tempo_map(parsed: &ParsedFile) -> Option<TempoMap> {
+    let mut tempos = parsed.tempos.clone();
+    // Stable, so that the last of several tempos at a tick wins
+    tempos.sort_by_key(|(ticks, _)| *ticks);
+    let (_, first) = tempos.first()?;
+    let mut tempo_map = TempoMap::new(micros_per_beat_to_tempo(*first));
+    for (ticks, micros) in tempos {
+        let beat = F::from(ticks) / F::from(parsed.division.max(1));
+        let tempo = micros_per_beat_to_tempo(micros);
+        // Files often repeat the tempo, e.g. on every bar
+        if tempo_map.tempo_at(BeatTime::new(beat)) != tempo {
+            tempo_map.insert(TempoPoint::new(beat, tempo, Ramp::Step));
+        }
+    }
+    Some(tempo_map)
+}
+
+/// Meter of the time signatures of a file. Time signatures in the middle
+/// of a bar take effect from the next bar on.
+fn meter(parsed: &ParsedFile) -> Meter {
+    let mut time_signatures = parsed.time_signatures.clone();
+    // Stable, so that the last of several time signatures at a tick wins
+    time_signatures.sort_by_key(|(ticks, _)| *ticks);
+    let mut meter = Meter::default();
+    for (ticks, time_signature) in time_signatures {
+        let position = meter.position(BeatTime::new(
+            F::from(ticks) / F::from(parsed.division.max(1)),
+        ));
+        let bar = match position {
+            Position {
+                beat: 0, tick: 0, ..
+            } => position.bar,
+            _ => position.bar + 1,
+        };
+        meter.insert(bar, time_signature);
+    }
+    meter
+}
+
+pub fn read_project(path: impl AsRef<Path>, options: &ImportOptions) -> SSResult<Project> {
+    let data = fs::read(path)?;
+    import_project(&data, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        beatmaker::beat_time::BeatTime,
+        drum_track::{Beat, DrumTrack, DrumTrackBeat},
+        error::{SMFError, SSError},
+        meter::Meter,
+        midi::note::Note,
+        project::{Project, TimeSignature, F},
+        smf::{export::export_project, write_chunk},
+        tempo::{Ramp, TempoMap, TempoPoint},
+    };
+
+    use super::{import_project, ImportOptions};
+
+    fn smf(format: u16, division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut buf = vec![];
+        let mut header = vec![];
+        header.extend_from_slice(&format.to_be_bytes());
+        header.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        header.extend_from_slice(&division.to_be_bytes());
+        write_chunk(&mut buf, b"MThd", &header);
+        for track in tracks {
+            write_chunk(&mut buf, b"MTrk", track);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_import_round_trip() {
+        let project = Project::new();
+        let mut kick = DrumTrack::with_beats_for_test(F::from(4), Note::C(1), 0);
+        let mut snare = DrumTrack::with_beats_for_test(F::from(4), Note::D(1), 0);
+        for step in [0, 4, 8] {
+            kick.assign_beat(step, DrumTrackBeat::DefaultBeat);
+        }
+        snare.assign_beat(2, DrumTrackBeat::DefaultBeat);
+        snare.assign_beat(
+            6,
+            DrumTrackBeat::OverrideBeat(vec![Beat {
+                velocity: 100,
+                ..snare.get_default_beat()
+            }]),
+        );
+        snare.assign_beat(10, DrumTrackBeat::DefaultBeat);
+        kick.resize(12);
+        snare.resize(12);
+        project.add_track(kick.clone());
+        project.add_track(snare.clone());
+        {
+            let project_settings = project.project_settings();
+            let mut project_settings = project_settings.write().unwrap();
+            project_settings.tempo_map = TempoMap::new(F::from(120));
+            project_settings.meter = Meter::new(TimeSignature {
+                numerator: 3,
+                denominator: 4,
+            });
+        }
+
+        let data = export_project(&project, BeatTime::new(F::from(3))).unwrap();
+        let imported = import_project(&data, &ImportOptions::default()).unwrap();
+        let tracks = imported.tracks();
+        let tracks = tracks.read().unwrap();
+        let tracks: Vec<&DrumTrack> = tracks.values().collect();
+        assert_eq!(tracks.len(), 2);
+        for (imported, original) in tracks.into_iter().zip([kick, snare]) {
+            assert_eq!(
+                imported.name(),
+                original.get_default_beat().note.to_string()
+            );
+            assert_eq!(imported.get_default_beat(), original.get_default_beat());
+            assert_eq!(imported.get_tempo_scale(), F::from(4));
+            assert!(imported.iter().eq(original.iter()));
+        }
+        let project_settings = imported.project_settings();
+        let project_settings = project_settings.read().unwrap();
+        assert_eq!(project_settings.tempo_map.initial_tempo(), F::from(120));
+        assert_eq!(project_settings.meter.initial().numerator, 3);
+        assert_eq!(project_settings.meter.initial().denominator, 4);
+    }
+
+    #[test]
+    fn test_import_tempo_changes() {
+        let project = Project::new();
+        let mut kick = DrumTrack::with_beats_for_test(F::from(1), Note::C(1), 0);
+        kick.assign_beat(0, DrumTrackBeat::DefaultBeat);
+        kick.resize(4);
+        project.add_track(kick);
+        let mut tempo_map = TempoMap::new(F::from(120));
+        tempo_map.insert(TempoPoint::new(F::from(2), F::from(150), Ramp::Step));
+        tempo_map.insert(TempoPoint::new(F::new(9u64, 2u64), F::from(90), Ramp::Step));
+        project.project_settings().write().unwrap().tempo_map = tempo_map.clone();
+
+        let data = export_project(&project, BeatTime::new(F::from(8))).unwrap();
+        let imported = import_project(&data, &ImportOptions::default()).unwrap();
+        assert_eq!(
+            imported.project_settings().read().unwrap().tempo_map,
+            tempo_map
+        );
+    }
+
+    #[test]
+    fn test_import_running_status() {
+        #[rustfmt::skip]
+        let track: &[u8] = &[
+            // 100 BPM, 6/8
+            0x00, 0xFF, 0x51, 0x03, 0x09, 0x27, 0xC0,
+            0x00, 0xFF, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08,
+            // Kick and snare, the snare using running status
+            0x00, 0x99, 36, 100,
+            0x00, 38, 80,
+            // Note-off as note-on with velocity 0
+            0x18, 36, 0,
+            0x18, 36, 100,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = smf(0, 96, &[track]);
+        let project = import_project(&data, &ImportOptions::default()).unwrap();
+        let tracks = project.tracks();
+        let tracks = tracks.read().unwrap();
+        let tracks: Vec<&DrumTrack> = tracks.values().collect();
+        assert_eq!(tracks.len(), 2);
+        // A 6/8 bar is 3 beats, i.e. 12 sixteenth-note steps
+        assert_eq!(tracks[0].len(), 12);
+        assert_eq!(tracks[0].get(0), Some(&DrumTrackBeat::DefaultBeat));
+        assert_eq!(tracks[0].get(1), Some(&DrumTrackBeat::Unset));
+        assert_eq!(tracks[0].get(2), Some(&DrumTrackBeat::DefaultBeat));
+        assert_eq!(tracks[0].get_default_beat().channel, 9);
+        assert_eq!(tracks[1].get_default_beat().note, Note::D(1));
+        assert_eq!(tracks[1].get_default_beat().velocity, 80);
+        let project_settings = project.project_settings();
+        let project_settings = project_settings.read().unwrap();
+        assert_eq!(project_settings.tempo_map.initial_tempo(), F::from(100));
+        assert_eq!(
+            project_settings.meter.initial(),
+            TimeSignature {
+                numerator: 6,
+                denominator: 8
+            }
+        );
+    }
+
+    #[test]
+    fn test_import_time_signature_changes() {
+        #[rustfmt::skip]
+        let track: &[u8] = &[
+            // A bar of 4/4, then 3/4 from the second bar
+            0x00, 0xFF, 0x58, 0x04, 0x04, 0x02, 0x18, 0x08,
+            0x83, 0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08,
+            // 7/8 half way into the third bar, taking effect from the fourth
+            0x83, 0x30, 0xFF, 0x58, 0x04, 0x07, 0x03, 0x18, 0x08,
+            0x00, 0x99, 36, 100,
+            0x18, 36, 0,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = smf(0, 96, &[track]);
+        let project = import_project(&data, &ImportOptions::default()).unwrap();
+        let project_settings = project.project_settings();
+        let project_settings = project_settings.read().unwrap();
+        let changes: Vec<_> = project_settings
+            .meter
+            .changes()
+            .iter()
+            .map(|change| (change.bar, change.time_signature.to_string()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (0, "4/4".to_string()),
+                (1, "3/4".to_string()),
+                (3, "7/8".to_string())
+            ]
+        );
+        // Up to the end of the third bar, 4 + 3 + 3 beats
+        let tracks = project.tracks();
+        let tracks = tracks.read().unwrap();
+        assert_eq!(tracks.values().next().unwrap().len(), 40);
+    }
+
+    #[test]
+    fn test_import_unquantizable_note() {
+        #[rustfmt::skip]
+        let track: &[u8] = &[
+            0x00, 0x99, 36, 100,
+            // 10 ticks are 5/12 of a sixteenth note at 96 ticks per beat
+            0x0A, 0x99, 38, 100,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = smf(0, 96, &[track]);
+        let result = import_project(&data, &ImportOptions::default());
+        assert!(matches!(
+            result,
+            Err(SSError::SMFError(SMFError::UnquantizableNote {
+                channel: 9,
+                key: 38,
+                ticks: 10
+            }))
+        ));
+        // On a 32nd-note grid it is only 1/6 of a step off
+        let options = ImportOptions {
+            grid: F::new(1u64, 8u64),
+            ..Default::default()
+        };
+        assert!(import_project(&data, &options).is_ok());
+    }
+
+    #[test]
+    fn test_import_invalid_time_signature() {
+        #[rustfmt::skip]
+        let track: &[u8] = &[
+            // 0/4 from the start
+            0x00, 0xFF, 0x58, 0x04, 0x00, 0x02, 0x18, 0x08,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = smf(0, 96, &[track]);
+        assert!(matches!(
+            import_project(&data, &ImportOptions::default()),
+            Err(SSError::SMFError(SMFError::InvalidFile(_)))
+        ));
+    }
+
+    #[test]
+    fn test_import_invalid_grid() {
+        let data = smf(0, 96, &[&[0x00, 0x99, 36, 100, 0x00, 0xFF, 0x2F, 0x00]]);
+        for grid in [F::from(0), F::from(-1), F::nan(), F::infinity()] {
+            let options = ImportOptions {
+                grid,
+                ..Default::default()
+            };
+            assert!(matches!(
+                import_project(&data, &options),
+                Err(SSError::SMFError(SMFError::InvalidGrid(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_import_too_long() {
+        #[rustfmt::skip]
+        let track: &[u8] = &[
+            0x00, 0x99, 36, 100,
+            // Ends 2^27 ticks later, over a million beats at 96 ticks per beat
+            0xC0, 0x80, 0x80, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let data = smf(0, 96, &[track]);
+        assert!(matches!(
+            import_project(&data, &ImportOptions::default()),
+            Err(SSError::SMFError(SMFError::TooManySteps(_)))
+        ));
+    }
+
+    #[test]
+    fn test_import_unsupported_format() {
+        let data = smf(2, 96, &[&[0x00, 0xFF, 0x2F, 0x00]]);
+        assert!(matches!(
+            import_project(&data, &ImportOptions::default()),
+            Err(SSError::SMFError(SMFError::UnsupportedFormat(2)))
+        ));
+        let data = smf(1, 0xE728, &[&[0x00, 0xFF, 0x2F, 0x00]]);
+        assert!(matches!(
+            import_project(&data, &ImportOptions::default()),
+            Err(SSError::SMFError(SMFError::UnsupportedDivision(0xE728)))
+        ));
+    }
+}
@@ -0,0 +1,118 @@
+//! Standard MIDI File (SMF) support.
+//!
+//! One beat of the project maps to one quarter note in the file, so a track
+//! at tempo scale 1 places a step on every quarter note.
+
+pub mod export;
+pub mod import;
+
+use fraction::ToPrimitive;
+
+use crate::{
+    project::{Tempo, TimeSignature, F},
+    tempo::tempo_from_f64,
+};
+
+/// Resolution of exported files, in ticks per quarter note.
+/// Divisible by 2, 3, 4, 5, 6 and 8 so that common tempo scales land on
+/// exact ticks.
+pub const TICKS_PER_BEAT: u16 = 960;
+
+const HEADER_CHUNK_ID: &[u8; 4] = b"MThd";
+const TRACK_CHUNK_ID: &[u8; 4] = b"MTrk";
+
+const META_EVENT: u8 = 0xFF;
+const META_TRACK_NAME: u8 = 0x03;
+const META_END_OF_TRACK: u8 = 0x2F;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+
+/// MIDI clocks per metronome click and 32nd notes per quarter note,
+/// written along with every time signature.
+const METRONOME_CLOCKS: u8 = 24;
+const THIRTY_SECONDS_PER_BEAT: u8 = 8;
+
+const MICROSECONDS_PER_MINUTE: u32 = 60_000_000;
+
+/// Tempo meta events hold 24 bits
+const MAX_MICROS_PER_BEAT: u32 = 0xFFFFFF;
+
+fn tempo_to_micros_per_beat(tempo: Tempo) -> u32 {
+    let micros = F::from(MICROSECONDS_PER_MINUTE) / tempo.max(F::from(1));
+    micros
+        .floor()
+        .to_u32()
+        .unwrap_or(MAX_MICROS_PER_BEAT)
+        .min(MAX_MICROS_PER_BEAT)
+}
+
+/// Tempo to a hundredth of a BPM
+fn micros_per_beat_to_tempo(micros_per_beat: u32) -> Tempo {
+    tempo_from_f64(MICROSECONDS_PER_MINUTE as f64 / micros_per_beat.max(1) as f64)
+}
+
+/// Writes `value` as a variable-length quantity: 7 bits per byte,
+/// most significant group first, with the high bit set on all but the last byte.
+fn write_vlq(buf: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        groups.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.extend(groups.into_iter().rev());
+}
+
+/// Time signature meta event payload. The denominator is stored as a power of two.
+fn time_signature_to_meta(time_signature: TimeSignature) -> [u8; 4] {
+    [
+        time_signature.numerator,
+        time_signature.denominator.max(1).ilog2() as u8,
+        METRONOME_CLOCKS,
+        THIRTY_SECONDS_PER_BEAT,
+    ]
+}
+
+fn write_chunk(buf: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    buf.extend_from_slice(id);
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::project::F;
+
+    use super::{micros_per_beat_to_tempo, tempo_to_micros_per_beat, write_vlq};
+
+    #[test]
+    fn test_write_vlq() {
+        for (value, expected) in [
+            (0, vec![0x00]),
+            (0x40, vec![0x40]),
+            (0x7F, vec![0x7F]),
+            (0x80, vec![0x81, 0x00]),
+            (0x2000, vec![0xC0, 0x00]),
+            (0x3FFF, vec![0xFF, 0x7F]),
+            (0x4000, vec![0x81, 0x80, 0x00]),
+            (0x0FFFFFFF, vec![0xFF, 0xFF, 0xFF, 0x7F]),
+        ] {
+            let mut buf = vec![];
+            write_vlq(&mut buf, value);
+            assert_eq!(buf, expected, "{:#x}", value);
+        }
+    }
+
+    #[test]
+    fn test_tempo_conversion() {
+        assert_eq!(tempo_to_micros_per_beat(F::from(120)), 500_000);
+        assert_eq!(micros_per_beat_to_tempo(500_000), F::from(120));
+        // 272727 microseconds is 220.0002 BPM
+        assert_eq!(tempo_to_micros_per_beat(F::from(220)), 272_727);
+        assert_eq!(micros_per_beat_to_tempo(272_727), F::from(220));
+        assert_eq!(tempo_to_micros_per_beat(F::new(241u64, 2u64)), 497_925);
+        assert_eq!(micros_per_beat_to_tempo(497_925), F::new(241u64, 2u64));
+        assert_eq!(micros_per_beat_to_tempo(0xFFFFFF), F::new(179u64, 50u64));
+        assert_eq!(tempo_to_micros_per_beat(F::from(1)), 0xFFFFFF);
+    }
+}
@@ -0,0 +1,422 @@
+//! Tempo automation.
+//!
+//! A tempo map is a list of points in beat time, each setting the tempo from
+//! that beat on. A point can also ramp towards the tempo of the next point,
+//! linearly or exponentially over beat time. BeatMaker walks the map by the
+//! time elapsed between ticks, so that the beat position carries on smoothly
+//! across tempo changes instead of jumping.
+//!
+//! Beat positions stay exact as long as the tempo is constant. Within ramps,
+//! they are rounded to a millionth of a beat.
+
+use std::time::Duration;
+
+use fraction::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    beatmaker::beat_time::BeatTime,
+    consts,
+    project::{Tempo, F},
+};
+
+/// How the tempo moves from a point to the next one
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum Ramp {
+    /// Holds the tempo until the next point
+    #[default]
+    Step,
+    /// Changes the tempo by the same amount every beat
+    Linear,
+    /// Changes the tempo by the same ratio every beat
+    Exponential,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct TempoPoint {
+    /// Beat time at which the tempo is reached
+    pub beat: F,
+    pub tempo: Tempo,
+    #[serde(default)]
+    pub ramp: Ramp,
+}
+
+impl TempoPoint {
+    pub fn new(beat: F, tempo: Tempo, ramp: Ramp) -> Self {
+        Self { beat, tempo, ramp }
+    }
+
+    /// Whether the point lies at or after beat 0
+    pub fn is_valid(&self) -> bool {
+        !self.beat.is_nan() && self.beat.is_finite() && self.beat >= F::from(0)
+    }
+}
+
+/// Points ordered by beat time, the first one at beat 0.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "Vec<TempoPoint>", into = "Vec<TempoPoint>")]
+pub struct TempoMap {
+    points: Vec<TempoPoint>,
+}
+
+/// Rounds a tempo in BPM to a hundredth, e.g. from a MIDI file or the GUI.
+pub fn tempo_from_f64(bpm: f64) -> Tempo {
+    F::new((bpm.max(0.0) * 100.0).round() as u64, 100u64)
+}
+
+pub fn tempo_to_f64(tempo: Tempo) -> f64 {
+    to_f64(tempo)
+}
+
+fn to_f64(value: F) -> f64 {
+    value.to_f64().unwrap_or_default()
+}
+
+/// Rounds beats or seconds within a ramp to a millionth.
+fn round_to_micro(value: f64) -> F {
+    let micro = consts::BEAT_TIME_MICRO as f64;
+    F::new(
+        (value.max(0.0) * micro).round() as u64,
+        consts::BEAT_TIME_MICRO as u64,
+    )
+}
+
+/// Tempo within a segment of the map as a function of the beats since
+/// its start.
+#[derive(Copy, Clone, Debug)]
+enum Curve {
+    Constant(Tempo),
+    /// Tempo at the start and change in BPM per beat
+    Linear(f64, f64),
+    /// Tempo at the start and rate of change, i.e. the tempo is
+    /// multiplied by e every 1/rate beats
+    Exponential(f64, f64),
+}
+
+impl Curve {
+    fn new(from: &TempoPoint, to: Option<&TempoPoint>) -> Self {
+        let Some(to) = to else {
+            return Curve::Constant(from.tempo);
+        };
+        let beats = to_f64(to.beat - from.beat);
+        let (start, end) = (to_f64(from.tempo), to_f64(to.tempo));
+        match from.ramp {
+            _ if from.tempo == to.tempo || beats <= 0.0 => Curve::Constant(from.tempo),
+            Ramp::Step => Curve::Constant(from.tempo),
+            Ramp::Linear => Curve::Linear(start, (end - start) / beats),
+            Ramp::Exponential if start > 0.0 && end > 0.0 => {
+                Curve::Exponential(start, (end / start).ln() / beats)
+            }
+            Ramp::Exponential => Curve::Constant(from.tempo),
+        }
+    }
+
+    fn tempo(&self, beats: F) -> Tempo {
+        let beats = to_f64(beats);
+        match *self {
+            Curve::Constant(tempo) => tempo,
+            Curve::Linear(start, slope) => tempo_from_f64(start + slope * beats),
+            Curve::Exponential(start, rate) => tempo_from_f64(start * (rate * beats).exp()),
+        }
+    }
+
+    /// Seconds taken from `from` to `to` beats since the start.
+    fn seconds(&self, from: F, to: F) -> F {
+        let seconds = match *self {
+            Curve::Constant(tempo) => return (to - from) * F::from(60) / tempo.max(F::from(1)),
+            Curve::Linear(start, slope) => {
+                let (from, to) = (to_f64(from), to_f64(to));
+                60.0 / slope * ((start + slope * to) / (start + slope * from)).ln()
+            }
+            Curve::Exponential(start, rate) => {
+                let (from, to) = (to_f64(from), to_f64(to));
+                60.0 / (start * rate) * ((-rate * from).exp() - (-rate * to).exp())
+            }
+        };
+        round_to_micro(seconds)
+    }
+
+    /// Beats since the start reached `seconds` after `from` beats since
+    /// the start, or `None` if never reached.
+    fn advance(&self, from: F, seconds: F) -> Option<F> {
+        let beats = match *self {
+            Curve::Constant(tempo) => {
+                return Some(from + seconds * tempo.max(F::from(1)) / F::from(60));
+            }
+            Curve::Linear(start, slope) => {
+                let tempo = start + slope * to_f64(from);
+                (tempo * (slope * to_f64(seconds) / 60.0).exp() - tempo) / slope
+            }
+            Curve::Exponential(start, rate) => {
+                let remaining =
+                    (-rate * to_f64(from)).exp() - to_f64(seconds) * start * rate / 60.0;
+                if remaining <= 0.0 {
+                    return None;
+                }
+                -remaining.ln() / rate - to_f64(from)
+            }
+        };
+        (beats.is_finite() && beats >= 0.0).then(|| from + round_to_micro(beats))
+    }
+}
+
+impl TempoMap {
+    pub fn new(tempo: Tempo) -> Self {
+        Self {
+            points: vec![TempoPoint::new(F::from(0), tempo, Ramp::Step)],
+        }
+    }
+
+    pub fn points(&self) -> &[TempoPoint] {
+        &self.points
+    }
+
+    /// Tempo at the start, before any automation
+    pub fn initial_tempo(&self) -> Tempo {
+        self.points[0].tempo
+    }
+
+    pub fn set_initial_tempo(&mut self, tempo: Tempo) {
+        self.points[0].tempo = tempo;
+    }
+
+    /// Adds a point, replacing any point at the same beat time. Returns
+    /// false, leaving the map as it is, if the point is not valid.
+    pub fn insert(&mut self, point: TempoPoint) -> bool {
+        if !point.is_valid() {
+            return false;
+        }
+        match self.points.binary_search_by(|p| p.beat.cmp(&point.beat)) {
+            Ok(i) => self.points[i] = point,
+            Err(i) => self.points.insert(i, point),
+        }
+        true
+    }
+
+    /// Removes the point at `beat`. The point at beat 0 cannot be removed.
+    pub fn remove(&mut self, beat: F) -> Option<TempoPoint> {
+        match self.points.binary_search_by(|p| p.beat.cmp(&beat)) {
+            Ok(i) if i > 0 => Some(self.points.remove(i)),
+            _ => None,
+        }
+    }
+
+    /// Index of the point whose segment contains `beat_time`
+    fn segment(&self, beat_time: F) -> usize {
+        self.points
+            .partition_point(|p| p.beat <= beat_time)
+            .saturating_sub(1)
+    }
+
+    fn curve(&self, segment: usize) -> Curve {
+        Curve::new(&self.points[segment], self.points.get(segment + 1))
+    }
+
+    pub fn tempo_at(&self, beat_time: BeatTime) -> Tempo {
+        let beat_time = beat_time.as_fraction();
+        let segment = self.segment(beat_time);
+        self.curve(segment)
+            .tempo(beat_time - self.points[segment].beat)
+    }
+
+    /// Beat time reached `duration` after `from`.
+    pub fn advance(&self, from: BeatTime, duration: Duration) -> BeatTime {
+        let mut beat_time = from.as_fraction();
+        let mut seconds = F::new(duration.as_micros() as u64, 1_000_000u64);
+        let mut segment = self.segment(beat_time);
+        loop {
+            let start = self.points[segment].beat;
+            let curve = self.curve(segment);
+            let Some(end) = self.points.get(segment + 1).map(|p| p.beat) else {
+                // The tempo stays the same after the last point
+                return BeatTime::new(start + curve.advance(beat_time - start, seconds).unwrap());
+            };
+            let until_end = curve.seconds(beat_time - start, end - start);
+            if until_end > seconds
+                && let Some(beats) = curve.advance(beat_time - start, seconds)
+            {
+                return BeatTime::new((start + beats).min(end));
+            }
+            seconds = (seconds - until_end).max(F::from(0));
+            beat_time = end;
+            segment += 1;
+        }
+    }
+
+    /// Time taken from `from` to `to`, or zero if `to` is not after `from`.
+    pub fn duration(&self, from: BeatTime, to: BeatTime) -> Duration {
+        let (mut beat_time, to) = (from.as_fraction(), to.as_fraction());
+        let mut seconds = F::from(0);
+        let mut segment = self.segment(beat_time);
+        while beat_time < to {
+            let start = self.points[segment].beat;
+            let end = self.points.get(segment + 1).map_or(to, |p| p.beat.min(to));
+            seconds += self.curve(segment).seconds(beat_time - start, end - start);
+            beat_time = end;
+            segment += 1;
+        }
+        Duration::from_micros(
+            (seconds * F::from(1_000_000))
+                .round()
+                .to_u64()
+                .unwrap_or_default(),
+        )
+    }
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        Self::new(F::from(220))
+    }
+}
+
+/// Sorts the points and makes sure one starts at beat 0, at the tempo of
+/// the earliest point. Fails on a point before beat 0.
+impl TryFrom<Vec<TempoPoint>> for TempoMap {
+    type Error = String;
+
+    fn try_from(mut points: Vec<TempoPoint>) -> Result<Self, Self::Error> {
+        if let Some(point) = points.iter().find(|p| !p.is_valid()) {
+            return Err(format!("Invalid tempo point at beat {}", point.beat));
+        }
+        points.sort_by_key(|p| p.beat);
+        let mut map = match points.first() {
+            Some(point) => Self::new(point.tempo),
+            None => Self::default(),
+        };
+        for point in points {
+            map.insert(point);
+        }
+        Ok(map)
+    }
+}
+
+impl From<TempoMap> for Vec<TempoPoint> {
+    fn from(map: TempoMap) -> Self {
+        map.points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{beatmaker::beat_time::BeatTime, project::F};
+
+    use super::{tempo_from_f64, Ramp, TempoMap, TempoPoint};
+
+    fn beat_time(numer: u64, denom: u64) -> BeatTime {
+        BeatTime::new(F::new(numer, denom))
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn assert_close(a: BeatTime, b: BeatTime) {
+        let diff = (a.as_fraction() - b.as_fraction()).abs();
+        assert!(diff < F::new(1u64, 10_000u64), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_constant_tempo() {
+        let map = TempoMap::new(F::new(241u64, 2u64));
+        assert_eq!(
+            map.advance(BeatTime::zero(), millis(10)),
+            beat_time(241, 12_000)
+        );
+        assert_eq!(
+            map.duration(BeatTime::zero(), beat_time(241, 2)),
+            Duration::from_secs(60)
+        );
+        assert_eq!(map.tempo_at(beat_time(100, 1)), F::new(241u64, 2u64));
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut map = TempoMap::new(F::from(120));
+        map.insert(TempoPoint::new(F::from(4), F::from(60), Ramp::Step));
+        assert_eq!(map.tempo_at(beat_time(7, 2)), F::from(120));
+        assert_eq!(map.tempo_at(beat_time(4, 1)), F::from(60));
+        // 3 beats at 120 BPM, then half a beat at 60 BPM
+        assert_eq!(map.advance(beat_time(1, 1), millis(2_000)), beat_time(9, 2));
+        assert_eq!(
+            map.duration(beat_time(1, 1), beat_time(9, 2)),
+            millis(2_000)
+        );
+        // The first point stays
+        assert!(map.remove(F::from(0)).is_none());
+        assert!(map.remove(F::from(4)).is_some());
+        assert_eq!(map.advance(beat_time(1, 1), millis(2_000)), beat_time(5, 1));
+    }
+
+    #[test]
+    fn test_linear_ramp() {
+        let mut map = TempoMap::new(F::from(60));
+        map.insert(TempoPoint::new(F::from(0), F::from(60), Ramp::Linear));
+        map.insert(TempoPoint::new(F::from(4), F::from(180), Ramp::Step));
+        assert_eq!(map.tempo_at(beat_time(2, 1)), F::from(120));
+        // 60/30 * ln(180/60) seconds to go through the ramp
+        let ramp = map.duration(BeatTime::zero(), beat_time(4, 1));
+        assert!((ramp.as_secs_f64() - 2.0 * 3f64.ln()).abs() < 1e-6);
+        assert_close(map.advance(BeatTime::zero(), ramp), beat_time(4, 1));
+        // Then at 180 BPM
+        let after = map.advance(BeatTime::zero(), ramp + millis(1_000));
+        assert_close(after, beat_time(7, 1));
+    }
+
+    #[test]
+    fn test_exponential_ramp() {
+        let mut map = TempoMap::new(F::from(100));
+        map.insert(TempoPoint::new(F::from(0), F::from(100), Ramp::Exponential));
+        map.insert(TempoPoint::new(F::from(2), F::from(400), Ramp::Step));
+        assert_eq!(map.tempo_at(beat_time(1, 1)), F::from(200));
+        let half = map.duration(BeatTime::zero(), beat_time(1, 1));
+        assert_close(map.advance(BeatTime::zero(), half), beat_time(1, 1));
+        // The tempo doubles every beat, so the first beat takes twice as long
+        let rest = map.duration(beat_time(1, 1), beat_time(2, 1));
+        assert!((half.as_secs_f64() - rest.as_secs_f64() * 2.0).abs() < 1e-5);
+    }
+
+    /// Walking the map tick by tick gets to the same position as in one go,
+    /// and never goes backwards
+    #[test]
+    fn test_advance_by_ticks() {
+        let mut map = TempoMap::new(F::from(90));
+        map.insert(TempoPoint::new(F::from(1), F::from(90), Ramp::Linear));
+        map.insert(TempoPoint::new(F::from(3), F::from(150), Ramp::Exponential));
+        map.insert(TempoPoint::new(F::from(5), F::from(75), Ramp::Step));
+        let mut now = BeatTime::zero();
+        for _ in 0..500 {
+            let next = map.advance(now, millis(10));
+            assert!(next > now);
+            now = next;
+        }
+        assert_close(now, map.advance(BeatTime::zero(), millis(5_000)));
+    }
+
+    #[test]
+    fn test_from_points() {
+        let map = TempoMap::try_from(vec![
+            TempoPoint::new(F::from(8), F::from(90), Ramp::Step),
+            TempoPoint::new(F::from(4), F::from(100), Ramp::Linear),
+        ])
+        .unwrap();
+        let beats: Vec<_> = map.points().iter().map(|p| p.beat).collect();
+        assert_eq!(beats, vec![F::from(0), F::from(4), F::from(8)]);
+        // The tempo of the earliest point holds from the start
+        assert_eq!(map.initial_tempo(), F::from(100));
+        assert_eq!(tempo_from_f64(120.456), F::new(12_046u64, 100u64));
+    }
+
+    #[test]
+    fn test_reject_points_before_start() {
+        let point = TempoPoint::new(-F::from(4), F::from(100), Ramp::Step);
+        let mut map = TempoMap::new(F::from(120));
+        assert!(!map.insert(point));
+        assert_eq!(map.points().len(), 1);
+        assert!(TempoMap::try_from(vec![point]).is_err());
+        let json = serde_json::to_string(&vec![point]).unwrap();
+        assert!(serde_json::from_str::<TempoMap>(&json).is_err());
+    }
+}
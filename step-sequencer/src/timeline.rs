@@ -1,14 +1,13 @@
 use std::{
     sync::{Arc, Condvar, Mutex, RwLock},
     thread,
-    time::{Duration, Instant},
 };
 
 use crossbeam::channel::bounded;
 use log::{info, warn};
 
 use crate::{
-    consts,
+    clock::{ClockSource, WallClock},
     models::channel_subscription::{ChannelEventSubscription, ChannelEventSubscriptionModel},
 };
 
@@ -29,11 +28,12 @@ pub enum TimelineEvent {
 pub type TimelineSubscriptionModel = ChannelEventSubscriptionModel<TimelineEvent>;
 pub type TimelineSubscription = ChannelEventSubscription<TimelineEvent>;
 
-/// Timeline is the heart of step-sequencer. It sends out ticks at precise interval.
+/// Timeline is the heart of step-sequencer. It sends out ticks at precise interval,
+/// as measured by its clock source.
 /// BeatMaker's beat time is driven by the ticks, which ensures beat notes are sent
 /// out at the proper time and in the correct order.
 pub struct Timeline {
-    interval: Duration,
+    clock: Arc<dyn ClockSource>,
     /// This is only updated upon pause/stop
     current_tick: Arc<RwLock<Tick>>,
     start_mutex: Arc<Mutex<bool>>,
@@ -43,8 +43,12 @@ pub struct Timeline {
 
 impl Timeline {
     pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_clock(clock: Arc<dyn ClockSource>) -> Self {
         Self {
-            interval: consts::TIMELINE_TICK_DURATION,
+            clock,
             ..Default::default()
         }
     }
@@ -53,6 +57,10 @@ impl Timeline {
         self.subscription_model.subscribe()
     }
 
+    pub fn clock(&self) -> Arc<dyn ClockSource> {
+        self.clock.clone()
+    }
+
     pub fn state(&self) -> TimelineState {
         if *self.start_mutex.lock().unwrap() {
             TimelineState::Started
@@ -66,33 +74,31 @@ impl Timeline {
         let start_mutex = self.start_mutex.clone();
         let state_condvar = self.state_condvar.clone();
         let last_current_tick = self.current_tick.clone();
-        let interval = self.interval;
+        let clock = self.clock.clone();
         let subscriber_map = self.subscription_model.subscriber_map().clone();
+        clock.start();
         let _ = thread::spawn(move || {
             let mut new_current_tick = *last_current_tick.read().unwrap();
-            let mut next_tick_time = Instant::now() + interval;
+            let mut tick_due = true;
             loop {
                 if !*start_mutex.lock().unwrap() {
                     *last_current_tick.write().unwrap() = new_current_tick;
                     state_condvar.notify_one();
                     return;
                 }
-                ChannelEventSubscriptionModel::send_all(
-                    &subscriber_map,
-                    TimelineEvent::Tick(new_current_tick),
-                );
-                new_current_tick += 1;
-                next_tick_time += interval;
-                let now = Instant::now();
-                if now > next_tick_time {
-                    warn!("Skipping tick(s) due to slow processing");
-                }
-                while Instant::now() > next_tick_time {
+                if tick_due {
+                    ChannelEventSubscriptionModel::send_all(
+                        &subscriber_map,
+                        TimelineEvent::Tick(new_current_tick),
+                    );
                     new_current_tick += 1;
-                    next_tick_time += interval;
                 }
-
-                thread::sleep(next_tick_time - Instant::now());
+                let elapsed = clock.wait();
+                if elapsed > 1 {
+                    warn!("Skipping tick(s) due to slow processing");
+                    new_current_tick += elapsed - 1;
+                }
+                tick_due = elapsed > 0;
             }
         });
     }
@@ -101,6 +107,7 @@ impl Timeline {
         let mut guard = self.start_mutex.lock().unwrap();
         while *guard {
             *guard = false;
+            self.clock.interrupt();
             guard = self.state_condvar.wait(guard).unwrap();
         }
         let subscriber_map = self.subscription_model.subscriber_map();
@@ -112,6 +119,7 @@ impl Timeline {
         let mut guard = self.start_mutex.lock().unwrap();
         while *guard {
             *guard = false;
+            self.clock.interrupt();
             guard = self.state_condvar.wait(guard).unwrap();
         }
 
@@ -126,7 +134,7 @@ impl Timeline {
 impl Default for Timeline {
     fn default() -> Self {
         Self {
-            interval: Duration::from_millis(10),
+            clock: Arc::new(WallClock::default()),
             current_tick: Default::default(),
             start_mutex: Default::default(),
             state_condvar: Default::default(),
@@ -1 +1,2 @@
 pub mod interval_executor;
+pub mod random;
@@ -0,0 +1,46 @@
+//! Stateless pseudo-random numbers.
+//!
+//! Every number is derived from its inputs alone, so that decisions made
+//! during playback do not depend on how often or in which order they are
+//! made, e.g. whether a range is rendered at once or tick by tick.
+
+/// SplitMix64 output function
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Hashes `values` into a uniformly distributed number.
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |acc, value| mix(acc ^ mix(*value)))
+}
+
+/// Uniformly distributed number in `0..bound`, derived from `values`.
+pub fn below(values: &[u64], bound: u64) -> u64 {
+    hash(values) % bound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{below, hash};
+
+    #[test]
+    fn test_hash_is_deterministic() {
+        assert_eq!(hash(&[1, 2, 3]), hash(&[1, 2, 3]));
+        assert_ne!(hash(&[1, 2, 3]), hash(&[1, 3, 2]));
+        assert_ne!(hash(&[0]), hash(&[0, 0]));
+    }
+
+    #[test]
+    fn test_below_is_roughly_uniform() {
+        let mut counts = [0; 10];
+        for i in 0..10_000 {
+            counts[below(&[42, i], 10) as usize] += 1;
+        }
+        for count in counts {
+            assert!((900..1100).contains(&count), "{:?}", counts);
+        }
+    }
+}
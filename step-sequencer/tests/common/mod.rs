@@ -0,0 +1,186 @@
+//! Runs `Timeline` and `BeatMaker` on a `ManualClock`, so that tests decide
+//! when ticks elapse instead of waiting for them.
+
+use std::{sync::Arc, time::Duration};
+
+use step_sequencer::{
+    beatmaker::{beat_time::BeatTime, BeatMaker, BeatMakerEvent, BeatMakerSubscription},
+    clock::ManualClock,
+    consts::TRACK_DEFAULT_BEAT,
+    drum_track::{Beat, DrumTrack, DrumTrackBeat},
+    midi::{note::Note, ChannelVoiceEvent, Key},
+    project::{Project, Tempo},
+    tempo::TempoMap,
+    timeline::Timeline,
+};
+
+/// Guards against waiting forever for an event that never comes
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Rig {
+    pub clock: Arc<ManualClock>,
+    pub timeline: Timeline,
+    pub beatmaker: BeatMaker,
+    pub project: Project,
+    subscription: BeatMakerSubscription,
+}
+
+impl Rig {
+    pub fn new(tracks: Vec<DrumTrack>, tempo: Tempo) -> Self {
+        let clock = Arc::new(ManualClock::new());
+        let timeline = Timeline::with_clock(clock.clone());
+        let beatmaker = BeatMaker::new();
+        let project = Project::new();
+        project.project_settings().write().unwrap().tempo_map = TempoMap::new(tempo);
+        for track in tracks {
+            project.add_track(track);
+        }
+        let subscription = beatmaker.subscribe();
+        beatmaker.start(&project, timeline.subscribe(), clock.clone());
+        Self {
+            clock,
+            timeline,
+            beatmaker,
+            project,
+            subscription,
+        }
+    }
+
+    /// Starts or resumes the timeline and returns the events of the tick
+    /// sent right away.
+    pub fn start(&self) -> Vec<BeatMakerEvent> {
+        self.timeline.start();
+        self.collect_ticks(1)
+    }
+
+    /// Lets `ticks` ticks elapse and returns their events.
+    pub fn step(&self, ticks: u64) -> Vec<BeatMakerEvent> {
+        self.clock.advance(ticks);
+        self.collect_ticks(ticks)
+    }
+
+    /// Steps until BeatMaker has played everything before `beat_time`.
+    pub fn step_to(&self, beat_time: BeatTime) -> Vec<BeatMakerEvent> {
+        let mut events = vec![];
+        while self.current_beat_time() < beat_time {
+            events.extend(self.step(1));
+        }
+        events
+    }
+
+    pub fn pause(&self) -> Vec<BeatMakerEvent> {
+        self.timeline.pause();
+        self.collect_until(|event| *event == BeatMakerEvent::Pause)
+    }
+
+    pub fn stop(&self) -> Vec<BeatMakerEvent> {
+        self.timeline.stop();
+        self.collect_until(|event| *event == BeatMakerEvent::Stop)
+    }
+
+    pub fn set_tempo(&self, tempo: Tempo) {
+        self.set_tempo_map(TempoMap::new(tempo));
+    }
+
+    pub fn set_tempo_map(&self, tempo_map: TempoMap) {
+        self.project.project_settings().write().unwrap().tempo_map = tempo_map;
+    }
+
+    pub fn current_beat_time(&self) -> BeatTime {
+        *self
+            .project
+            .project_settings()
+            .read()
+            .unwrap()
+            .current_beat_time
+            .read()
+            .unwrap()
+    }
+
+    fn collect_ticks(&self, ticks: u64) -> Vec<BeatMakerEvent> {
+        let mut events = vec![];
+        for _ in 0..ticks {
+            events.extend(self.collect_until(|event| matches!(event, BeatMakerEvent::Tick(_))));
+        }
+        events
+    }
+
+    /// Returns the events received up to and including the first one
+    /// matching `last`.
+    fn collect_until(&self, last: impl Fn(&BeatMakerEvent) -> bool) -> Vec<BeatMakerEvent> {
+        let mut events = vec![];
+        loop {
+            let event = self
+                .subscription
+                .receiver
+                .recv_timeout(RECV_TIMEOUT)
+                .expect("BeatMaker event");
+            let done = last(&event);
+            events.push(event);
+            if done {
+                return events;
+            }
+        }
+    }
+}
+
+impl Drop for Rig {
+    fn drop(&mut self) {
+        self.timeline.stop();
+    }
+}
+
+/// Track playing `keys` on consecutive beats
+pub fn track(keys: &[Key]) -> DrumTrack {
+    let beats: Vec<_> = keys
+        .iter()
+        .map(|key| {
+            DrumTrackBeat::OverrideBeat(vec![Beat {
+                note: Note::from(*key),
+                ..TRACK_DEFAULT_BEAT
+            }])
+        })
+        .collect();
+    DrumTrack::with_beats("track", TRACK_DEFAULT_BEAT, &beats)
+}
+
+/// NoteOns as (tick, key), where tick is the tick they were sent on
+pub fn note_ons(events: &[BeatMakerEvent]) -> Vec<(u64, Key)> {
+    notes(events)
+        .into_iter()
+        .filter_map(|(tick, on, key)| on.then_some((tick, key)))
+        .collect()
+}
+
+/// Keys of NoteOffs, wherever they were sent
+pub fn note_offs(events: &[BeatMakerEvent]) -> Vec<Key> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            BeatMakerEvent::MIDIEvent(ChannelVoiceEvent::NoteOff { key, .. }, _) => Some(*key),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Notes as (tick, is NoteOn, key). Notes sent outside of a tick, e.g.
+/// when stopping, are not included.
+fn notes(events: &[BeatMakerEvent]) -> Vec<(u64, bool, Key)> {
+    let mut notes = vec![];
+    let mut pending = vec![];
+    for event in events {
+        match event {
+            BeatMakerEvent::MIDIEvent(ChannelVoiceEvent::NoteOn { key, .. }, _) => {
+                pending.push((true, *key))
+            }
+            BeatMakerEvent::MIDIEvent(ChannelVoiceEvent::NoteOff { key, .. }, _) => {
+                pending.push((false, *key))
+            }
+            BeatMakerEvent::Tick(tick) => {
+                notes.extend(pending.drain(..).map(|(on, key)| (*tick, on, key)))
+            }
+            _ => {}
+        }
+    }
+    notes
+}
@@ -0,0 +1,287 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{note_offs, note_ons, track, Rig};
+use step_sequencer::{
+    beatmaker::{beat_time::BeatTime, BeatMaker, BeatMakerEvent},
+    clock::FrameClock,
+    consts::TRACK_DEFAULT_BEAT,
+    drum_track::{Beat, DrumTrackBeat, PatternSlot},
+    meter::{Meter, Position},
+    midi::{note::Note, ChannelVoiceEvent},
+    project::{Project, TimeSignature, F},
+    tempo::{Ramp, TempoMap, TempoPoint},
+    timeline::{Timeline, TimelineState},
+};
+
+/// A beat every 40 ticks
+const TEMPO: u64 = 150;
+
+fn tempo() -> F {
+    F::from(TEMPO)
+}
+
+fn beat_time(beats: u64) -> BeatTime {
+    BeatTime::new(F::from(beats))
+}
+
+#[test]
+fn test_play_on_beats() {
+    let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+    let mut events = rig.start();
+    assert_eq!(
+        events,
+        vec![
+            BeatMakerEvent::Position(Position::default()),
+            BeatMakerEvent::Tick(0)
+        ]
+    );
+    events.extend(rig.step_to(beat_time(4)));
+    // Each tick plays the beat time it has just passed
+    assert_eq!(
+        note_ons(&events),
+        vec![(1, 36), (41, 38), (81, 40), (121, 41)]
+    );
+    assert_eq!(note_offs(&events), vec![36, 38, 40, 41]);
+}
+
+#[test]
+fn test_pause_and_resume() {
+    let expected = {
+        let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+        let mut events = rig.start();
+        events.extend(rig.step(200));
+        events
+    };
+
+    let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+    let mut events = rig.start();
+    // Pause while the note of the second beat is sounding
+    events.extend(rig.step(50));
+    let paused = rig.pause();
+    assert_eq!(paused, vec![BeatMakerEvent::Pause]);
+    // Nothing happens while paused
+    rig.clock.advance(100);
+    assert!(matches!(rig.timeline.state(), TimelineState::Stopped));
+    // Resuming carries on from the tick after the last one
+    let resumed = rig.start();
+    assert_eq!(resumed.last(), Some(&BeatMakerEvent::Tick(51)));
+    events.extend(resumed);
+    events.extend(rig.step(149));
+
+    assert_eq!(note_ons(&events), note_ons(&expected));
+    assert_eq!(note_offs(&events), note_offs(&expected));
+}
+
+#[test]
+fn test_stop_and_restart() {
+    let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+    rig.start();
+    let events = rig.step(45);
+    assert_eq!(note_ons(&events), vec![(1, 36), (41, 38)]);
+    // The sounding note is released right away
+    let stopped = rig.stop();
+    assert_eq!(note_offs(&stopped), vec![38]);
+    assert_eq!(stopped.last(), Some(&BeatMakerEvent::Stop));
+    assert_eq!(rig.current_beat_time(), BeatTime::zero());
+    // Restarting plays from the start
+    let mut events = rig.start();
+    assert_eq!(
+        events,
+        vec![
+            BeatMakerEvent::Position(Position::default()),
+            BeatMakerEvent::Tick(0)
+        ]
+    );
+    events.extend(rig.step_to(beat_time(2)));
+    assert_eq!(note_ons(&events), vec![(1, 36), (41, 38)]);
+}
+
+#[test]
+fn test_tempo_change() {
+    let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+    let mut events = rig.start();
+    events.extend(rig.step_to(beat_time(2)));
+    assert_eq!(note_ons(&events), vec![(1, 36), (41, 38)]);
+    assert_eq!(rig.current_beat_time(), beat_time(2));
+
+    // A beat every 20 ticks from here on, without jumping ahead
+    rig.set_tempo(tempo() * F::from(2));
+    rig.step(1);
+    assert_eq!(
+        rig.current_beat_time(),
+        BeatTime::new(F::from(2) + F::new(1u64, 20u64))
+    );
+    let events = rig.step_to(beat_time(6));
+    assert_eq!(
+        note_ons(&events),
+        vec![(101, 41), (121, 36), (141, 38)]
+    );
+}
+
+#[test]
+fn test_tempo_ramp() {
+    let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+    // Speeds up to twice the tempo over 4 beats
+    let mut tempo_map = TempoMap::new(tempo());
+    tempo_map.insert(TempoPoint::new(F::from(0), tempo(), Ramp::Linear));
+    tempo_map.insert(TempoPoint::new(F::from(4), tempo() * F::from(2), Ramp::Step));
+    rig.set_tempo_map(tempo_map);
+    let mut events = rig.start();
+    events.extend(rig.step_to(beat_time(8)));
+    let ticks: Vec<_> = note_ons(&events).iter().map(|(tick, _)| *tick).collect();
+    // Beat times within ramps are rounded, so the last tick may be a bit
+    // past the 8th beat
+    let ticks = &ticks[..8];
+    let intervals: Vec<_> = ticks.windows(2).map(|ticks| ticks[1] - ticks[0]).collect();
+    // Every beat of the ramp is shorter than the one before
+    assert!(
+        intervals[..4].windows(2).all(|i| i[1] < i[0]),
+        "{:?}",
+        intervals
+    );
+    assert!(intervals[0] < 40);
+    assert_eq!(intervals[4..], [20, 20, 20]);
+}
+
+#[test]
+fn test_reload_beat_sorter() {
+    let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+    rig.start();
+    rig.step_to(beat_time(2));
+    rig.project
+        .tracks()
+        .write()
+        .unwrap()
+        .values_mut()
+        .next()
+        .unwrap()
+        .assign_beat(
+            3,
+            DrumTrackBeat::OverrideBeat(vec![Beat {
+                note: Note::from(50),
+                ..TRACK_DEFAULT_BEAT
+            }]),
+        );
+    rig.beatmaker.reload_beat_sorter();
+    // Carries on from the current beat with the change applied, without
+    // playing the earlier beats again
+    let events = rig.step_to(beat_time(4));
+    assert_eq!(note_ons(&events), vec![(81, 40), (121, 50)]);
+    let events = rig.step_to(beat_time(6));
+    assert_eq!(note_ons(&events), vec![(161, 36), (201, 38)]);
+}
+
+#[test]
+fn test_reload_after_pattern_switch() {
+    let mut track = track(&[36, 38, 40, 41]);
+    let a2 = PatternSlot::parse("A2").unwrap();
+    track.select_pattern(a2);
+    track.resize(3);
+    track.assign_beat(
+        0,
+        DrumTrackBeat::OverrideBeat(vec![Beat {
+            note: Note::from(50),
+            ..TRACK_DEFAULT_BEAT
+        }]),
+    );
+    track.select_pattern(PatternSlot::default());
+    let rig = Rig::new(vec![track], tempo());
+    rig.start();
+    let mut events = rig.step_to(beat_time(1));
+    rig.project
+        .tracks()
+        .write()
+        .unwrap()
+        .values_mut()
+        .next()
+        .unwrap()
+        .queue_pattern(a2);
+    // A2 takes over at beat 4 and loops every 3 beats
+    events.extend(rig.step_to(beat_time(6)));
+    rig.beatmaker.reload_beat_sorter();
+    events.extend(rig.step_to(beat_time(11)));
+    let a2_ticks: Vec<_> = note_ons(&events)
+        .into_iter()
+        .filter(|(_, key)| *key == 50)
+        .map(|(tick, _)| tick)
+        .collect();
+    assert_eq!(a2_ticks, vec![161, 281, 401]);
+}
+
+#[test]
+fn test_positions() {
+    let rig = Rig::new(vec![track(&[36, 38, 40, 41])], tempo());
+    // A bar of 3/4, then 6/8
+    let mut meter = Meter::new(TimeSignature::parse("3/4").unwrap());
+    meter.insert(1, TimeSignature::parse("6/8").unwrap());
+    rig.project.project_settings().write().unwrap().meter = meter;
+    let mut events = rig.start();
+    events.extend(rig.step_to(beat_time(5)));
+    let positions: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            BeatMakerEvent::Position(position) => Some(position.to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        positions,
+        ["1:1:0", "1:2:0", "1:3:0", "2:1:0", "2:2:0", "2:3:0", "2:4:0", "2:5:0"]
+    );
+    // Restarting starts over at the first bar
+    rig.stop();
+    let events = rig.start();
+    assert_eq!(
+        events,
+        vec![
+            BeatMakerEvent::Position(Position::default()),
+            BeatMakerEvent::Tick(0)
+        ]
+    );
+}
+
+#[test]
+fn test_timestamps_on_frame_clock() {
+    let clock = Arc::new(FrameClock::default());
+    let timeline = Timeline::with_clock(clock.clone());
+    let beatmaker = BeatMaker::new();
+    let project = Project::new();
+    project.project_settings().write().unwrap().tempo_map = TempoMap::new(tempo());
+    project.add_track(track(&[36, 38]));
+    let subscription = beatmaker.subscribe();
+    beatmaker.start(&project, timeline.subscribe(), clock.clone());
+    timeline.start();
+    let mut events = vec![];
+    let mut recv_until = |tick| loop {
+        let event = subscription
+            .receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("BeatMaker event");
+        let done = event == BeatMakerEvent::Tick(tick);
+        events.push(event);
+        if done {
+            break;
+        }
+    };
+    recv_until(0);
+    // Cycles of 10ms from frame 1000 on, processed far quicker than in
+    // real time
+    for cycle in 0..45 {
+        clock.advance(1000 + cycle * 480, 480, 48_000);
+    }
+    recv_until(45);
+    timeline.stop();
+    let frames: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            BeatMakerEvent::MIDIEvent(ChannelVoiceEvent::NoteOn { key, .. }, timestamp) => {
+                Some((*key, clock.frame_at(*timestamp)))
+            }
+            _ => None,
+        })
+        .collect();
+    // A tick late, at 10ms and 410ms of the frames processed
+    assert_eq!(frames, vec![(36, 1480), (38, 20680)]);
+}